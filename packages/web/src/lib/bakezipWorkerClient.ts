@@ -2,9 +2,12 @@ import type {
   CompatibilityLevel,
   InspectConfig,
   InspectedArchive,
+  RebuildConfig,
+  RebuildProgress,
   ZipWarning,
 } from "bakezip";
 import type {
+  ProgressMessage,
   RequestMessage,
   ResponseMessage,
   WorkerRPC,
@@ -15,6 +18,10 @@ const pending = new Map<
   string | number,
   { resolve: (value: any) => void; reject: (reason?: any) => void }
 >();
+const progressListeners = new Map<
+  string,
+  (progress: RebuildProgress) => void
+>();
 
 function getWorker(): Worker {
   if (workerSingleton) {
@@ -29,6 +36,12 @@ function getWorker(): Worker {
   );
 
   worker.onmessage = (event: MessageEvent) => {
+    if ((event.data as ProgressMessage).type === "progress") {
+      const { rebuildId, progress } = event.data as ProgressMessage;
+      progressListeners.get(rebuildId)?.(progress);
+      return;
+    }
+
     const msg = event.data as ResponseMessage<any>;
     const handler = pending.get(msg.id);
     if (!handler) {
@@ -70,12 +83,31 @@ async function callWorker<K extends keyof WorkerRPC>(
   });
 }
 
+export type RebuildOptions = {
+  onProgress?: (progress: RebuildProgress) => void;
+  signal?: AbortSignal;
+};
+
+export type RebuiltArchive = {
+  blob: Blob;
+  /** Deletes the storage behind the blob, which must not be read afterwards */
+  release(): Promise<void>;
+};
+
 export type ZipProcessorProxy = {
   readonly compatibility: CompatibilityLevel;
   readonly warnings: readonly ZipWarning[];
 
   inspect(config: InspectConfig): Promise<InspectedArchive>;
-  rebuild(config: InspectConfig, omitEntries: BigUint64Array): Promise<Blob>;
+  /**
+   * Rebuilds the archive. Aborting the signal cancels the rebuild, which then rejects.
+   */
+  rebuild(
+    config: InspectConfig,
+    rebuildConfig: RebuildConfig,
+    omitEntries: BigUint64Array,
+    options?: RebuildOptions,
+  ): Promise<RebuiltArchive>;
   dispose(): Promise<void>;
 };
 
@@ -108,15 +140,44 @@ class ZipProcessorWorkerProxyImpl implements ZipProcessorProxy {
 
   async rebuild(
     config: InspectConfig,
+    rebuildConfig: RebuildConfig,
     omitEntries: BigUint64Array,
-  ): Promise<Blob> {
+    options: RebuildOptions = {},
+  ): Promise<RebuiltArchive> {
     const omit = Array.from(omitEntries);
-    const result = await callWorker("rebuild", {
-      processorId: this.#processorId,
-      config,
-      omitEntries: omit,
-    });
-    return result.blob;
+    const rebuildId = crypto.randomUUID();
+    const { onProgress, signal } = options;
+    signal?.throwIfAborted();
+
+    const onAbort = () => {
+      void callWorker("cancelRebuild", { rebuildId });
+    };
+    if (onProgress) {
+      progressListeners.set(rebuildId, onProgress);
+    }
+    signal?.addEventListener("abort", onAbort, { once: true });
+    try {
+      const result = await callWorker("rebuild", {
+        processorId: this.#processorId,
+        config,
+        rebuildConfig,
+        omitEntries: omit,
+        rebuildId,
+      });
+      return {
+        blob: result.blob,
+        release: async () => {
+          await callWorker("releaseRebuild", { rebuildId });
+        },
+      };
+    } catch (err) {
+      // Reject with the abort reason like other abortable APIs
+      signal?.throwIfAborted();
+      throw err;
+    } finally {
+      progressListeners.delete(rebuildId);
+      signal?.removeEventListener("abort", onAbort);
+    }
   }
 
   async dispose(): Promise<void> {
@@ -1,10 +1,13 @@
 /// <reference lib="webworker" />
 
 import init, {
+  RebuildCancellation,
   ZipProcessor,
   type CompatibilityLevel,
   type InspectConfig,
   type InspectedArchive,
+  type RebuildConfig,
+  type RebuildProgress,
   type ZipWarning,
 } from "bakezip";
 
@@ -38,12 +41,30 @@ export type WorkerRPC = {
     Request: {
       processorId: number;
       config: InspectConfig;
+      rebuildConfig: RebuildConfig;
       omitEntries: bigint[];
+      rebuildId: string;
     };
     Response: {
       blob: Blob;
     };
   };
+  cancelRebuild: {
+    Request: {
+      rebuildId: string;
+    };
+    Response: {
+      cancelled: true;
+    };
+  };
+  releaseRebuild: {
+    Request: {
+      rebuildId: string;
+    };
+    Response: {
+      released: true;
+    };
+  };
   dispose: {
     Request: {
       processorId: number;
@@ -70,6 +91,13 @@ export type ResponseMessage<T> =
   | SuccessResponseMessage<T>
   | ErrorResponseMessage;
 
+/** Sent while a rebuild is running, before its response */
+export type ProgressMessage = {
+  type: "progress";
+  rebuildId: string;
+  progress: RebuildProgress;
+};
+
 export type RequestMessage<T> = {
   id: string | number;
   type: keyof WorkerRPC;
@@ -79,6 +107,53 @@ export type RequestMessage<T> = {
 const processors = new Map<number, ZipProcessor>();
 let nextProcessorId = 1;
 
+const rebuildCancellations = new Map<string, RebuildCancellation>();
+
+/** Minimum interval between progress messages of a rebuild */
+const PROGRESS_INTERVAL_MS = 100;
+
+function getOutputFilename(rebuildId: string): string {
+  return `rebuild-${rebuildId}.zip`;
+}
+
+/**
+ * Creates the destination of a rebuilt archive.
+ * The origin private file system is preferred so that large archives are not kept in memory.
+ */
+async function createRebuildOutput(rebuildId: string): Promise<{
+  stream: WritableStream<Uint8Array>;
+  finish: () => Promise<Blob>;
+}> {
+  try {
+    const root = await navigator.storage.getDirectory();
+    const handle = await root.getFileHandle(getOutputFilename(rebuildId), {
+      create: true,
+    });
+    const stream = await handle.createWritable();
+    return { stream, finish: () => handle.getFile() };
+  } catch {
+    const parts: Uint8Array[] = [];
+    const stream = new WritableStream<Uint8Array>({
+      write(chunk) {
+        parts.push(chunk);
+      },
+    });
+    return {
+      stream,
+      finish: async () => new Blob(parts, { type: "application/zip" }),
+    };
+  }
+}
+
+async function removeRebuildOutput(rebuildId: string): Promise<void> {
+  try {
+    const root = await navigator.storage.getDirectory();
+    await root.removeEntry(getOutputFilename(rebuildId));
+  } catch {
+    // Nothing was written to the origin private file system
+  }
+}
+
 let initialized = false;
 async function ensureInit(): Promise<void> {
   if (initialized) {
@@ -131,7 +206,9 @@ const handler = {
   async rebuild(payload: {
     processorId: number;
     config: InspectConfig;
+    rebuildConfig: RebuildConfig;
     omitEntries: bigint[];
+    rebuildId: string;
   }): Promise<{ blob: Blob }> {
     await ensureInit();
     const processor = processors.get(payload.processorId);
@@ -140,8 +217,74 @@ const handler = {
     }
 
     const omit = new BigUint64Array(payload.omitEntries);
-    const blob = processor.rebuild(payload.config, omit);
-    return { blob };
+    const output = await createRebuildOutput(payload.rebuildId);
+    const cancellation = new RebuildCancellation();
+    rebuildCancellations.set(payload.rebuildId, cancellation);
+
+    const postProgress = (progress: RebuildProgress) => {
+      const msg: ProgressMessage = {
+        type: "progress",
+        rebuildId: payload.rebuildId,
+        progress,
+      };
+      (globalThis as unknown as DedicatedWorkerGlobalScope).postMessage(msg);
+    };
+    let lastProgressTime = -Infinity;
+    // Latest progress held back by the throttle, sent once the rebuild finishes
+    let pendingProgress: RebuildProgress | null = null;
+    const onProgress = (progress: RebuildProgress) => {
+      const now = performance.now();
+      if (now - lastProgressTime < PROGRESS_INTERVAL_MS) {
+        pendingProgress = progress;
+        return;
+      }
+      lastProgressTime = now;
+      pendingProgress = null;
+      postProgress(progress);
+    };
+
+    try {
+      let rebuilding: Promise<number>;
+      try {
+        rebuilding = processor.rebuild(
+          payload.config,
+          payload.rebuildConfig,
+          omit,
+          output.stream,
+          onProgress,
+          cancellation,
+        );
+      } catch (err) {
+        // The stream is only closed or aborted by the rebuild once it starts
+        await output.stream.abort(err);
+        throw err;
+      }
+      await rebuilding;
+      if (pendingProgress) {
+        postProgress(pendingProgress);
+      }
+      return { blob: await output.finish() };
+    } catch (err) {
+      await removeRebuildOutput(payload.rebuildId);
+      throw err;
+    } finally {
+      rebuildCancellations.delete(payload.rebuildId);
+      cancellation.free();
+    }
+  },
+
+  async cancelRebuild(payload: {
+    rebuildId: string;
+  }): Promise<{ cancelled: true }> {
+    rebuildCancellations.get(payload.rebuildId)?.cancel();
+    return { cancelled: true };
+  },
+
+  async releaseRebuild(payload: {
+    rebuildId: string;
+  }): Promise<{ released: true }> {
+    await removeRebuildOutput(payload.rebuildId);
+    return { released: true };
   },
 
   async dispose(payload: { processorId: number }): Promise<{ disposed: true }> {
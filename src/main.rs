@@ -1,16 +1,24 @@
 use std::io::{self, Read, Seek, Write};
 use std::path::{Path, PathBuf};
 
-use clap::{Parser, Subcommand, ValueEnum};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 
 use bakezip::zip::{
     compatibility::CompatibilityLevel,
     inspect::{
-        EncodingSelectionStrategy, FieldSelectionStrategy, InspectConfig, InspectedArchive,
-        WaveDashHandling, WaveDashNormalization,
+        BackslashHandling, CommentFieldSelectionStrategy, DetectionHint, EncodingOverride,
+        EncodingOverrideTarget, EncodingSelectionStrategy, EscapeStyle, FieldSelectionStrategy,
+        InspectConfig, InspectedArchive, UnicodeNormalization, WaveDashHandling,
+        WaveDashNormalization,
     },
-    parse::{ZipFile, ZipReader},
-    rebuild::{RebuildChunk, rebuild},
+    parse::{MultiVolumeReader, ZipFile, ZipReader},
+    rebuild::{
+        ArchiveCommentPolicy, CancellationToken, FilenameOutputMode, PrefixHandling, RebuildChunk,
+        RebuildConfig, RebuildError, RebuildProgress, RebuildReport, RebuildWarning,
+        UnmappablePolicy, ZipWriter, rebuild_to, rebuild_with_report, restore,
+    },
+    rename::{RenameConfig, RenameOverride, RenameRule, WINDOWS_ILLEGAL_CHARACTERS},
+    substitution::SubstitutionPreset,
 };
 
 #[derive(Parser)]
@@ -20,6 +28,9 @@ struct Cli {
     command: Option<Commands>,
 
     /// Input ZIP file
+    ///
+    /// For split archives, specify the last volume (.zip) or the first volume (.001);
+    /// the other volumes are picked up automatically.
     input: PathBuf,
 
     /// Encoding to use (fallback by default, forced if --force is used)
@@ -30,10 +41,32 @@ struct Cli {
     #[arg(long, short, global = true)]
     force: bool,
 
+    /// Locale or TLD the archive comes from (e.g. ja, zh-TW, ru), used as a detection hint
+    #[arg(long, global = true, conflicts_with = "force")]
+    locale_hint: Option<String>,
+
+    /// Only detect the specified encoding, can be specified multiple times
+    #[arg(long, global = true, conflicts_with = "force")]
+    allow_encoding: Vec<String>,
+
+    /// Encoding for a single entry as INDEX=ENCODING, can be specified multiple times
+    #[arg(long, global = true, value_parser = parse_entry_override)]
+    override_entry: Vec<(u64, String)>,
+
+    /// Encoding for entries under a path as PREFIX=ENCODING, can be specified multiple times
+    ///
+    /// Entry overrides take precedence, then the first matching prefix.
+    #[arg(long, global = true, value_parser = parse_prefix_override)]
+    override_prefix: Vec<(String, String)>,
+
     /// Field selection strategy
     #[arg(long, short = 's', global = true, value_enum, default_value_t = FieldSelectionStrategyArg::CdhUnicodeThenLfhUnicodeThenCdh)]
     field: FieldSelectionStrategyArg,
 
+    /// Comment field selection strategy
+    #[arg(long, global = true, value_enum, default_value_t = CommentFieldSelectionStrategyArg::CdhUnicodeThenCdh)]
+    comment_field: CommentFieldSelectionStrategyArg,
+
     /// Ignore UTF-8 flag
     #[arg(long, global = true)]
     ignore_utf8_flag: bool,
@@ -42,6 +75,10 @@ struct Cli {
     #[arg(long, global = true)]
     ignore_crc32_mismatch: bool,
 
+    /// Recover entries by scanning local file headers instead of reading the central directory
+    #[arg(long, global = true)]
+    recover: bool,
+
     /// How to handle Wave Dash (U+301C) when decoding from Shift_JIS
     #[arg(long, global = true, value_enum, default_value_t = WaveDashHandlingArg::DecodeToFullwidthTilde)]
     wave_dash_handling: WaveDashHandlingArg,
@@ -49,6 +86,62 @@ struct Cli {
     /// How to normalize Wave Dash (U+301C) and Fullwidth Tilde (U+FF5E)
     #[arg(long, global = true, value_enum, default_value_t = WaveDashNormalizationArg::Preserve)]
     wave_dash_normalization: WaveDashNormalizationArg,
+
+    /// Built-in character substitution table to apply, can be specified multiple times
+    #[arg(long = "substitution", global = true, value_enum)]
+    substitutions: Vec<SubstitutionPresetArg>,
+
+    /// Unicode normalization form applied to decoded filenames
+    #[arg(long, global = true, value_enum, default_value_t = UnicodeNormalizationArg::Preserve)]
+    unicode_normalization: UnicodeNormalizationArg,
+
+    /// Number of ranked candidate encodings to show
+    #[arg(long, global = true, default_value_t = 0)]
+    candidates: usize,
+
+    /// Also show ranked candidate encodings for each entry
+    #[arg(long, global = true, requires = "candidates")]
+    entry_candidates: bool,
+
+    /// Decode every filename source of each entry and report where they disagree
+    #[arg(long, global = true)]
+    compare_sources: bool,
+
+    /// How to handle backslashes in decoded filenames
+    #[arg(long, global = true, value_enum, default_value_t = BackslashHandlingArg::Preserve)]
+    backslash_handling: BackslashHandlingArg,
+
+    /// How to show bytes that failed to decode
+    #[arg(long, global = true, value_enum, default_value_t = EscapeStyleArg::Backslash)]
+    escape_style: EscapeStyleArg,
+}
+
+#[derive(Args)]
+struct RenameArgs {
+    /// Name for a single entry as INDEX=NAME, can be specified multiple times
+    #[arg(long, value_parser = parse_rename)]
+    rename: Vec<(u64, String)>,
+
+    /// Remove this many leading path components, such as a top-level folder
+    ///
+    /// Entries with no more path components than this are left out.
+    #[arg(long)]
+    strip_components: Option<u32>,
+
+    /// Replace text in names as FROM=TO, can be specified multiple times and applied in order
+    #[arg(long, value_parser = parse_replace)]
+    replace: Vec<(String, String)>,
+
+    /// Replace matches of a regular expression in names, can be specified multiple times and applied
+    /// in order after `--replace`
+    ///
+    /// The replacement may refer to capture groups as $1 or ${name}.
+    #[arg(long, num_args = 2, value_names = ["PATTERN", "REPLACEMENT"])]
+    replace_regex: Vec<String>,
+
+    /// Replace characters not allowed on Windows (<>:"\|?* and control characters) with underscores
+    #[arg(long)]
+    replace_windows_illegal: bool,
 }
 
 #[derive(Subcommand)]
@@ -68,7 +161,83 @@ enum Commands {
         /// Remove OS metadata files (__MACOSX, .DS_Store, Thumbs.db, desktop.ini)
         #[arg(long, short = 'm')]
         remove_os_metadata: bool,
+
+        /// Keep data prepended to the archive (e.g. self-extractor stub)
+        #[arg(long)]
+        keep_prefix: bool,
+
+        /// How to write the archive comment
+        #[arg(long, value_enum, default_value_t = ArchiveCommentArg::Keep)]
+        archive_comment: ArchiveCommentArg,
+
+        /// Replace the archive comment with the given text
+        #[arg(long, conflicts_with = "archive_comment")]
+        set_archive_comment: Option<String>,
+
+        /// Repair names detected as double-encoded UTF-8
+        #[arg(long)]
+        repair_double_encoding: bool,
+
+        /// Write names in this legacy encoding (e.g. Shift_JIS, IBM437) with a Unicode Path extra field,
+        /// for tools that ignore the UTF-8 flag
+        #[arg(long)]
+        legacy_encoding: Option<String>,
+
+        /// Write names only in this encoding (e.g. Shift_JIS, GBK) without the UTF-8 flag
+        #[arg(long, conflicts_with = "legacy_encoding")]
+        target_encoding: Option<String>,
+
+        #[command(flatten)]
+        rename: Box<RenameArgs>,
+
+        /// Write a JSON manifest of the original and new names, for use with `restore`
+        #[arg(long)]
+        manifest: Option<PathBuf>,
+
+        /// How to handle characters that cannot be mapped to the target encoding
+        #[arg(
+            long,
+            value_enum,
+            default_value_t = UnmappableArg::Fail,
+            requires = "target_encoding"
+        )]
+        unmappable: UnmappableArg,
+
+        /// Replacement for unmappable characters with `--unmappable replace` [default: _]
+        #[arg(long, requires = "target_encoding")]
+        replacement: Option<String>,
     },
+    /// Restore the original names of an archive written by `rebuild --manifest`
+    Restore {
+        /// Manifest written when rebuilding the input archive
+        #[arg(long)]
+        manifest: PathBuf,
+
+        /// Output file path
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum UnmappableArg {
+    /// Fail the rebuild
+    Fail,
+    /// Replace with the string given by `--replacement`
+    Replace,
+    /// Replace with HTML numeric character references such as `&#54620;`
+    #[clap(name = "ncr")]
+    NumericCharacterReference,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum ArchiveCommentArg {
+    /// Keep the original bytes
+    Keep,
+    /// Convert to UTF-8 using the detected encoding
+    TranscodeUtf8,
+    /// Remove the comment
+    Strip,
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
@@ -91,6 +260,25 @@ enum FieldSelectionStrategyArg {
     LfhOnly,
 }
 
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum CommentFieldSelectionStrategyArg {
+    #[clap(name = "cdhu-cdh")]
+    CdhUnicodeThenCdh,
+    #[clap(name = "cdh")]
+    CdhOnly,
+}
+
+impl From<CommentFieldSelectionStrategyArg> for CommentFieldSelectionStrategy {
+    fn from(arg: CommentFieldSelectionStrategyArg) -> Self {
+        match arg {
+            CommentFieldSelectionStrategyArg::CdhUnicodeThenCdh => {
+                CommentFieldSelectionStrategy::CdhUnicodeThenCdh
+            }
+            CommentFieldSelectionStrategyArg::CdhOnly => CommentFieldSelectionStrategy::CdhOnly,
+        }
+    }
+}
+
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
 enum WaveDashHandlingArg {
     DecodeToFullwidthTilde,
@@ -127,6 +315,148 @@ impl From<WaveDashNormalizationArg> for WaveDashNormalization {
     }
 }
 
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum SubstitutionPresetArg {
+    Cp932ToJis,
+    JisToCp932,
+}
+
+impl From<SubstitutionPresetArg> for SubstitutionPreset {
+    fn from(arg: SubstitutionPresetArg) -> Self {
+        match arg {
+            SubstitutionPresetArg::Cp932ToJis => SubstitutionPreset::Cp932ToJis,
+            SubstitutionPresetArg::JisToCp932 => SubstitutionPreset::JisToCp932,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum UnicodeNormalizationArg {
+    Preserve,
+    Nfc,
+    Nfd,
+    Nfkc,
+}
+
+impl From<UnicodeNormalizationArg> for UnicodeNormalization {
+    fn from(arg: UnicodeNormalizationArg) -> Self {
+        match arg {
+            UnicodeNormalizationArg::Preserve => UnicodeNormalization::Preserve,
+            UnicodeNormalizationArg::Nfc => UnicodeNormalization::Nfc,
+            UnicodeNormalizationArg::Nfd => UnicodeNormalization::Nfd,
+            UnicodeNormalizationArg::Nfkc => UnicodeNormalization::Nfkc,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum BackslashHandlingArg {
+    Preserve,
+    ConvertToSlash,
+}
+
+impl From<BackslashHandlingArg> for BackslashHandling {
+    fn from(arg: BackslashHandlingArg) -> Self {
+        match arg {
+            BackslashHandlingArg::Preserve => BackslashHandling::Preserve,
+            BackslashHandlingArg::ConvertToSlash => BackslashHandling::ConvertToSlash,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum EscapeStyleArg {
+    /// \xXX
+    Backslash,
+    /// %XX
+    Percent,
+}
+
+impl From<EscapeStyleArg> for EscapeStyle {
+    fn from(arg: EscapeStyleArg) -> Self {
+        match arg {
+            EscapeStyleArg::Backslash => EscapeStyle::Backslash,
+            EscapeStyleArg::Percent => EscapeStyle::Percent,
+        }
+    }
+}
+
+fn parse_entry_override(s: &str) -> Result<(u64, String), String> {
+    let (index, encoding) = s
+        .split_once('=')
+        .ok_or_else(|| format!("Expected INDEX=ENCODING, got '{s}'"))?;
+    let index = index
+        .parse()
+        .map_err(|e| format!("Invalid entry index '{index}': {e}"))?;
+    Ok((index, encoding.to_string()))
+}
+
+fn parse_rename(s: &str) -> Result<(u64, String), String> {
+    let (index, name) = s
+        .split_once('=')
+        .ok_or_else(|| format!("Expected INDEX=NAME, got '{s}'"))?;
+    let index = index
+        .parse()
+        .map_err(|e| format!("Invalid entry index '{index}': {e}"))?;
+    Ok((index, name.to_string()))
+}
+
+fn parse_replace(s: &str) -> Result<(String, String), String> {
+    let (from, to) = s
+        .split_once('=')
+        .ok_or_else(|| format!("Expected FROM=TO, got '{s}'"))?;
+    if from.is_empty() {
+        return Err("Text to replace must not be empty".to_string());
+    }
+    Ok((from.to_string(), to.to_string()))
+}
+
+fn parse_prefix_override(s: &str) -> Result<(String, String), String> {
+    // Encoding labels never contain '=', unlike paths
+    let (prefix, encoding) = s
+        .rsplit_once('=')
+        .ok_or_else(|| format!("Expected PREFIX=ENCODING, got '{s}'"))?;
+    Ok((prefix.to_string(), encoding.to_string()))
+}
+
+impl From<RenameArgs> for RenameConfig {
+    /// Rules are applied in a fixed order, regardless of the order of the flags
+    fn from(args: RenameArgs) -> Self {
+        RenameConfig {
+            overrides: args
+                .rename
+                .into_iter()
+                .map(|(index, name)| RenameOverride { index, name })
+                .collect(),
+            rules: args
+                .strip_components
+                .map(|count| RenameRule::StripComponents { count })
+                .into_iter()
+                .chain(
+                    args.replace
+                        .into_iter()
+                        .map(|(from, to)| RenameRule::Replace { from, to }),
+                )
+                .chain(
+                    args.replace_regex
+                        .chunks_exact(2)
+                        .map(|pair| RenameRule::Regex {
+                            pattern: pair[0].clone(),
+                            replacement: pair[1].clone(),
+                        }),
+                )
+                .chain(
+                    args.replace_windows_illegal
+                        .then(|| RenameRule::ReplaceCharacters {
+                            characters: WINDOWS_ILLEGAL_CHARACTERS.to_string(),
+                            replacement: "_".to_string(),
+                        }),
+                )
+                .collect(),
+        }
+    }
+}
+
 impl From<FieldSelectionStrategyArg> for FieldSelectionStrategy {
     fn from(arg: FieldSelectionStrategyArg) -> Self {
         match arg {
@@ -156,22 +486,26 @@ impl From<FieldSelectionStrategyArg> for FieldSelectionStrategy {
 
 struct FileZipReader {
     file: std::fs::File,
+    /// Length of the file when it was opened
+    size: u64,
 }
 
 impl FileZipReader {
     fn new(path: impl AsRef<Path>) -> io::Result<Self> {
         let file = std::fs::File::open(path)?;
-        Ok(Self { file })
+        let size = file.metadata()?.len();
+        Ok(Self { file, size })
     }
 }
 
 impl ZipReader for FileZipReader {
     async fn get_size(&mut self) -> io::Result<u64> {
-        let metadata = self.file.metadata()?;
-        Ok(metadata.len())
+        Ok(self.size)
     }
 
     async fn read(&mut self, offset: u64, size: u64) -> io::Result<Vec<u8>> {
+        // Like other readers, return fewer bytes when reading past the end of the file
+        let size = size.min(self.size.saturating_sub(offset));
         self.file.seek(io::SeekFrom::Start(offset))?;
         let mut buffer = vec![0; size as usize];
         self.file.read_exact(&mut buffer)?;
@@ -179,6 +513,105 @@ impl ZipReader for FileZipReader {
     }
 }
 
+struct FileZipWriter {
+    file: io::BufWriter<std::fs::File>,
+}
+
+impl FileZipWriter {
+    fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = std::fs::File::create(path)?;
+        Ok(Self {
+            file: io::BufWriter::new(file),
+        })
+    }
+}
+
+impl ZipWriter for FileZipWriter {
+    async fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        self.file.write_all(data)
+    }
+}
+
+/// Write the chunks of a rebuilt archive to the output file, showing the progress on stderr
+///
+/// Ctrl-C cancels the write, and the output file is removed if the write does not complete.
+async fn write_chunks(
+    reader: &mut impl ZipReader,
+    chunks: &[RebuildChunk],
+    output: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut writer = FileZipWriter::create(output)?;
+    // Only redraw the progress line when the percentage or the entry count changes
+    let mut last_reported = None;
+    let on_progress = |progress: &RebuildProgress| {
+        let percent = (progress.bytes_written * 100)
+            .checked_div(progress.total_bytes)
+            .unwrap_or(100);
+        if last_reported != Some((percent, progress.entries_written)) {
+            last_reported = Some((percent, progress.entries_written));
+            eprint!(
+                "\rWriting: {percent:3}% ({}/{} entries)",
+                progress.entries_written, progress.total_entries
+            );
+        }
+    };
+    let cancellation = CancellationToken::new();
+    let handler_cancellation = cancellation.clone();
+    ctrlc::set_handler(move || handler_cancellation.cancel())?;
+    let result = rebuild_to(reader, &mut writer, chunks, on_progress, &cancellation)
+        .await
+        .and_then(|_| writer.file.flush().map_err(RebuildError::from));
+    eprintln!();
+    if let Err(e) = result {
+        drop(writer);
+        remove_output(output);
+        return Err(format!("Failed to write zip: {e}").into());
+    }
+    Ok(())
+}
+
+/// Remove an output that must not be kept, reporting when that fails
+fn remove_output(output: &Path) {
+    if let Err(e) = std::fs::remove_file(output) {
+        eprintln!(
+            "Failed to remove incomplete output {}: {e}",
+            output.display()
+        );
+    }
+}
+
+/// Find all volumes of a split archive in order, given the path to one of them
+///
+/// Supports spanned archives (`.z01`, `.z02`, ..., `.zip`) and archives split by size (`.001`, `.002`, ...).
+fn find_volumes(input: &Path) -> Vec<PathBuf> {
+    let Some(extension) = input.extension().and_then(|ext| ext.to_str()) else {
+        return vec![input.to_path_buf()];
+    };
+
+    if extension.eq_ignore_ascii_case("zip") {
+        let prefix = if extension == "ZIP" { "Z" } else { "z" };
+        let mut volumes = (1..)
+            .map(|n| input.with_extension(format!("{prefix}{n:02}")))
+            .take_while(|path| path.is_file())
+            .collect::<Vec<_>>();
+        volumes.push(input.to_path_buf());
+        return volumes;
+    }
+
+    if extension.len() >= 3 && extension.bytes().all(|b| b.is_ascii_digit()) {
+        let width = extension.len();
+        let first = input.with_extension(format!("{:0width$}", 1));
+        if first.is_file() {
+            return (1..)
+                .map(|n| input.with_extension(format!("{n:0width$}")))
+                .take_while(|path| path.is_file())
+                .collect();
+        }
+    }
+
+    vec![input.to_path_buf()]
+}
+
 fn is_os_metadata_file(filename: &str) -> bool {
     let lower = filename.to_lowercase();
     // Directories and files
@@ -208,13 +641,30 @@ fn is_os_metadata_file(filename: &str) -> bool {
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let cli = Cli::parse();
 
-    let mut reader = FileZipReader::new(&cli.input)?;
-    let zip_file = ZipFile::parse(&mut reader, false, |idx, err| {
+    let volumes = find_volumes(&cli.input);
+    if volumes.len() > 1 {
+        eprintln!("Reading {} volumes", volumes.len());
+    }
+    let mut reader = MultiVolumeReader::new(
+        volumes
+            .iter()
+            .map(FileZipReader::new)
+            .collect::<io::Result<Vec<_>>>()?,
+    )
+    .await?;
+    let on_warning = |idx, err| {
         eprintln!("Warning at index {idx:?}: {err}");
         Ok(())
-    })
-    .await
-    .map_err(|e| format!("Failed to parse zip: {e}"))?;
+    };
+    let zip_file = if cli.recover {
+        ZipFile::recover(&mut reader, on_warning)
+            .await
+            .map_err(|e| format!("Failed to recover zip: {e}"))?
+    } else {
+        ZipFile::parse(&mut reader, false, on_warning)
+            .await
+            .map_err(|e| format!("Failed to parse zip: {e}"))?
+    };
 
     let encoding_strategy = if cli.force {
         if let Some(enc) = cli.encoding {
@@ -229,16 +679,47 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         EncodingSelectionStrategy::PreferOverallDetected {
             fallback_encoding: cli.encoding,
             ignore_utf8_flag: cli.ignore_utf8_flag,
+            detection_hint: DetectionHint {
+                locale: cli.locale_hint,
+                allowed_encodings: cli.allow_encoding,
+            },
         }
     };
 
     let config = InspectConfig {
         encoding: encoding_strategy,
+        encoding_overrides: cli
+            .override_entry
+            .into_iter()
+            .map(|(index, encoding)| EncodingOverride {
+                target: EncodingOverrideTarget::Index { index },
+                encoding,
+            })
+            .chain(
+                cli.override_prefix
+                    .into_iter()
+                    .map(|(prefix, encoding)| EncodingOverride {
+                        target: EncodingOverrideTarget::PathPrefix { prefix },
+                        encoding,
+                    }),
+            )
+            .collect(),
         field_selection_strategy: cli.field.into(),
+        comment_field_selection_strategy: cli.comment_field.into(),
         ignore_crc32_mismatch: cli.ignore_crc32_mismatch,
         needs_original_bytes: false,
         wave_dash_handling: cli.wave_dash_handling.into(),
         wave_dash_normalization: cli.wave_dash_normalization.into(),
+        substitution_tables: cli
+            .substitutions
+            .iter()
+            .map(|&preset| SubstitutionPreset::from(preset).table())
+            .collect(),
+        unicode_normalization: cli.unicode_normalization.into(),
+        backslash_handling: cli.backslash_handling.into(),
+        candidate_count: cli.candidates,
+        entry_candidates: cli.entry_candidates,
+        compare_filename_sources: cli.compare_sources,
     };
 
     match cli.command.unwrap_or(Commands::Inspect) {
@@ -246,37 +727,156 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             let inspected = InspectedArchive::inspect(&zip_file, &config)
                 .map_err(|e| format!("Failed to inspect zip: {e}"))?;
 
-            println!("Overall encoding: {:?}", inspected.overall_encoding);
+            match inspected.overall_encoding_score {
+                Some(score) => println!(
+                    "Overall encoding: {:?} (score {score:.2})",
+                    inspected.overall_encoding
+                ),
+                None => println!("Overall encoding: {:?}", inspected.overall_encoding),
+            }
+            for candidate in &inspected.encoding_candidates {
+                println!(
+                    "  Candidate {} (score {:.2}, {} errors): {}",
+                    candidate.encoding, candidate.score, candidate.error_count, candidate.preview
+                );
+            }
+            for cluster in &inspected.encoding_clusters {
+                println!(
+                    "  Cluster {}/: {:?} ({} entries)",
+                    cluster.directory,
+                    cluster.encoding,
+                    cluster.entries.len()
+                );
+            }
+
+            if zip_file.prefix_length != 0 {
+                println!("Prepended data: {} bytes", zip_file.prefix_length);
+            }
+
+            if let Some(comment) = inspected.comment.as_ref().and_then(|c| c.decoded.as_ref()) {
+                println!("Archive comment: {}", comment.string);
+            }
 
             let compatibility = CompatibilityLevel::analyze(&zip_file);
             println!("Compatibility: {compatibility:?}");
 
             println!("Entries: {}", inspected.entries.len());
-            if inspected.contains_sjis_wave_dash {
-                println!("Contains Shift_JIS Wave Dash/Fullwidth Tilde");
+            for hit in &inspected.substitution_hits {
+                println!(
+                    "Substituted U+{:04X} -> U+{:04X} ({}): {} entries",
+                    hit.mapping.from as u32,
+                    hit.mapping.to as u32,
+                    hit.table,
+                    hit.entries.len()
+                );
+            }
+            for potential in &inspected.potential_substitutions {
+                println!(
+                    "Substitutable U+{:04X} -> U+{:04X} in {} names: {} entries",
+                    potential.mapping.from as u32,
+                    potential.mapping.to as u32,
+                    potential.source_encoding,
+                    potential.entries.len()
+                );
             }
-            if inspected.contains_other_wave_dash {
-                println!("Contains Wave Dash (Non-Shift_JIS)");
+            if !inspected.backslash_entries.is_empty() {
+                println!(
+                    "Entries with backslashes: {}",
+                    inspected.backslash_entries.len()
+                );
             }
-            if inspected.contains_other_fullwidth_tilde {
-                println!("Contains Fullwidth Tilde (Non-Shift_JIS)");
+            if !inspected.mismatched_source_entries.is_empty() {
+                println!(
+                    "Entries with mismatched filename sources: {}",
+                    inspected.mismatched_source_entries.len()
+                );
+            }
+            if !inspected.unicode_normalized_entries.is_empty() {
+                println!(
+                    "Unicode normalized entries: {}",
+                    inspected.unicode_normalized_entries.len()
+                );
             }
 
+            let escape_style = cli.escape_style.into();
             for (i, entry) in inspected.entries.iter().enumerate() {
                 let filename = entry
                     .filename
                     .decoded
                     .as_ref()
-                    .map(|d| d.string.as_str())
-                    .unwrap_or("<decoding failed>");
+                    .map(|d| d.escaped(escape_style))
+                    .unwrap_or_else(|| "<decoding failed>".to_string());
+
+                match entry.timestamps.modification_time {
+                    Some(time) => {
+                        println!("{i}: {filename} ({} {:?})", time.to_iso8601(), time.source)
+                    }
+                    None => println!("{i}: {filename}"),
+                }
 
-                println!("{i}: {filename}");
+                if let Some(decoded) = &entry.filename.decoded
+                    && !decoded.errors.is_empty()
+                {
+                    let errors = decoded
+                        .errors
+                        .iter()
+                        .map(|e| format!("bytes {}..{}", e.byte_start, e.byte_end))
+                        .collect::<Vec<_>>();
+                    println!("    Decoding errors: {}", errors.join(", "));
+                }
+                if !entry.filename.mismatches.is_empty() {
+                    for mismatch in &entry.filename.mismatches {
+                        println!("    Mismatch: {mismatch:?}");
+                    }
+                    for source in &entry.filename.sources {
+                        let name = source
+                            .decoded
+                            .as_ref()
+                            .map(|d| d.escaped(escape_style))
+                            .unwrap_or_else(|| "<decoding failed>".to_string());
+                        let selected = if source.kind == entry.filename.kind {
+                            " (selected)"
+                        } else {
+                            ""
+                        };
+                        println!("    {:?}: {name}{selected}", source.kind);
+                    }
+                }
+                if !entry.filename.encoding_candidates.is_empty() {
+                    let candidates = entry
+                        .filename
+                        .encoding_candidates
+                        .iter()
+                        .map(|c| format!("{} {:.2}", c.encoding, c.score))
+                        .collect::<Vec<_>>();
+                    println!("    Candidates: {}", candidates.join(", "));
+                }
+                if let Some(repair) = &entry.filename.double_encoding_repair {
+                    println!(
+                        "    Double-encoded via {}, repaired: {}",
+                        repair.encoding_chain.join(" -> "),
+                        repair.repaired
+                    );
+                }
+                if let Some(comment) = entry.comment.as_ref().and_then(|c| c.decoded.as_ref()) {
+                    println!("    Comment: {}", comment.string);
+                }
             }
         }
         Commands::Rebuild {
             output,
             omit,
             remove_os_metadata,
+            keep_prefix,
+            archive_comment,
+            set_archive_comment,
+            repair_double_encoding,
+            legacy_encoding,
+            target_encoding,
+            rename,
+            manifest,
+            unmappable,
+            replacement,
         } => {
             let mut omit_indices = omit.clone();
             if remove_os_metadata {
@@ -295,24 +895,102 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 }
             }
 
-            let (chunks, _) = rebuild(&zip_file, &config, &omit_indices)
-                .map_err(|e| format!("Failed to rebuild zip: {e}"))?;
-
-            let mut output_file = std::fs::File::create(output)?;
-            for chunk in chunks {
-                match chunk {
-                    RebuildChunk::Binary(data) => {
-                        output_file.write_all(&data)?;
+            let rebuild_config = RebuildConfig {
+                prefix_handling: if keep_prefix {
+                    PrefixHandling::Keep
+                } else {
+                    PrefixHandling::Drop
+                },
+                archive_comment: match (set_archive_comment, archive_comment) {
+                    (Some(comment), _) => ArchiveCommentPolicy::Replace { comment },
+                    (None, ArchiveCommentArg::Keep) => ArchiveCommentPolicy::Keep,
+                    (None, ArchiveCommentArg::TranscodeUtf8) => ArchiveCommentPolicy::TranscodeUtf8,
+                    (None, ArchiveCommentArg::Strip) => ArchiveCommentPolicy::Strip,
+                },
+                repair_double_encoding,
+                filename_mode: match (legacy_encoding, target_encoding) {
+                    (Some(encoding), _) => FilenameOutputMode::LegacyWithUnicodePath { encoding },
+                    (None, Some(encoding)) => FilenameOutputMode::Legacy {
+                        encoding,
+                        unmappable: match unmappable {
+                            UnmappableArg::Fail => UnmappablePolicy::Fail,
+                            UnmappableArg::Replace => UnmappablePolicy::Replace {
+                                replacement: replacement.unwrap_or_else(|| "_".to_string()),
+                            },
+                            UnmappableArg::NumericCharacterReference => {
+                                UnmappablePolicy::NumericCharacterReference
+                            }
+                        },
+                    },
+                    (None, None) => FilenameOutputMode::Utf8,
+                },
+                rename: (*rename).into(),
+            };
+
+            let (chunks, _, report) =
+                rebuild_with_report(&zip_file, &config, &rebuild_config, &omit_indices)
+                    .map_err(|e| format!("Failed to rebuild zip: {e}"))?;
+            for warning in &report.warnings {
+                match warning {
+                    RebuildWarning::NotRepresentable {
+                        index,
+                        name,
+                        encoding,
+                    } => eprintln!(
+                        "Warning at index {index}: {name} cannot be represented in {encoding}, written as UTF-8"
+                    ),
+                    RebuildWarning::LossyConversion {
+                        index,
+                        name,
+                        written_name,
+                        comment_lossy,
+                        unmappable,
+                    } => {
+                        let comment = if *comment_lossy { " (comment too)" } else { "" };
+                        eprintln!(
+                            "Warning at index {index}: replaced {} in {name} -> {written_name}{comment}",
+                            unmappable
+                                .iter()
+                                .map(|c| format!("U+{:04X}", *c as u32))
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        );
                     }
-                    RebuildChunk::Reference { offset, size } => {
-                        let data = reader.read(offset, size).await?;
-                        output_file.write_all(&data)?;
+                    RebuildWarning::RenamedToEmpty { index, name } => {
+                        eprintln!(
+                            "Warning at index {index}: {name} was renamed to nothing, left out"
+                        )
                     }
+                    RebuildWarning::UndecodableComment { index, name } => eprintln!(
+                        "Warning at index {index}: the comment of {name} could not be decoded, invalid bytes replaced"
+                    ),
                 }
             }
 
+            // Serialize the manifest first, as the renamed archive cannot be restored without it
+            let manifest = manifest
+                .map(|manifest| serde_json::to_vec_pretty(&report).map(|data| (manifest, data)))
+                .transpose()
+                .map_err(|e| format!("Failed to serialize manifest: {e}"))?;
+            write_chunks(&mut reader, &chunks, &output).await?;
+            if let Some((manifest, data)) = manifest
+                && let Err(e) = std::fs::write(&manifest, data)
+            {
+                remove_output(&output);
+                return Err(format!("Failed to write manifest: {e}").into());
+            }
+
             println!("Rebuild complete.");
         }
+        Commands::Restore { manifest, output } => {
+            let report: RebuildReport = serde_json::from_slice(&std::fs::read(manifest)?)
+                .map_err(|e| format!("Failed to read manifest: {e}"))?;
+            let (chunks, _) =
+                restore(&zip_file, &report).map_err(|e| format!("Failed to restore zip: {e}"))?;
+            write_chunks(&mut reader, &chunks, &output).await?;
+
+            println!("Restore complete.");
+        }
     }
 
     Ok(())
@@ -1,15 +1,19 @@
 use std::io;
 
-use js_sys::{JsString, Uint8Array};
+use js_sys::{Array, Function, JsString, Promise, Uint8Array};
+use tsify::Tsify;
 use wasm_bindgen::prelude::*;
-use wasm_bindgen_futures::JsFuture;
-use web_sys::{Blob, BlobPropertyBag};
+use wasm_bindgen_futures::{JsFuture, future_to_promise};
+use web_sys::{Blob, WritableStream, WritableStreamDefaultWriter};
 
 use crate::zip::{
     compatibility::CompatibilityLevel,
     inspect::{InspectConfig, InspectedArchive},
-    parse::{ZipFile, ZipParseError, ZipReader},
-    rebuild::{RebuildChunk, rebuild},
+    parse::{MultiVolumeReader, ZipFile, ZipParseError, ZipReader},
+    rebuild::{
+        CancellationToken, ProgressThrottle, RebuildConfig, RebuildError, RebuildProgress,
+        ZipWriter, rebuild, rebuild_to,
+    },
 };
 
 #[wasm_bindgen]
@@ -43,6 +47,48 @@ impl ZipProcessor {
         })
     }
 
+    /// Parse a split or spanned archive from its volumes in order
+    pub async fn parse_volumes(volumes: Vec<Blob>) -> Result<Self, JsValue> {
+        let mut reader =
+            MultiVolumeReader::new(volumes.iter().cloned().map(JsBlobReader::new).collect())
+                .await
+                .map_err(|e| JsValue::from_str(&format!("Failed to read volumes: {e}")))?;
+        let (zip_file, warnings) = ZipFile::parse_with_warnings(&mut reader, true)
+            .await
+            .map_err(|e| JsValue::from_str(&format!("Failed to parse zip: {e}")))?;
+        let compatibility = CompatibilityLevel::analyze(&zip_file);
+
+        // Offsets are global across volumes, so the concatenation can be sliced directly
+        let blob =
+            Blob::new_with_blob_sequence(&volumes.into_iter().collect::<Array>()).map_err(|e| {
+                JsValue::from_str(&format!(
+                    "Failed to concatenate volumes: {}",
+                    JsString::from(e)
+                ))
+            })?;
+
+        Ok(ZipProcessor {
+            blob,
+            zip_file,
+            compatibility,
+            warnings,
+        })
+    }
+
+    pub async fn recover(blob: Blob) -> Result<Self, JsValue> {
+        let mut reader = JsBlobReader::new(blob.clone());
+        let (zip_file, warnings) = ZipFile::recover_with_warnings(&mut reader)
+            .await
+            .map_err(|e| JsValue::from_str(&format!("Failed to recover zip: {e}")))?;
+        let compatibility = CompatibilityLevel::analyze(&zip_file);
+        Ok(ZipProcessor {
+            blob,
+            zip_file,
+            compatibility,
+            warnings,
+        })
+    }
+
     #[wasm_bindgen(getter)]
     pub fn compatibility(&self) -> CompatibilityLevel {
         self.compatibility
@@ -68,40 +114,108 @@ impl ZipProcessor {
             .map_err(|e| JsValue::from_str(&format!("Failed to inspect zip archive: {e}")))
     }
 
-    pub fn rebuild(&self, config: &InspectConfig, omit_entries: &[u64]) -> Result<Blob, JsValue> {
-        let (rebuilt_chunks, _) = rebuild(&self.zip_file, config, omit_entries)
+    #[wasm_bindgen(getter)]
+    pub fn prefix_length(&self) -> u64 {
+        self.zip_file.prefix_length
+    }
+
+    /// Rebuild the archive and write it to the stream
+    ///
+    /// The stream is closed once the archive is written and aborted on failure or cancellation.
+    /// Resolves to the number of bytes written.
+    #[wasm_bindgen(unchecked_return_type = "Promise<number>")]
+    pub fn rebuild(
+        &self,
+        config: &InspectConfig,
+        rebuild_config: &RebuildConfig,
+        omit_entries: &[u64],
+        output: WritableStream,
+        #[wasm_bindgen(unchecked_param_type = "(progress: RebuildProgress) => void")]
+        on_progress: Function,
+        cancellation: &RebuildCancellation,
+    ) -> Result<Promise, JsValue> {
+        let (chunks, _) = rebuild(&self.zip_file, config, rebuild_config, omit_entries)
             .map_err(|e| JsValue::from_str(&format!("Failed to rebuild zip archive: {e}")))?;
-        let rebuilt_blob_parts = rebuilt_chunks
-            .into_iter()
-            .map(|chunk| match chunk {
-                RebuildChunk::Binary(data) => Ok(JsValue::from(Uint8Array::from(&data[..]))),
-                RebuildChunk::Reference { offset, size } => {
-                    let slice = self
-                        .blob
-                        .slice_with_f64_and_f64(offset as f64, (offset + size) as f64)
-                        .map_err(|e| {
-                            JsValue::from_str(&format!(
-                                "Failed to slice blob {size} bytes from {offset}: {}",
-                                JsString::from(e)
-                            ))
-                        })?;
-                    Ok(JsValue::from(slice))
+        let mut reader = JsBlobReader::new(self.blob.clone());
+        let cancellation = cancellation.0.clone();
+
+        Ok(future_to_promise(async move {
+            let mut writer = JsStreamWriter(output.get_writer().map_err(|e| {
+                JsValue::from_str(&format!(
+                    "Failed to lock output stream: {}",
+                    JsString::from(e)
+                ))
+            })?);
+            // Converting and calling into JavaScript for every write would dominate small entries
+            let mut throttle = ProgressThrottle::new();
+            let report = |progress: &RebuildProgress| {
+                if throttle.should_report(progress)
+                    && let Ok(progress) = progress.into_js()
+                {
+                    // Errors thrown by the callback must not abort the rebuild
+                    let _ = on_progress.call1(&JsValue::NULL, &progress);
                 }
-            })
-            .collect::<Result<js_sys::Array, JsValue>>()?;
-        let blob_property_bag = BlobPropertyBag::new();
-        blob_property_bag.set_type("application/zip");
-        let rebuilt_blob = Blob::new_with_buffer_source_sequence_and_options(
-            &rebuilt_blob_parts,
-            &blob_property_bag,
-        )
-        .map_err(|e| {
-            JsValue::from_str(&format!(
-                "Failed to create rebuilt blob: {}",
-                JsString::from(e)
-            ))
-        })?;
-        Ok(rebuilt_blob)
+            };
+
+            match rebuild_to(&mut reader, &mut writer, &chunks, report, &cancellation).await {
+                Ok(written) => {
+                    JsFuture::from(writer.0.close()).await.map_err(|e| {
+                        JsValue::from_str(&format!(
+                            "Failed to close output stream: {}",
+                            JsString::from(e)
+                        ))
+                    })?;
+                    Ok(JsValue::from(written as f64))
+                }
+                Err(e) => {
+                    let message = match e {
+                        RebuildError::Cancelled => e.to_string(),
+                        e => format!("Failed to write zip archive: {e}"),
+                    };
+                    let reason = JsValue::from_str(&message);
+                    let _ = JsFuture::from(writer.0.abort_with_reason(&reason)).await;
+                    Err(reason)
+                }
+            }
+        }))
+    }
+}
+
+/// Cancellation token for [`ZipProcessor::rebuild`]
+#[wasm_bindgen]
+#[derive(Debug, Default)]
+pub struct RebuildCancellation(CancellationToken);
+
+#[wasm_bindgen]
+impl RebuildCancellation {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.cancel();
+    }
+}
+
+/// WASM writer that appends data to a JavaScript WritableStream
+#[derive(Debug)]
+pub struct JsStreamWriter(WritableStreamDefaultWriter);
+
+impl ZipWriter for JsStreamWriter {
+    async fn write(&mut self, data: &[u8]) -> Result<(), io::Error> {
+        // The chunk is copied out of the WASM memory, which may be reused before the stream consumes it
+        let chunk = Uint8Array::from(data);
+        JsFuture::from(self.0.write_with_chunk(&chunk))
+            .await
+            .map_err(|e| {
+                io::Error::other(format!(
+                    "Failed to write {} bytes: {}",
+                    data.len(),
+                    JsString::from(e)
+                ))
+            })?;
+        Ok(())
     }
 }
 
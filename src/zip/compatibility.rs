@@ -216,6 +216,7 @@ mod tests {
             file_comment: vec![],
             zip64: None,
             unicode_path: unicode_path.clone(),
+            unicode_comment: None,
         };
         let lfh = LocalFileHeader {
             signature: 0x04034b50,
@@ -247,6 +248,7 @@ mod tests {
     fn create_mock_zip(entries: Vec<ZipFileEntry>) -> ZipFile {
         ZipFile {
             size: 0,
+            prefix_length: 0,
             eocd: EndOfCentralDirectory {
                 signature: 0x06054b50,
                 disk_number: 0,
@@ -0,0 +1,1297 @@
+//! Character tables to tell apart CJK encodings that decode the same bytes without errors
+//!
+//! Each national character set splits its characters into a first level of commonly used ones and a second
+//! level of rare ones. Decoding with the wrong encoding yields valid but mostly rare characters, so the share
+//! of first level characters tells which decode is plausible even for names too short for statistics.
+//!
+//! The tables are bitsets over the CJK Unified Ideographs and Hangul Syllables blocks.
+
+use encoding_rs::{BIG5, EUC_JP, EUC_KR, Encoding, GB18030, GBK, ISO_2022_JP, SHIFT_JIS};
+
+/// Language whose common characters are tabulated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Language {
+    Japanese,
+    SimplifiedChinese,
+    TraditionalChinese,
+    Korean,
+}
+
+/// First code point of the CJK Unified Ideographs block
+const HAN_START: u32 = 0x4E00;
+/// First code point of the Hangul Syllables block
+const HANGUL_START: u32 = 0xAC00;
+
+impl Language {
+    /// Get the language written with the encoding, None for encodings without tables
+    pub fn for_encoding(encoding: &'static Encoding) -> Option<Self> {
+        if encoding == SHIFT_JIS || encoding == EUC_JP || encoding == ISO_2022_JP {
+            Some(Language::Japanese)
+        } else if encoding == GBK || encoding == GB18030 {
+            Some(Language::SimplifiedChinese)
+        } else if encoding == BIG5 {
+            Some(Language::TraditionalChinese)
+        } else if encoding == EUC_KR {
+            Some(Language::Korean)
+        } else {
+            None
+        }
+    }
+
+    /// Score the share of Han, Kana and Hangul characters in the string that are common in the language
+    ///
+    /// Returns None if the string contains none of them.
+    pub fn score(self, string: &str) -> Option<f64> {
+        let mut total = 0;
+        let mut common = 0;
+        for c in string.chars() {
+            let is_common = match c {
+                '\u{4E00}'..='\u{9FFF}' => match self {
+                    Language::Japanese => contains(&JAPANESE_HAN, HAN_START, c),
+                    Language::SimplifiedChinese => contains(&SIMPLIFIED_CHINESE_HAN, HAN_START, c),
+                    Language::TraditionalChinese => {
+                        contains(&TRADITIONAL_CHINESE_HAN, HAN_START, c)
+                    }
+                    // Hanja are rarely used in names
+                    Language::Korean => false,
+                },
+                // Hiragana, Katakana and Halfwidth Katakana
+                '\u{3041}'..='\u{30FF}' | '\u{FF66}'..='\u{FF9F}' => self == Language::Japanese,
+                '\u{AC00}'..='\u{D7A3}' => {
+                    self == Language::Korean && contains(&KOREAN_HANGUL, HANGUL_START, c)
+                }
+                _ => continue,
+            };
+            total += 1;
+            if is_common {
+                common += 1;
+            }
+        }
+        (total > 0).then(|| common as f64 / total as f64)
+    }
+}
+
+/// Whether the character is set in the bitset starting at the code point
+fn contains(table: &[u64], start: u32, c: char) -> bool {
+    let index = (c as u32 - start) as usize;
+    table
+        .get(index / 64)
+        .is_some_and(|word| word & (1 << (index % 64)) != 0)
+}
+
+/// Kanji of JIS X 0208 level 1, 2965 characters
+static JAPANESE_HAN: [u64; 328] = [
+    0x0b04204243526f8b,
+    0x400a0000e280e828,
+    0x040079721b361b41,
+    0x0845403803708c83,
+    0x355080002403e402,
+    0x90280000122be048,
+    0x8060e00328002808,
+    0x0528400a2080041c,
+    0x0240285882442a00,
+    0x2074002010008200,
+    0x40a0300003022000,
+    0x080000800422a020,
+    0x0004040080040011,
+    0x11e2392014016bfa,
+    0x00d0112102842460,
+    0x274204c220003850,
+    0x0dc10230208205c9,
+    0x0025803808402488,
+    0x42120e0988000288,
+    0xc4040094a32002a8,
+    0x8e00040322c00026,
+    0x813b8041159e058a,
+    0x0808230085000010,
+    0x01cf9e3e0ad07f04,
+    0x4b0008418803ff18,
+    0x3008050000020744,
+    0x200c000000001800,
+    0x0004030200000203,
+    0x40028000004100d0,
+    0x0000000000088050,
+    0x00411c8034000a10,
+    0x0000000800000000,
+    0x0002020001800240,
+    0x0510010008001004,
+    0x0000000400000080,
+    0x240d00094c000000,
+    0x0001218080048008,
+    0x0000045000030484,
+    0x0000000c00000804,
+    0x1690000190004800,
+    0x0433041000200065,
+    0x40200a0047920403,
+    0x4008010010880008,
+    0x0087580000201482,
+    0x00824e8416608200,
+    0x2018452000928390,
+    0x4a0011200248041c,
+    0x88400c60001b0a00,
+    0x100082010100000a,
+    0x8000004004000042,
+    0x0000000008040000,
+    0x0000000200001202,
+    0x0001100400000200,
+    0x00000858b1910000,
+    0x8279403cbfa0bba0,
+    0xc5204282a80c1074,
+    0xfc0220100442ce56,
+    0x0002803340222d21,
+    0x010a130200010000,
+    0x0841810300000000,
+    0x0000020000404080,
+    0x0000820000010000,
+    0x0400000000000800,
+    0x689a41ea60001000,
+    0x2109a8202040104c,
+    0x7b1c000a00201020,
+    0x01e028c014e0849a,
+    0x9cc0000180080608,
+    0x50a200e089b98412,
+    0x12031e4400080400,
+    0x22184602008d1833,
+    0x2020080113803028,
+    0x000085a130440000,
+    0x0021a32400250800,
+    0x1044064980101200,
+    0x02090108940200a0,
+    0x000000008c008302,
+    0x4041418c00205900,
+    0x4044029000014004,
+    0x0104000000010080,
+    0x8910804084474400,
+    0x8242400001282a81,
+    0x3222080051a20411,
+    0x40c830032b0d2020,
+    0xa400890082020282,
+    0x0c84418010a01200,
+    0x081417a709041108,
+    0x041040020c418008,
+    0x4400300000002000,
+    0x0500020001000004,
+    0x0205681044040008,
+    0x4000104400002002,
+    0xca00800000000000,
+    0x00b1104c02828020,
+    0x3201b0b212835280,
+    0x040033e400808820,
+    0x1000a1a18018d0c4,
+    0x0450c2400004080c,
+    0x0010484400c20082,
+    0xe31c000032000080,
+    0x24123d00a8b02b01,
+    0xc0a2a026904bc200,
+    0x0040800534a10080,
+    0xc83a0000051b8412,
+    0x3310040600c8001c,
+    0x00400080b01b010e,
+    0x1043818400880022,
+    0x0404400084040a10,
+    0x801000001a006821,
+    0x3028a00504280400,
+    0x0000000008104404,
+    0x2800000003003800,
+    0x26200e0282800800,
+    0x8000000281000800,
+    0x0000000000004001,
+    0x0000010008080000,
+    0x6404008b20000010,
+    0x0818865c00085000,
+    0x8c30000000400e40,
+    0x0000000009146020,
+    0x4190000000828000,
+    0x24050001a4814007,
+    0x9b08080602481108,
+    0x0009012e00201602,
+    0x4804062048800800,
+    0x0190564010000032,
+    0x100480001a001100,
+    0x08aa080201020801,
+    0x000092630c080ba0,
+    0xc000808009400400,
+    0x0440000430411001,
+    0x0010000060020820,
+    0x0100180d00308246,
+    0x0001401090100020,
+    0x0002000000800010,
+    0x000088030000000b,
+    0x000010c040200000,
+    0x3101880001000000,
+    0x0600200000004600,
+    0x0200000000008100,
+    0x1040004204100000,
+    0x2000429002004200,
+    0x0002000080100400,
+    0x0000206000210108,
+    0x6460040000000040,
+    0x22040286aa041180,
+    0x0040900100000001,
+    0x310032000a810004,
+    0x80c04c0088000000,
+    0x0004000800000030,
+    0x0004020000400a90,
+    0x4000240100002404,
+    0x0078000400000248,
+    0x000800014c000000,
+    0x2001000000000008,
+    0x0040004410000000,
+    0x0c8f092895020000,
+    0x8089046532129000,
+    0x420408000002c800,
+    0x00100204093000a0,
+    0x0000000000000000,
+    0x6c00000000441004,
+    0x80004000000100d0,
+    0x4114401888800548,
+    0x1400000180001a02,
+    0x0000004a00000001,
+    0x0008302000000000,
+    0x0008a2a408000000,
+    0x841400e000300004,
+    0x0004980020000000,
+    0x0400028000aa2082,
+    0x0000810000010002,
+    0x5400000000004002,
+    0x0080212460410382,
+    0xe00100400000e032,
+    0x0801025081060803,
+    0xb004400014904801,
+    0x845008080001e045,
+    0x0400c400800c001a,
+    0x8640842910000808,
+    0x0200106108020100,
+    0x0000000000568b40,
+    0x0102240200b000c0,
+    0x0000201100000291,
+    0xc100000200000000,
+    0x4008000000002000,
+    0x400000a089a42a06,
+    0x49000081c0404400,
+    0x060998070f912831,
+    0x026200464001101c,
+    0xc816300016000000,
+    0x0010930104068c00,
+    0x4000484048000012,
+    0x0001200000302c02,
+    0x0000000000008004,
+    0x0000000000000000,
+    0x0040000000000000,
+    0x00a54c0000000000,
+    0x2000031000004420,
+    0x1801080100041002,
+    0x2048000000a1102b,
+    0x4090800240400000,
+    0x0416862621401a80,
+    0x2110001240005048,
+    0x020a0000040005e4,
+    0x8701080000314000,
+    0x8008010034008000,
+    0x1018252800080040,
+    0x02e01400d9805100,
+    0x0044c04000000080,
+    0x230aa06022000800,
+    0x000208e0089a0020,
+    0x0140010010004034,
+    0x0880000001048600,
+    0x0002080040000000,
+    0x0003820090481420,
+    0x2002020000005010,
+    0x0422104a08804200,
+    0x1104000012110800,
+    0x0000500000020a10,
+    0x20202040040a0001,
+    0x0000070000804608,
+    0x0000de4002800010,
+    0x0880021000002002,
+    0x0000200000000080,
+    0x0a00080054014000,
+    0x0000001000200400,
+    0x4100601002006880,
+    0x0000000011000004,
+    0x8040004000200a00,
+    0x0400000000002000,
+    0x0a00000000000000,
+    0x0000000000000000,
+    0x8081010a28881041,
+    0x0090080000400900,
+    0x6108000290208026,
+    0x0000000000050080,
+    0x8004000080400000,
+    0x0008048004c088c2,
+    0x0000004800040000,
+    0x1c1a240881884505,
+    0x000f4a4940330000,
+    0x9205301141283280,
+    0x4500604010449880,
+    0x020010022a004017,
+    0x0085004011000000,
+    0x0000000000010008,
+    0x0000000000000000,
+    0x0080000000000000,
+    0x0200040204000440,
+    0x99919b8760001000,
+    0x10002445580a449d,
+    0x0000000000000900,
+    0x0091085000000000,
+    0x0008010800000420,
+    0x0028810220000000,
+    0x0000000000008400,
+    0x0010000080000010,
+    0x0000880000002000,
+    0x2100000480043400,
+    0x8100860020100208,
+    0x0000000280002010,
+    0x9c07000048000000,
+    0x20812a4000124034,
+    0x1106400da699804b,
+    0x95a0622b10386ca6,
+    0x0100044800020010,
+    0x20a0210200004402,
+    0x0000200000000000,
+    0x01a0140400147a00,
+    0x0100001010852080,
+    0x000000c83102f1c0,
+    0x0090000000006000,
+    0x4002004408000010,
+    0x0000200005020029,
+    0x110c241000040000,
+    0x0004994101010040,
+    0x4020100008102800,
+    0x0000408004c01000,
+    0x0000000200020000,
+    0x0100000003000000,
+    0x00000000000a0000,
+    0x0000000000000000,
+    0x0000000000000000,
+    0x0000000000000000,
+    0x0080000000000000,
+    0x0004003c000a8a09,
+    0x0000000001000080,
+    0x8804040010000000,
+    0x2569043c08012011,
+    0x188000091a10c560,
+    0x08c50d0c080210f3,
+    0x0004008050000481,
+    0x0010220442440000,
+    0x0000200101002010,
+    0x8808400000080000,
+    0x18103000058f016e,
+    0x0000008049307000,
+    0x0000010000000000,
+    0x7014800488000000,
+    0x0000010000091420,
+    0x0000000000800000,
+    0x0018110002400000,
+    0x8000000000250172,
+    0x00010100000c4000,
+    0x0000000004000000,
+    0x0100010000000000,
+    0x0000240001000010,
+    0x1000000000000000,
+    0x0000800004100026,
+    0x00006c0000044000,
+    0x0020010008400200,
+    0x0a00a00000012000,
+    0x0000000000840100,
+    0x0000000000000000,
+    0x0058022000000000,
+    0x0800190008004080,
+    0x0000100310000000,
+    0x0010000000008000,
+    0x0604000000000000,
+    0x0000000000000000,
+    0x8100000000000000,
+    0x8e00004080880000,
+    0x000000000a042010,
+    0x0800000100084000,
+    0x0000000400000000,
+    0x0000000000002000,
+    0x0000000000000000,
+];
+
+/// Hanzi of GB 2312 level 1, 3755 characters
+static SIMPLIFIED_CHINESE_HAN: [u64; 328] = [
+    0x6f152cb43f5a6f8b,
+    0x400b0043e35dfb28,
+    0x84047bf60c36db40,
+    0xa8c5143883fa6ce3,
+    0x15518451c783e402,
+    0x800992091a2be048,
+    0x8020c3e928802910,
+    0x0402e20261818418,
+    0x5400044287142000,
+    0x80c0002014008080,
+    0x0804110800002121,
+    0x0828008004008000,
+    0x8000000200040000,
+    0x3bfb392414122b7a,
+    0x08ed90111aa43321,
+    0xaf49a01328029a51,
+    0x2fc14b112f8604cb,
+    0x800486a00053240e,
+    0x800e0f0be8000100,
+    0xc400001081040a88,
+    0x8e00040b22c00161,
+    0x81bb889754eec78a,
+    0x8800034485201a74,
+    0x13cdfc790bd23f06,
+    0x5b32fbc1e8abf71a,
+    0x3928010419210541,
+    0x210a91000265d841,
+    0x14046760808263d3,
+    0x4002d010000102c2,
+    0x1100447200000458,
+    0x0888008031000695,
+    0x2000000a10080020,
+    0x8856000009004200,
+    0x0040150000004000,
+    0x108000080000c000,
+    0x641320154c000400,
+    0x4401210880000148,
+    0x00808453ec83e481,
+    0x0000484c0804081c,
+    0x060000018010480c,
+    0x0021041200220044,
+    0x0000080041121000,
+    0x00000300c20c0028,
+    0x0249581000200002,
+    0x0792ce809460a090,
+    0x2358002500d2cb90,
+    0x0a004120024c05d4,
+    0x88001120141b0840,
+    0x004202219100009a,
+    0x8050040004000240,
+    0x0008000004040000,
+    0x0000020200001206,
+    0x0000000000000000,
+    0x24210658b3b10000,
+    0xe2790c5f7f809baa,
+    0x9f000122e00d10f4,
+    0xf702009025528652,
+    0x820280234022cf27,
+    0x8108220008900006,
+    0x0801254202000000,
+    0x0000220000504080,
+    0x0000000000001000,
+    0x0000000000000000,
+    0x409e4be660002000,
+    0x210060c03d61116e,
+    0xb95c000400201024,
+    0x01c020c0d6b984d0,
+    0x04c0000000480600,
+    0x060280e189a8841d,
+    0x1a033654202c0000,
+    0x2080440201850d33,
+    0xa822088103803068,
+    0x080085a130744007,
+    0x9049bb1400252808,
+    0x1104914980102210,
+    0x0649ebc190220c20,
+    0x8000009084008302,
+    0x0081018400225100,
+    0x4000010000004800,
+    0x0000004000040504,
+    0x809844000547f500,
+    0xd249fac8091e6883,
+    0xb22219370dee0611,
+    0xf09ab3ec9b5d73f4,
+    0x24008d3bec424286,
+    0x08c0408ec021f264,
+    0x058807ad01459385,
+    0x2010260a0045a200,
+    0x2400340050198027,
+    0x03000280011005d0,
+    0x10217210402600a4,
+    0x0000004040046024,
+    0xca40880001040001,
+    0x0095104c6a429120,
+    0x2201a2b292821880,
+    0x04c233e500808822,
+    0x5000a1a18018d044,
+    0x4451c150042c1808,
+    0x0010400000c20084,
+    0xd21d000022000000,
+    0x2432bd00a8902b01,
+    0xc001a1239043c24d,
+    0x00c08c0c34a10212,
+    0x801a9021501f8010,
+    0x3380040200ca89a0,
+    0x00400328001b116c,
+    0x0009a1c400800062,
+    0x0422400105242a01,
+    0xa001200000046020,
+    0x1080000044100000,
+    0x0090010010100044,
+    0x2000000008010000,
+    0x0002040280000400,
+    0x0002000202000080,
+    0x0000100000000011,
+    0x0000000000000000,
+    0x4404008f00000000,
+    0x1a0004fc00001280,
+    0x80b0000000400e48,
+    0x000088020af4a822,
+    0x0111c1c4885a8000,
+    0x62050413e8a10287,
+    0xfb084cf28a00830e,
+    0x3829052e48201630,
+    0x7806042048840802,
+    0x01904640060a4ce2,
+    0x012487daa8205324,
+    0x0a8a581201421851,
+    0x0510a21b20912420,
+    0xc000000009402008,
+    0x0440840410010228,
+    0x00100372441a0882,
+    0x400a080100188044,
+    0x0000604051002000,
+    0x0008000210004110,
+    0x0000000000040001,
+    0x0000000000020000,
+    0xd161a80000001000,
+    0x3308f00002344600,
+    0x2280bad08b00010e,
+    0x0041004006200600,
+    0x2000409040005000,
+    0x0000100082108410,
+    0x0000002000204008,
+    0x23e2140000000040,
+    0x062402868a001180,
+    0x0240900000000403,
+    0x1103700342810114,
+    0xc0104e0018404000,
+    0x0000200000000000,
+    0x08058a0008400a90,
+    0x0000200100012400,
+    0x1078000400010268,
+    0x00410000c4000000,
+    0x0000000000000008,
+    0x00c0405c10000000,
+    0x09af2b2095000010,
+    0x0110046012109020,
+    0x7224c83286828040,
+    0x02110404493407a0,
+    0x01100e5080000002,
+    0x6900000800001000,
+    0x80024000004208d4,
+    0x5114c00089d7c564,
+    0x14c0830180000a02,
+    0x0200184a08000003,
+    0x080816a000044030,
+    0xe0086a2c08800000,
+    0x4191008004543006,
+    0x1080580020240021,
+    0x401b008081827200,
+    0x0480010000210c22,
+    0x5400000000000002,
+    0x01802120e0400102,
+    0x6a00000000008012,
+    0x290182d081062a4b,
+    0xb808000104806801,
+    0x84d0000000800040,
+    0x80000020b1c6201a,
+    0x8200a029c2400800,
+    0x1400114008120840,
+    0x4280000008578a00,
+    0x0002220200b02001,
+    0x00824002020800c0,
+    0x8000008000000080,
+    0x2808000000002000,
+    0x420a443081a40200,
+    0x0800800192600400,
+    0x0000888500000400,
+    0x0000400000000000,
+    0x0000000000000000,
+    0x0000000000000000,
+    0x0000000000000000,
+    0x0000000000000002,
+    0xa7ee9cd500000004,
+    0xb1b926ecf72ee5f8,
+    0x0510430125784271,
+    0x0004460404a30000,
+    0x2200001244105400,
+    0x1803000181101022,
+    0xa948040003a1302b,
+    0x0000040005101c04,
+    0xc404c6372d401ad8,
+    0x254980025440504b,
+    0x41028000044b8644,
+    0xce01402000330c40,
+    0x0008010039008401,
+    0x181824080000004d,
+    0x02e31400d0143061,
+    0x440d800001000080,
+    0x220ab06030042804,
+    0x02028860c892a080,
+    0x036019800000403e,
+    0x0080889a04150240,
+    0x2286080000004240,
+    0x0006020110009480,
+    0x200001b020007010,
+    0x0022180a08800000,
+    0x0104000012000804,
+    0x00001000a0420210,
+    0x30801802048a0000,
+    0x4010000000000600,
+    0x0000840008000010,
+    0x0800021000218200,
+    0x0100000000020000,
+    0x620208004403c000,
+    0x0000001010201407,
+    0x420140100a100cc1,
+    0x0000000211a40005,
+    0x0050000000804280,
+    0x0400000080002000,
+    0x0200000000000000,
+    0x0000000400200000,
+    0x0081192a02903021,
+    0x0002280010442812,
+    0x0318001002300026,
+    0x0000042000050004,
+    0x8000000080000010,
+    0x0000000000000042,
+    0x0000004800040376,
+    0x0000000000000001,
+    0x0200000000000000,
+    0x0000000000080600,
+    0x0000000000000000,
+    0x0000000000000000,
+    0x0000104000000000,
+    0xf74deb3e00000000,
+    0x6db6b8ee6ca23773,
+    0x0093335c14896d6a,
+    0x06000c0600001042,
+    0x0000000000001200,
+    0x0000000000000000,
+    0xd7b3fffee0000000,
+    0x00f1085f4d51dc1e,
+    0x4018000880000c22,
+    0x04288500a0021808,
+    0x0000060440009400,
+    0x8004304000000910,
+    0x0404980000000082,
+    0x0000000000000000,
+    0x0000000000000000,
+    0xa811d3c000000000,
+    0x62030b08daeb0768,
+    0x23812c04fb1341b6,
+    0x59004005ec95864f,
+    0x802060280088a086,
+    0x0a22c44c000a0001,
+    0x2110218220024402,
+    0x0000000002000004,
+    0x81e2542864843a00,
+    0x000000000c040a80,
+    0x000000001002f680,
+    0x0000000000000000,
+    0x0010000000000000,
+    0x0000000000000000,
+    0x0000000000000000,
+    0x0000000000000000,
+    0x0000000000000000,
+    0x0000000000000000,
+    0x0000000000000000,
+    0x0000000000000000,
+    0x0000000000000000,
+    0x482a43e7e20c4300,
+    0x4547300a3000006f,
+    0x0a01e17a06831933,
+    0x8041200810032483,
+    0x0000000000000000,
+    0x2f95e50000000000,
+    0x887c400090134227,
+    0x00e00716082139f1,
+    0x4040008013118860,
+    0x42c005000020b0f3,
+    0x0304000050482291,
+    0x005802074a440000,
+    0x0000200101002800,
+    0x006822c000000000,
+    0x0000000000000000,
+    0xeee0000000000000,
+    0x0000009131cb05f6,
+    0x00000000c1004000,
+    0x0000000000010000,
+    0x9467e02000000000,
+    0x0000000002448962,
+    0x0000000000000000,
+    0x0000000000000000,
+    0x5a5bf00000000000,
+    0x01000112048298d4,
+    0x0000000001080000,
+    0x1000000000000008,
+    0x0000000000108016,
+    0x0000000000000000,
+    0x0000000000000000,
+    0x0000000000000000,
+    0x1000000000000000,
+    0x0100001010002002,
+    0x0000000040400008,
+    0x0000000000000000,
+    0x0000000000000000,
+    0x0000000000000000,
+    0x0000000000000000,
+    0xa028a06a80000000,
+    0x8001001000008428,
+    0x0800004000080000,
+    0x0000000001126010,
+    0x0800000100084000,
+    0x8000000000010000,
+    0x0000000086000810,
+    0x0000000000000000,
+];
+
+/// Frequently used hanzi of Big5 (0xA440 to 0xC67E), 5401 characters
+static TRADITIONAL_CHINESE_HAN: [u64; 328] = [
+    0x0b052840c3732f0b,
+    0x40080200e34ce928,
+    0x04087976ca365944,
+    0xa84d0038c3f02cdb,
+    0x355180000223ae02,
+    0xcc2992097eb3e1c8,
+    0x80e0c0002842a948,
+    0x450ac003e583c41c,
+    0x10002f6a8756ba41,
+    0x24f0a0201420d288,
+    0x48acb00407422021,
+    0x0a2262a00462c0a0,
+    0x998c040281350335,
+    0x11e27ba410b43bfb,
+    0x20c1143500a62601,
+    0x274b000330003840,
+    0x0dc1473020c645cb,
+    0xa0b4860038507c8a,
+    0x48001e09880a278c,
+    0xcd29005ceba4028a,
+    0xc202840b22e1a160,
+    0x8babc043549e07ab,
+    0x08042020a4000010,
+    0x192fff7c88d02f08,
+    0x5b6a7ac1e807ff5a,
+    0x39ea01040000454d,
+    0xa90a9fc004655851,
+    0x3404676200004397,
+    0x418ad080001182c2,
+    0x5800045ad0e0845a,
+    0x080e5c80b7100e9d,
+    0x60c0817a940a42e0,
+    0x8a55c20088d16341,
+    0x03529f3402884000,
+    0x10d041200021c0a1,
+    0x84010c116c021707,
+    0x4809b300814c2b18,
+    0x108006520003e481,
+    0x0000400d0008080c,
+    0x068000118010480c,
+    0x0033841320000664,
+    0x6220280445961c00,
+    0x40084340d0080429,
+    0x26009a14c32a40a2,
+    0x41826eb516708000,
+    0x2358668505b3c390,
+    0x4a08c138624c241e,
+    0x88001568151f2c60,
+    0x10400229990a120a,
+    0x8080044404000643,
+    0x700c00800c040000,
+    0x0820024a01c01206,
+    0x0111041020001b00,
+    0xa52b0a18bdbb0009,
+    0x8379407c1f809ba8,
+    0x05615bf6c00d10f4,
+    0xd78210140442ef92,
+    0x020291353123e963,
+    0x018a0a0200131000,
+    0x2851250002040000,
+    0x001002940ad240c2,
+    0x0000820000011080,
+    0x3500000020100090,
+    0x609e49e660542200,
+    0x21c82820ad62104c,
+    0x7b5c080e88b01029,
+    0x41e028c114e08480,
+    0xc4c8100da4480716,
+    0x10a222c089bc8d12,
+    0x92833e3422805540,
+    0x22a0570353871fa3,
+    0xa1220c0123c03028,
+    0x00208fa32874c000,
+    0x8001bf3c02212048,
+    0x3974010984503230,
+    0x0e5b0043952208e0,
+    0xc09000889c24e343,
+    0x50e1498ca5207990,
+    0x40445a9004134404,
+    0xd5e4004800002f84,
+    0xc05d44068c677501,
+    0xd248e008091e6b83,
+    0xb222190001ee0651,
+    0xf09af0001fdd7bd4,
+    0x64008d0000420386,
+    0x0cc60b800021f244,
+    0x000017af0b449319,
+    0x0210660a0c45a241,
+    0x6050340050d8a040,
+    0x0f00a28081160000,
+    0xd80d7a34462d0180,
+    0x4c97304505146cb6,
+    0xcb409c1833108041,
+    0x01b4104c63f28320,
+    0x328198b29aa3008c,
+    0x0402336400c0d822,
+    0x0002a1b18038d4c5,
+    0x44d1c1506404922e,
+    0x0312490421c22390,
+    0xd39d1241324002d0,
+    0x20327dc0a8b02b09,
+    0xd0afa401d0cbc240,
+    0x0151800034a90a80,
+    0x803a9224661f8010,
+    0x3300060201880000,
+    0x80400000001b116f,
+    0x002ba186a098006e,
+    0x0e24408985a42a10,
+    0x0001000000046020,
+    0x02a069096c180400,
+    0x0890694818300044,
+    0x200821000009380a,
+    0x2720000ac20a0426,
+    0x4000080683000990,
+    0x0908d00d10910011,
+    0x0c0010202c08000c,
+    0x6404000b00410410,
+    0x8b8404fe92015280,
+    0x94a4c01001400648,
+    0x200098000c9ca861,
+    0x4118d9d8884b8120,
+    0x64550503eaa16007,
+    0xfb0a58000b55336e,
+    0x002b812f18e81632,
+    0x6b664ea048805800,
+    0x0390566006000002,
+    0x9124c400003857a0,
+    0x0eaa5d52470618f0,
+    0x4514b07b2e000020,
+    0x0004c28889506418,
+    0x14508ca431410200,
+    0xc0949000003a18b4,
+    0xc10e9e0d00388046,
+    0x1501e05189120000,
+    0x025a00d300804151,
+    0x4403da138920000f,
+    0x40050100ad228a40,
+    0x3141081841021000,
+    0x0508f81002244400,
+    0x2200801003008500,
+    0x0041004216000600,
+    0x200053f052405240,
+    0x4202110082140410,
+    0x591070e192254318,
+    0x6f61350008012040,
+    0x22148287ab4411c0,
+    0x0000908408440013,
+    0x3380020302814015,
+    0xc0d002201a480000,
+    0x0da5010000814030,
+    0x088c0b2080400ad1,
+    0x4000640140002401,
+    0x8478856408002668,
+    0x4001a618de200002,
+    0xa00103c8d1000809,
+    0x00c0005d14508400,
+    0x218f3b6495203010,
+    0x81410c643610d800,
+    0x732c083c26028cc0,
+    0x841f000d4b3024a4,
+    0x051002d780082a03,
+    0x7906522681441044,
+    0x84114000044849d4,
+    0x5314c4bad814656c,
+    0x55c0030180000a82,
+    0xa2001d6e58008000,
+    0xb80934a1c0025890,
+    0x2008e22c1c880080,
+    0x019520a1a0103004,
+    0x9000980020004008,
+    0x54090288818a5200,
+    0x0490918004210c22,
+    0x44001c4002004002,
+    0x84002121e4e103c3,
+    0xe60a44c00000e002,
+    0x080002d881060b03,
+    0xb8824001d4102921,
+    0x86500a0700807841,
+    0x8400c60181e40112,
+    0xa640a02982000cb0,
+    0x000051414a020040,
+    0x00a040810057aa40,
+    0x0112200320b08890,
+    0x408002530a0012d3,
+    0xe1800c8a40023080,
+    0x200c503980013009,
+    0x622a002080a40200,
+    0x49018085f2600400,
+    0x120fc8071eb12ef5,
+    0x020f41464821005d,
+    0xed373005500a8002,
+    0x0a14930967040480,
+    0xe2b6c80868030700,
+    0x32082e024634016a,
+    0x0000000010589004,
+    0x0000000000000000,
+    0x2540000000000000,
+    0x02a5460080311110,
+    0xb344831044105d20,
+    0x1a0b420380345022,
+    0xa048014013a7387b,
+    0xe0cf800045440440,
+    0x04168e262d481af0,
+    0x311ba0326c405018,
+    0x42018a1800400564,
+    0x871b4860003b5840,
+    0xe40803013d008505,
+    0x5c1835ac8500a24d,
+    0x02e01d04d81537a5,
+    0x4086c04003000880,
+    0x6b8bb028a2042800,
+    0x000280e8d89e0000,
+    0x037609000080027e,
+    0x1980000000158609,
+    0x20000885414c4640,
+    0x2117820190101422,
+    0x3122100000007c98,
+    0x00a03a4a08800240,
+    0x9104000052010004,
+    0x80005000000a0e51,
+    0x2000302a049a0040,
+    0x4000270042000708,
+    0x0601874018120490,
+    0x0e8006302021a200,
+    0x8100200004820cc0,
+    0x0e020880d8314000,
+    0x0062861810081400,
+    0x4a002010ca110ad1,
+    0x88900224110805cd,
+    0x0150014102a81000,
+    0x0c080804c000200c,
+    0x4a04900100060941,
+    0x0a42000e80302020,
+    0x2281090e2a883001,
+    0x0002080040442906,
+    0x23188102b3340406,
+    0x29440c22042d0484,
+    0x80049411c0400010,
+    0x240414401048884a,
+    0x1110000800140001,
+    0x5c52242ca9ebd50d,
+    0x1a0f7b4d48710042,
+    0x924505fb452a30a0,
+    0x55ca68c018442a94,
+    0x020019012a814417,
+    0x209198409700c242,
+    0x00000000144c0401,
+    0x0000000000000000,
+    0x8080000000000000,
+    0x06083c0604315342,
+    0xb9dc9f8760083604,
+    0x3d10345f500e05bf,
+    0x0000000008c8a520,
+    0x009928d000000000,
+    0x4418010882200422,
+    0x14088552ac025840,
+    0x02320a06e0018000,
+    0x1454105882200f14,
+    0x04041c02000a2ea6,
+    0x590000008814bc01,
+    0xc9088660f83c0608,
+    0x0000000280106234,
+    0x0007e14898000000,
+    0x25912d7400124074,
+    0x5166400deef1a06b,
+    0xb5b4630d7b3bec86,
+    0x0162044c00238d9f,
+    0x602021062000440a,
+    0x0213208043840204,
+    0x01b0162800843f00,
+    0x0412580850040a88,
+    0x002022c83383fae3,
+    0xe390000840332280,
+    0x190000100c822a21,
+    0x188c1100114a0021,
+    0x11010410000400a2,
+    0x0048c94707210010,
+    0x402110200c102c00,
+    0x0009500405d00404,
+    0x20000116b1c22108,
+    0x052c480001000008,
+    0xf085041900060010,
+    0x0000000000000000,
+    0x0000000000000000,
+    0x0000000000000000,
+    0x0080000000000000,
+    0x0802233a011eca0d,
+    0x0000000610711d40,
+    0x8806540210000000,
+    0x21fd141e69013815,
+    0x0b1205801b306c70,
+    0x0ac4870c5a643ef3,
+    0x63050490504ae2d1,
+    0x1050034758440114,
+    0x000020490100a820,
+    0x8a48200010081a4a,
+    0x0b812812118f156e,
+    0x000388a04908f040,
+    0x144a810000000000,
+    0x7014a68488000010,
+    0xe02041024d0d1628,
+    0x0000000050a60220,
+    0x001a712002400000,
+    0x80022004aa270002,
+    0x40a1080002404002,
+    0x000004248ca02029,
+    0x1181810000000000,
+    0x0800e042015e8002,
+    0x100601ac04002048,
+    0x008080000512e036,
+    0x00006c0000020000,
+    0x2000018008400701,
+    0x4802202000082000,
+    0x0180000080d00200,
+    0x0000000000000000,
+    0x0058022000000000,
+    0x8800014808240248,
+    0x0004140320020000,
+    0x0110800000000200,
+    0x0600002000880004,
+    0x000000004c000000,
+    0xb220000000000000,
+    0x58300220a08c0804,
+    0x009081017912f008,
+    0x4810900102284080,
+    0x008414ca90040c00,
+    0x0000000010112000,
+    0x0000000000000000,
+];
+
+/// Hangul syllables of KS X 1001, 2350 characters
+static KOREAN_HANGUL: [u64; 175] = [
+    0x1303b0113eff0793,
+    0x0593000011102801,
+    0x3b019703b0111e7b,
+    0x306b959300a01112,
+    0x113032011102b051,
+    0xb879300a011102b0,
+    0x0080001030011306,
+    0x93000011100b0113,
+    0x0593000000102b03,
+    0x3b011323b051746b,
+    0x7000000000001030,
+    0x111029001303b011,
+    0xb015300000012180,
+    0x020000303001030e,
+    0x1300000010230111,
+    0x0113030010106b81,
+    0x0000010030111013,
+    0x3000000022b85530,
+    0x113afb079702b011,
+    0x00000021011303b0,
+    0x03b011383b0d1b00,
+    0x1300000111330113,
+    0x00000100111c2b05,
+    0x2a011300b0111000,
+    0x1010000102b01930,
+    0x1030030111000000,
+    0x0011146b07130230,
+    0x8fb8f9742b051300,
+    0x00000000103b0113,
+    0x01134ab0d9700000,
+    0x000011030011103b,
+    0x100001112ab15930,
+    0x00100b0111010000,
+    0x0000102b01130000,
+    0x02a0111020000101,
+    0x0102b05930210111,
+    0x011307b019300000,
+    0x00000003b011383b,
+    0x383b0d1300000000,
+    0x000010000103b011,
+    0x0010102001130000,
+    0x0000011000000100,
+    0x0002181130000000,
+    0x0111000000100000,
+    0x0b01930000000023,
+    0x302b011100301110,
+    0x01303b0113c7b011,
+    0xb011300000000280,
+    0x03b011302b011383,
+    0x1102b011300a0011,
+    0x0111010000002000,
+    0x2b011302a011102b,
+    0x3000000101000010,
+    0x11302b0113029011,
+    0xb0113000000066b0,
+    0x07b0113a6b07d302,
+    0x1300000000200103,
+    0x011303b011386b05,
+    0x2b051b00000010b8,
+    0x1000000003000110,
+    0x79700a011102a011,
+    0x0000100a0111a2b0,
+    0x0090111000011100,
+    0x9300000000090111,
+    0x011322b0f9f2bb05,
+    0x000000002001323b,
+    0x303b019306b05930,
+    0x117000001123a011,
+    0x00001010001102b0,
+    0x0000011003011301,
+    0x01010010162b0793,
+    0x0111020011300000,
+    0x00000000b0113029,
+    0x383b05130eb05130,
+    0x000001000303b011,
+    0x0000103901930000,
+    0x000000003b000302,
+    0x0000000000230113,
+    0x0001000000100000,
+    0x0000000290113020,
+    0x1000000000000000,
+    0x0000030111020000,
+    0xb079b02b01130000,
+    0x02b011303b011323,
+    0x1343b0d9f0210111,
+    0x011103b011303b01,
+    0x20011322b0517020,
+    0x300b011101901110,
+    0x0016ab019302b011,
+    0xb011302101130100,
+    0x02b0313029010302,
+    0x1b42b81930000000,
+    0x0000033011383301,
+    0x3305130000000020,
+    0x0000000000001110,
+    0x0130230593000001,
+    0x3011101000010100,
+    0x0230113000000100,
+    0x1100000010100001,
+    0x8513020000000000,
+    0x2b01130010111003,
+    0x303b011363b87730,
+    0x7b30020111a2b091,
+    0xf0d1702b011357f0,
+    0x0ab971301b0111e3,
+    0x13029001303b0113,
+    0x071302b011302b01,
+    0x230113033011302b,
+    0x30ab011302b01130,
+    0x7130090111feb411,
+    0xb011307b05d347b8,
+    0x0000111021015303,
+    0x1102b011306b0513,
+    0x0513000000103301,
+    0x30000102a01038eb,
+    0x3020001302b01110,
+    0x001010000102b071,
+    0x1011100b01130000,
+    0x000000002b011300,
+    0x1303b095366b0593,
+    0x0000020001103b01,
+    0x20000103b0113000,
+    0x3000000001000010,
+    0x00101001030ab011,
+    0x0000000301110100,
+    0x0300001023011302,
+    0x0100000010000000,
+    0x0000029000100000,
+    0x7b01538630113000,
+    0x0021015103b01130,
+    0x11303b0113000000,
+    0x00011010001102b0,
+    0x020011102b011302,
+    0x0102b01110000000,
+    0x000102b011300100,
+    0x2b01110000011010,
+    0x002b011302101110,
+    0x11302b0393000000,
+    0x0000303b011302b0,
+    0x03b0193000000002,
+    0x0103b011102b0113,
+    0x011302b011300000,
+    0x0001010200001021,
+    0x102b011300000010,
+    0x1130200001020011,
+    0x30113001011102b0,
+    0x02b0113000000002,
+    0x0103b011303b0313,
+    0x0513000000002000,
+    0x10001102b011303b,
+    0x142b011300000110,
+    0x0110000001000001,
+    0xb011300000010280,
+    0x0000001010000102,
+    0x9302101110230113,
+    0x0113003011100b05,
+    0x3b011323b051702b,
+    0x3000000000000030,
+    0x11102b011303b011,
+    0xb011300a01010330,
+    0x0000000020000102,
+    0x9300a01110000011,
+    0x0000020000102b05,
+    0x2901110090111000,
+    0x3000000000b01110,
+    0x11302b211302b011,
+    0x00000020000103b0,
+    0x02b011302b051300,
+    0x13002011103b0113,
+    0x0013028011322b21,
+    0x0a011102a0113028,
+    0x3021011102921130,
+    0x11302b0113020011,
+    0x3011122b03d30290,
+    0x000000002b011302,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_for_encoding() {
+        assert_eq!(Language::for_encoding(SHIFT_JIS), Some(Language::Japanese));
+        assert_eq!(
+            Language::for_encoding(GBK),
+            Some(Language::SimplifiedChinese)
+        );
+        assert_eq!(
+            Language::for_encoding(BIG5),
+            Some(Language::TraditionalChinese)
+        );
+        assert_eq!(Language::for_encoding(EUC_KR), Some(Language::Korean));
+        assert_eq!(Language::for_encoding(encoding_rs::WINDOWS_1252), None);
+    }
+
+    #[test]
+    fn test_score() {
+        // "資料" in Shift_JIS decodes to rare characters in GBK and EUC-KR
+        let bytes = b"\x8e\x91\x97\xbf";
+        let score = |encoding: &'static Encoding| {
+            let (string, _, has_errors) = encoding.decode(bytes);
+            assert!(!has_errors);
+            Language::for_encoding(encoding).unwrap().score(&string)
+        };
+        assert_eq!(score(SHIFT_JIS), Some(1.0));
+        assert!(score(GBK).unwrap() < 0.5);
+        assert!(score(EUC_KR).unwrap() < 0.5);
+
+        assert_eq!(Language::Japanese.score("テスト.txt"), Some(1.0));
+        assert_eq!(Language::SimplifiedChinese.score("テスト.txt"), Some(0.0));
+        assert_eq!(Language::SimplifiedChinese.score("报告.txt"), Some(1.0));
+        assert_eq!(Language::TraditionalChinese.score("報告.txt"), Some(1.0));
+        assert_eq!(Language::Korean.score("보고서.hwp"), Some(1.0));
+        assert_eq!(Language::Korean.score("報告.txt"), Some(0.0));
+        assert_eq!(Language::Japanese.score("readme.txt"), None);
+    }
+}
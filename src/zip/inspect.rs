@@ -1,11 +1,21 @@
 use chardetng::EncodingDetector;
-use encoding_rs::{Encoding, UTF_8};
+use encoding_rs::{
+    BIG5, DecoderResult, EUC_JP, EUC_KR, Encoding, GBK, SHIFT_JIS, UTF_8, WINDOWS_874,
+    WINDOWS_1250, WINDOWS_1251, WINDOWS_1252, WINDOWS_1253, WINDOWS_1254, WINDOWS_1255,
+    WINDOWS_1256,
+};
 use serde::{Deserialize, Serialize};
+use unicode_normalization::UnicodeNormalization as _;
 
-use super::parse::ZipFile;
+use super::codepage::{CODE_PAGES, CodePage, IBM437, IBM850, IBM852, IBM857, IBM862, IBM865};
+#[cfg(feature = "dictionary")]
+use super::dictionary::Language;
+use super::parse::{UnicodePathExtraField, ZipFile, ZipFileEntry};
+use super::substitution::{CharacterMapping, SubstitutionPreset, SubstitutionTable};
+use super::timestamp::EntryTimestamps;
 
 /// Configuration for inspecting ZIP archives
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 #[cfg_attr(
     all(target_arch = "wasm32", target_os = "unknown"),
     derive(tsify::Tsify)
@@ -17,8 +27,16 @@ use super::parse::ZipFile;
 pub struct InspectConfig {
     /// Encoding selection strategy for filename decoding
     pub encoding: EncodingSelectionStrategy,
+    /// Encodings to use for specific entries instead of the one selected by the strategy
+    ///
+    /// The first matching override wins. They also apply to the comments of the matched entries.
+    #[serde(default)]
+    pub encoding_overrides: Vec<EncodingOverride>,
     /// Field selection strategy for filename decoding
     pub field_selection_strategy: FieldSelectionStrategy,
+    /// Field selection strategy for comment decoding
+    #[serde(default)]
+    pub comment_field_selection_strategy: CommentFieldSelectionStrategy,
     /// Whether to ignore CRC32 mismatches in UTF-8 extra fields
     ///
     /// Should be true only when the archive is known to be created by a broken implementation
@@ -31,6 +49,24 @@ pub struct InspectConfig {
     /// How to normalize Wave Dash (U+301C) and Fullwidth Tilde (U+FF5E)
     #[serde(default)]
     pub wave_dash_normalization: WaveDashNormalization,
+    /// Character substitution tables applied to decoded strings, after the Wave Dash options
+    #[serde(default)]
+    pub substitution_tables: Vec<SubstitutionTable>,
+    /// Unicode normalization form applied to decoded filenames
+    #[serde(default)]
+    pub unicode_normalization: UnicodeNormalization,
+    /// How to handle backslashes in decoded filenames
+    #[serde(default)]
+    pub backslash_handling: BackslashHandling,
+    /// Number of ranked candidate encodings to report, 0 to disable ranking
+    #[serde(default)]
+    pub candidate_count: usize,
+    /// Whether to also report ranked candidate encodings for each filename
+    #[serde(default)]
+    pub entry_candidates: bool,
+    /// Whether to decode every filename source of each entry and report disagreements between them
+    #[serde(default)]
+    pub compare_filename_sources: bool,
 }
 
 /// Strategy for handling Wave Dash when decoding from Shift_JIS
@@ -66,6 +102,57 @@ pub enum WaveDashNormalization {
     NormalizeToWaveDash,
 }
 
+/// Unicode normalization form for decoded filenames
+///
+/// macOS stores names in NFD, while most other systems use NFC
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    derive(tsify::Tsify)
+)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+pub enum UnicodeNormalization {
+    #[default]
+    Preserve,
+    Nfc,
+    Nfd,
+    Nfkc,
+}
+
+impl UnicodeNormalization {
+    /// Normalizes the string, returning None if it is already in the requested form
+    pub fn normalize(self, string: &str) -> Option<String> {
+        let normalized: String = match self {
+            UnicodeNormalization::Preserve => return None,
+            UnicodeNormalization::Nfc => string.nfc().collect(),
+            UnicodeNormalization::Nfd => string.nfd().collect(),
+            UnicodeNormalization::Nfkc => string.nfkc().collect(),
+        };
+        (normalized != string).then_some(normalized)
+    }
+}
+
+/// Strategy for handling backslashes in decoded filenames
+///
+/// APPNOTE requires forward slashes, but some Windows tools store backslashes as path separators
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    derive(tsify::Tsify)
+)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+pub enum BackslashHandling {
+    #[default]
+    Preserve,
+    ConvertToSlash,
+}
+
 /// Strategy for selecting which filename field to use
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
 #[cfg_attr(
@@ -88,6 +175,22 @@ pub enum FieldSelectionStrategy {
     LfhOnly,
 }
 
+/// Strategy for selecting which comment field to use
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    derive(tsify::Tsify)
+)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+pub enum CommentFieldSelectionStrategy {
+    #[default]
+    CdhUnicodeThenCdh,
+    CdhOnly,
+}
+
 /// Strategy for selecting encoding
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(tag = "type")]
@@ -104,11 +207,15 @@ pub enum EncodingSelectionStrategy {
     PreferOverallDetected {
         fallback_encoding: Option<String>,
         ignore_utf8_flag: bool,
+        #[serde(default)]
+        detection_hint: DetectionHint,
     },
     /// Use detected encoding per entry if available, then fallback to default
     EntryDetected {
         fallback_encoding: Option<String>,
         ignore_utf8_flag: bool,
+        #[serde(default)]
+        detection_hint: DetectionHint,
     },
     /// Always use the specified encoding
     ForceSpecified {
@@ -117,6 +224,73 @@ pub enum EncodingSelectionStrategy {
     },
 }
 
+impl Default for EncodingSelectionStrategy {
+    /// Detection without a fallback, as done by the CLI and the web app unless told otherwise
+    fn default() -> Self {
+        EncodingSelectionStrategy::PreferOverallDetected {
+            fallback_encoding: None,
+            ignore_utf8_flag: false,
+            detection_hint: DetectionHint::default(),
+        }
+    }
+}
+
+/// Prior knowledge about the origin of an archive to narrow down encoding detection
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    derive(tsify::Tsify)
+)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+pub struct DetectionHint {
+    /// Top-level domain or locale the archive comes from, such as "jp", "ko" or "zh-TW"
+    ///
+    /// Language tags without a region are mapped to the country where the language is mainly used.
+    pub locale: Option<String>,
+    /// Labels of the only encodings detection may pick, empty to allow all
+    pub allowed_encodings: Vec<String>,
+}
+
+/// Encoding to use for specific entries, as if it was forced with [`EncodingSelectionStrategy::ForceSpecified`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    derive(tsify::Tsify)
+)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+pub struct EncodingOverride {
+    /// The entries to override
+    pub target: EncodingOverrideTarget,
+    /// The encoding label
+    pub encoding: String,
+}
+
+/// Entries an encoding override applies to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    derive(tsify::Tsify)
+)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+pub enum EncodingOverrideTarget {
+    /// The entry at the given index
+    Index { index: u64 },
+    /// Entries whose filename starts with the given prefix
+    ///
+    /// The prefix is encoded with the override encoding and matched against the raw filename bytes.
+    PathPrefix { prefix: String },
+}
+
 /// Inspected ZIP archive
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[cfg_attr(
@@ -131,15 +305,139 @@ pub struct InspectedArchive {
     /// The decoded entries
     pub entries: Vec<InspectedEntry>,
     /// The overall detected encoding for the archive
-    /// None if detection failed or an error occurred during decoding
+    /// None if detection failed, an error occurred during decoding or the filenames mix encodings
     /// If present, this encoding can be used to decode all filenames in the archive without errors
     pub overall_encoding: Option<String>,
-    /// Whether the archive contains entries encoded in Shift_JIS that have Wave Dash or Fullwidth Tilde
-    pub contains_sjis_wave_dash: bool,
-    /// Whether the archive contains entries NOT encoded in Shift_JIS that have Wave Dash
-    pub contains_other_wave_dash: bool,
-    /// Whether the archive contains entries NOT encoded in Shift_JIS that have Fullwidth Tilde
-    pub contains_other_fullwidth_tilde: bool,
+    /// How plausible the filenames decoded with [`InspectedArchive::overall_encoding`] are, between 0 and 1
+    ///
+    /// On the same scale as [`EncodingCandidate::score`], so candidates scoring close to or above it are
+    /// worth suggesting. None if there is no overall encoding.
+    pub overall_encoding_score: Option<f64>,
+    /// Ranked candidate encodings for all filenames, best first
+    ///
+    /// Empty unless requested with [`InspectConfig::candidate_count`]
+    pub encoding_candidates: Vec<EncodingCandidate>,
+    /// Filename substitutions that took place, one per mapping that matched at least once
+    pub substitution_hits: Vec<SubstitutionHit>,
+    /// Filename characters that substitution mappings could change, whether or not their table is enabled
+    ///
+    /// Covers the mappings of the built-in presets, the Wave Dash options and the configured tables, matched
+    /// against the filenames decoded with the source encodings of their tables before substitution.
+    pub potential_substitutions: Vec<PotentialSubstitution>,
+    /// The decoded archive comment, None if the archive has no comment
+    pub comment: Option<InspectedArchiveComment>,
+    /// Indices of entries whose decoded filename was changed by Unicode normalization
+    pub unicode_normalized_entries: Vec<u64>,
+    /// Indices of entries whose decoded filename contains backslashes, most likely used as path separators
+    pub backslash_entries: Vec<u64>,
+    /// Indices of entries whose filename sources disagree
+    ///
+    /// Empty unless requested with [`InspectConfig::compare_filename_sources`]
+    pub mismatched_source_entries: Vec<u64>,
+    /// Groups of entries detected separately because the filenames do not share a single encoding
+    ///
+    /// Only filled with [`EncodingSelectionStrategy::PreferOverallDetected`] when no single encoding fits all
+    /// filenames, in which case [`InspectedArchive::overall_encoding`] is None.
+    pub encoding_clusters: Vec<EncodingCluster>,
+}
+
+/// Entries under the same top-level directory, with the encoding detected for their filenames together
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    derive(tsify::Tsify)
+)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+pub struct EncodingCluster {
+    /// The top-level directory, empty for entries at the root of the archive
+    pub directory: String,
+    /// The encoding detected for the cluster, None if detection failed
+    pub encoding: Option<String>,
+    /// Indices of the entries in the cluster
+    ///
+    /// Entries with UTF-8 or plain ASCII filenames, or with an encoding override, are not clustered.
+    pub entries: Vec<u64>,
+}
+
+/// Candidate encoding with its detection score
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    derive(tsify::Tsify)
+)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+pub struct EncodingCandidate {
+    /// The encoding name
+    pub encoding: String,
+    /// How plausible the decoded text is, between 0 and 1
+    pub score: f64,
+    /// The number of malformed byte sequences
+    pub error_count: u64,
+    /// The decoded filename, or the first non-ASCII filename for the whole archive
+    pub preview: String,
+}
+
+/// Entries whose filename was changed by a single mapping of a substitution table
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    derive(tsify::Tsify)
+)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+pub struct SubstitutionHit {
+    /// The name of the substitution table
+    pub table: String,
+    /// The mapping that matched
+    pub mapping: CharacterMapping,
+    /// Indices of the entries whose filename contained the mapped character
+    pub entries: Vec<u64>,
+}
+
+/// Entries decoded with the same encoding whose filename contains the source character of a mapping
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    derive(tsify::Tsify)
+)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+pub struct PotentialSubstitution {
+    /// The mapping that would match
+    pub mapping: CharacterMapping,
+    /// The encoding the filenames were decoded with
+    pub source_encoding: String,
+    /// Indices of the entries whose decoded filename contains the source character of the mapping
+    pub entries: Vec<u64>,
+}
+
+/// Inspected archive comment stored in the EOCD
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    derive(tsify::Tsify)
+)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+pub struct InspectedArchiveComment {
+    /// The original bytes before decoding
+    pub original_bytes: Option<Vec<u8>>,
+    /// The detected encoding used for decoding
+    pub detected_encoding: Option<String>,
+    /// The decoded comment
+    pub decoded: Option<DecodedString>,
 }
 
 /// Inspected ZIP file entry
@@ -159,6 +457,10 @@ pub struct InspectedEntry {
     pub uncompressed_size: u64,
     /// The compressed size of the entry
     pub compressed_size: u64,
+    /// The modification, access and creation times of the entry
+    pub timestamps: EntryTimestamps,
+    /// The decoded comment field, None if the entry has no comment
+    pub comment: Option<InspectedCommentField>,
 }
 
 /// Inspected filename field
@@ -182,6 +484,105 @@ pub struct InspectedFilenameField {
     pub detected_encoding: Option<String>,
     /// The decoded filename
     pub decoded: Option<DecodedString>,
+    /// The repaired filename if the decoded one looks like double-encoded UTF-8
+    pub double_encoding_repair: Option<DoubleEncodingRepair>,
+    /// Ranked candidate encodings, best first
+    ///
+    /// Empty unless requested with [`InspectConfig::entry_candidates`]
+    pub encoding_candidates: Vec<EncodingCandidate>,
+    /// Every filename source of the entry, decoded the same way as the selected one
+    ///
+    /// Empty unless requested with [`InspectConfig::compare_filename_sources`]
+    pub sources: Vec<FilenameSource>,
+    /// Disagreements between the filename sources
+    ///
+    /// Empty unless requested with [`InspectConfig::compare_filename_sources`]
+    pub mismatches: Vec<FilenameMismatch>,
+}
+
+/// Filename source of an entry, available or not to the field selection strategy
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    derive(tsify::Tsify)
+)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+pub struct FilenameSource {
+    /// The kind of filename field
+    pub kind: InspectedFilenameFieldKind,
+    /// Whether the field is decoded as UTF-8
+    pub utf8_flag: bool,
+    /// For "up" extra fields, whether the CRC32 matches the file name in the same header
+    pub crc32_matched: Option<bool>,
+    /// The decoded filename
+    pub decoded: Option<DecodedString>,
+}
+
+/// Disagreement between the filename sources of an entry, typically left by a buggy archiver
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    derive(tsify::Tsify)
+)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+#[serde(tag = "type")]
+pub enum FilenameMismatch {
+    /// The CDH and LFH file name fields have different bytes
+    CdhLfhFilename,
+    /// The UTF-8 flag is set in only one of the CDH and the LFH
+    CdhLfhUtf8Flag,
+    /// The CDH and LFH "up" extra fields have different names, or only one of them exists
+    CdhLfhUnicodePath,
+    /// The CRC32 of an "up" extra field does not match the file name of its header
+    UnicodePathCrc32 { kind: InspectedFilenameFieldKind },
+    /// A source decodes to another name than the selected one
+    DecodedName { kind: InspectedFilenameFieldKind },
+}
+
+/// Repair of a name that was decoded with a wrong encoding and then re-encoded as UTF-8
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    derive(tsify::Tsify)
+)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+pub struct DoubleEncodingRepair {
+    /// The repaired string
+    pub repaired: String,
+    /// The encodings the name was wrongly decoded with, outermost first
+    pub encoding_chain: Vec<String>,
+}
+
+/// Inspected comment field
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    derive(tsify::Tsify)
+)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+pub struct InspectedCommentField {
+    /// The kind of comment field
+    pub kind: InspectedCommentFieldKind,
+    /// Whether the UTF-8 flag is set for this field
+    pub utf8_flag: bool,
+    /// The original bytes before decoding
+    pub original_bytes: Option<Vec<u8>>,
+    /// The detected encoding used for decoding
+    pub detected_encoding: Option<String>,
+    /// The decoded comment
+    pub decoded: Option<DecodedString>,
 }
 
 /// Decoded string with metadata
@@ -201,6 +602,75 @@ pub struct DecodedString {
     pub has_errors: bool,
     /// The encoding used for decoding
     pub encoding_used: String,
+    /// The malformed byte sequences, each replaced with U+FFFD in the decoded string
+    pub errors: Vec<DecodingError>,
+}
+
+/// Malformed byte sequence in a decoded field
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    derive(tsify::Tsify)
+)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+pub struct DecodingError {
+    /// Offset of the first malformed byte in the original bytes
+    pub byte_start: u64,
+    /// Offset past the last malformed byte in the original bytes
+    pub byte_end: u64,
+    /// The malformed bytes
+    pub bytes: Vec<u8>,
+    /// Index of the replacement character in the decoded string, counted in characters
+    pub char_index: u64,
+}
+
+/// How [`DecodedString::escaped`] renders malformed bytes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    derive(tsify::Tsify)
+)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+pub enum EscapeStyle {
+    /// `\xXX`, with literal backslashes doubled
+    #[default]
+    Backslash,
+    /// `%XX`, with literal percent signs written as `%25`
+    Percent,
+}
+
+impl DecodedString {
+    /// Render the string with the malformed bytes escaped in place of the replacement characters
+    ///
+    /// Literal escape characters are escaped as well so that the original bytes can be told apart, even in
+    /// strings without errors.
+    pub fn escaped(&self, style: EscapeStyle) -> String {
+        let mut errors = self.errors.iter().peekable();
+        let mut escaped = String::with_capacity(self.string.len());
+        for (index, c) in self.string.chars().enumerate() {
+            if let Some(error) = errors.next_if(|e| e.char_index == index as u64) {
+                for byte in &error.bytes {
+                    match style {
+                        EscapeStyle::Backslash => escaped.push_str(&format!("\\x{byte:02X}")),
+                        EscapeStyle::Percent => escaped.push_str(&format!("%{byte:02X}")),
+                    }
+                }
+                continue;
+            }
+            match (style, c) {
+                (EscapeStyle::Backslash, '\\') => escaped.push_str("\\\\"),
+                (EscapeStyle::Percent, '%') => escaped.push_str("%25"),
+                _ => escaped.push(c),
+            }
+        }
+        escaped
+    }
 }
 
 /// Kind of inspected filename field
@@ -224,9 +694,79 @@ pub enum InspectedFilenameFieldKind {
     LfhUnicodePathExtraField,
 }
 
-impl InspectedArchive {
-    pub fn inspect(zip_file: &ZipFile, config: &InspectConfig) -> Result<Self, ZipInspectError> {
-        let fields = match config.field_selection_strategy {
+/// Kind of inspected comment field
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    derive(tsify::Tsify)
+)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+pub enum InspectedCommentFieldKind {
+    /// Central Directory Header File Comment Field
+    CdhComment,
+    /// Central Directory Header "uc" Extra Field (UTF-8)
+    CdhUnicodeCommentExtraField,
+}
+
+/// Filename field selected for an entry, before decoding
+struct FieldSelectedFileEntry<'a> {
+    kind: InspectedFilenameFieldKind,
+    utf8_flag: bool,
+    original_bytes: &'a [u8],
+}
+
+impl FieldSelectedFileEntry<'_> {
+    /// Whether the field is known to be UTF-8 and needs no detection
+    fn is_utf8(&self, ignore_utf8_flag: bool) -> bool {
+        (!ignore_utf8_flag && self.utf8_flag)
+            || matches!(
+                self.kind,
+                InspectedFilenameFieldKind::CdhUnicodePathExtraField
+                    | InspectedFilenameFieldKind::LfhUnicodePathExtraField
+            )
+    }
+}
+
+/// Comment field selected for an entry, before decoding
+struct FieldSelectedCommentEntry<'a> {
+    kind: InspectedCommentFieldKind,
+    utf8_flag: bool,
+    original_bytes: &'a [u8],
+}
+
+/// Decoded filename field along with what happened to it, to be aggregated over the archive
+struct DecodedFilenameField {
+    field: InspectedFilenameField,
+    normalized: bool,
+    has_backslash: bool,
+    /// (table, mapping) indices of the substitutions that took place
+    substitutions: Vec<(usize, usize)>,
+    /// Indices of the known mappings that could have changed the name
+    potential_substitutions: Vec<usize>,
+}
+
+/// Substitution table with the canonical names of its source encodings, empty for any encoding
+type ResolvedSubstitutionTable = (SubstitutionTable, Vec<&'static str>);
+
+/// Character mapping with the canonical names of the source encodings it applies to, empty for any encoding
+type KnownMapping = (CharacterMapping, Vec<&'static str>);
+
+/// Inspected entries and the per-archive reports aggregated from them
+struct CollectedEntries {
+    entries: Vec<InspectedEntry>,
+    substitution_hits: Vec<SubstitutionHit>,
+    potential_substitutions: Vec<PotentialSubstitution>,
+    unicode_normalized_entries: Vec<u64>,
+    backslash_entries: Vec<u64>,
+    mismatched_source_entries: Vec<u64>,
+}
+
+impl InspectedArchive {
+    pub fn inspect(zip_file: &ZipFile, config: &InspectConfig) -> Result<Self, ZipInspectError> {
+        let fields = match config.field_selection_strategy {
             FieldSelectionStrategy::CdhUnicodeThenLfhUnicodeThenCdh => &[
                 InspectedFilenameFieldKind::CdhUnicodePathExtraField,
                 InspectedFilenameFieldKind::LfhUnicodePathExtraField,
@@ -259,12 +799,6 @@ impl InspectedArchive {
             FieldSelectionStrategy::LfhOnly => &[InspectedFilenameFieldKind::LfhFilename][..],
         };
 
-        struct FieldSelectedFileEntry<'a> {
-            kind: InspectedFilenameFieldKind,
-            utf8_flag: bool,
-            original_bytes: &'a [u8],
-        }
-
         let predetect_entries = zip_file
             .entries
             .iter()
@@ -314,26 +848,66 @@ impl InspectedArchive {
             })
             .collect::<Vec<_>>();
 
+        let entry_overrides =
+            resolve_encoding_overrides(&config.encoding_overrides, &predetect_entries)?;
+
         let concatenated_filename_bytes = predetect_entries
             .iter()
-            .flat_map(|entry| entry.original_bytes)
+            .zip(&entry_overrides)
+            .filter(|(_, entry_override)| entry_override.is_none())
+            .flat_map(|(entry, _)| entry.original_bytes)
             .copied()
             .collect::<Vec<_>>();
-        let overall_encoding = detect_encoding(&concatenated_filename_bytes);
+        let detection_hint = match &config.encoding {
+            EncodingSelectionStrategy::PreferOverallDetected {
+                fallback_encoding,
+                ignore_utf8_flag: _,
+                detection_hint,
+            }
+            | EncodingSelectionStrategy::EntryDetected {
+                fallback_encoding,
+                ignore_utf8_flag: _,
+                detection_hint,
+            } => ResolvedDetectionHint::resolve(detection_hint, fallback_encoding.is_some())?,
+            EncodingSelectionStrategy::ForceSpecified {
+                encoding: _,
+                ignore_utf8_flag: _,
+            } => ResolvedDetectionHint::default(),
+        };
+
+        let overall_encoding = detect_encoding(&concatenated_filename_bytes, &detection_hint);
+
+        let encoding_candidates = if config.candidate_count > 0 {
+            let preview_bytes = predetect_entries
+                .iter()
+                .map(|entry| entry.original_bytes)
+                .find(|bytes| !bytes.is_ascii())
+                .unwrap_or_default();
+            top_encoding_candidates(
+                &concatenated_filename_bytes,
+                preview_bytes,
+                config.candidate_count,
+                &detection_hint,
+            )
+        } else {
+            Vec::new()
+        };
 
         let user_encoding = match &config.encoding {
             EncodingSelectionStrategy::PreferOverallDetected {
                 fallback_encoding,
                 ignore_utf8_flag: _,
+                detection_hint: _,
             }
             | EncodingSelectionStrategy::EntryDetected {
                 fallback_encoding,
                 ignore_utf8_flag: _,
+                detection_hint: _,
             } => {
                 if let Some(enc_name) = fallback_encoding {
-                    let encoding = Encoding::for_label(enc_name.as_bytes())
+                    let encoding = EncodingOrAscii::for_label(enc_name)
                         .ok_or(ZipInspectError::EncodingNotFound(enc_name.clone()))?;
-                    Some(EncodingOrAscii::Encoding(encoding))
+                    Some(encoding)
                 } else {
                     None
                 }
@@ -342,9 +916,9 @@ impl InspectedArchive {
                 encoding: enc_name,
                 ignore_utf8_flag: _,
             } => {
-                let encoding = Encoding::for_label(enc_name.as_bytes())
+                let encoding = EncodingOrAscii::for_label(enc_name)
                     .ok_or(ZipInspectError::EncodingNotFound(enc_name.clone()))?;
-                Some(EncodingOrAscii::Encoding(encoding))
+                Some(encoding)
             }
         };
 
@@ -352,10 +926,12 @@ impl InspectedArchive {
             EncodingSelectionStrategy::PreferOverallDetected {
                 fallback_encoding: _,
                 ignore_utf8_flag,
+                detection_hint: _,
             }
             | EncodingSelectionStrategy::EntryDetected {
                 fallback_encoding: _,
                 ignore_utf8_flag,
+                detection_hint: _,
             }
             | EncodingSelectionStrategy::ForceSpecified {
                 encoding: _,
@@ -363,66 +939,617 @@ impl InspectedArchive {
             } => *ignore_utf8_flag,
         };
 
-        let entries = predetect_entries
-            .into_iter()
-            .map(|predetect| {
-                let detected_encoding = detect_encoding(predetect.original_bytes);
-
-                let encoding = if (!ignore_utf8_flag && predetect.utf8_flag)
-                    || matches!(
-                        predetect.kind,
-                        InspectedFilenameFieldKind::CdhUnicodePathExtraField
-                            | InspectedFilenameFieldKind::LfhUnicodePathExtraField
-                    ) {
-                    Some(EncodingOrAscii::Encoding(UTF_8))
-                } else {
-                    match &config.encoding {
-                        EncodingSelectionStrategy::PreferOverallDetected {
-                            fallback_encoding: _,
-                            ignore_utf8_flag: _,
-                        } => overall_encoding.or(detected_encoding).or(user_encoding),
-                        EncodingSelectionStrategy::EntryDetected {
-                            fallback_encoding: _,
-                            ignore_utf8_flag: _,
-                        } => detected_encoding.or(user_encoding),
-                        EncodingSelectionStrategy::ForceSpecified {
-                            encoding: _,
-                            ignore_utf8_flag: _,
-                        } => user_encoding,
+        let (encoding_clusters, cluster_encodings) = if matches!(
+            config.encoding,
+            EncodingSelectionStrategy::PreferOverallDetected { .. }
+        ) {
+            detect_encoding_clusters(
+                &predetect_entries,
+                &entry_overrides,
+                ignore_utf8_flag,
+                overall_encoding,
+                &detection_hint,
+            )
+        } else {
+            (Vec::new(), vec![None; predetect_entries.len()])
+        };
+        // No single encoding applies to the whole archive if the clusters disagree
+        let overall_encoding = if encoding_clusters.is_empty() {
+            overall_encoding
+        } else {
+            None
+        };
+        let overall_encoding_score = overall_encoding.map(|encoding| {
+            let guessed_encoding = guess_encoding(&concatenated_filename_bytes, &detection_hint);
+            score_candidate(&concatenated_filename_bytes, encoding, guessed_encoding).0
+        });
+
+        let substitution_tables = resolve_substitution_tables(config)?;
+        let known_mappings = known_substitution_mappings(&substitution_tables)?;
+
+        // Selects the encoding for a field and decodes it, returning the detected encoding as well as
+        // the (table, mapping) indices of the substitutions that took place and the indices of the known
+        // mappings that could have
+        let decode_field = |original_bytes: &[u8],
+                            is_utf8: bool,
+                            overall_encoding: Option<EncodingOrAscii>,
+                            encoding_override: Option<EncodingOrAscii>| {
+            let detected_encoding = detect_encoding(original_bytes, &detection_hint);
+
+            let encoding = if is_utf8 {
+                Some(EncodingOrAscii::Encoding(UTF_8))
+            } else if encoding_override.is_some() {
+                encoding_override
+            } else {
+                match &config.encoding {
+                    EncodingSelectionStrategy::PreferOverallDetected {
+                        fallback_encoding: _,
+                        ignore_utf8_flag: _,
+                        detection_hint: _,
+                    } => overall_encoding.or(detected_encoding).or(user_encoding),
+                    EncodingSelectionStrategy::EntryDetected {
+                        fallback_encoding: _,
+                        ignore_utf8_flag: _,
+                        detection_hint: _,
+                    } => detected_encoding.or(user_encoding),
+                    EncodingSelectionStrategy::ForceSpecified {
+                        encoding: _,
+                        ignore_utf8_flag: _,
+                    } => user_encoding,
+                }
+            };
+
+            let mut decoded = encoding.map(|enc| {
+                let (string, errors, encoding_used) = enc.decode_with_errors(original_bytes);
+                DecodedString {
+                    string,
+                    has_errors: !errors.is_empty(),
+                    encoding_used: encoding_used.name().to_string(),
+                    errors,
+                }
+            });
+
+            let potential_substitutions = decoded.as_ref().map_or_else(Vec::new, |decoded| {
+                known_mappings
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, (mapping, source_encodings))| {
+                        decoded.string.contains(mapping.from)
+                            && (source_encodings.is_empty()
+                                || source_encodings.contains(&decoded.encoding_used.as_str()))
+                    })
+                    .map(|(index, _)| index)
+                    .collect()
+            });
+
+            let mut substitutions = Vec::new();
+            if let Some(decoded) = &mut decoded {
+                for (table_index, (table, source_encodings)) in
+                    substitution_tables.iter().enumerate()
+                {
+                    if !source_encodings.is_empty()
+                        && !source_encodings.contains(&decoded.encoding_used.as_str())
+                    {
+                        continue;
                     }
-                };
+                    let (string, mapping_indices) = table.apply(&decoded.string);
+                    decoded.string = string;
+                    substitutions.extend(mapping_indices.into_iter().map(|m| (table_index, m)));
+                }
+            }
 
-                let decoded = encoding.and_then(|enc| {
-                    decode_with_encoding(
-                        predetect.original_bytes,
-                        enc.encoding(),
-                        true,
-                        config.wave_dash_handling,
-                        config.wave_dash_normalization,
-                    )
-                    .map(|(string, has_errors, encoding_used)| DecodedString {
-                        string,
-                        has_errors,
-                        encoding_used: encoding_used.name().to_string(),
-                    })
-                });
+            (
+                detected_encoding.map(|e| e.name().to_string()),
+                decoded,
+                substitutions,
+                potential_substitutions,
+            )
+        };
 
-                let original_bytes = if config.needs_original_bytes {
-                    Some(predetect.original_bytes.to_vec())
-                } else {
-                    None
-                };
-
-                InspectedFilenameField {
-                    kind: predetect.kind,
-                    utf8_flag: predetect.utf8_flag,
-                    original_bytes,
-                    detected_encoding: detected_encoding.map(|e| e.name().to_string()),
-                    decoded,
+        // Unicode Path extra fields are usually selected when present, so the selected bytes tell nothing
+        // about the encoding of the legacy names that are compared against them
+        let legacy_filename_encoding = if config.compare_filename_sources {
+            let concatenated_legacy_bytes = zip_file
+                .entries
+                .iter()
+                .zip(&entry_overrides)
+                .filter(|(_, entry_override)| entry_override.is_none())
+                .flat_map(|(entry, _)| &entry.cdh.filename)
+                .copied()
+                .collect::<Vec<_>>();
+            detect_encoding(&concatenated_legacy_bytes, &detection_hint)
+        } else {
+            None
+        };
+
+        let filename_fields = predetect_entries
+            .iter()
+            .zip(&entry_overrides)
+            .zip(cluster_encodings)
+            .zip(&zip_file.entries)
+            .map(
+                |(((predetect, &encoding_override), cluster_encoding), entry)| {
+                    let (detected_encoding, mut decoded, substitutions, potential_substitutions) =
+                        decode_field(
+                            predetect.original_bytes,
+                            predetect.is_utf8(ignore_utf8_flag),
+                            overall_encoding.or(cluster_encoding),
+                            encoding_override,
+                        );
+
+                    let (sources, mismatches) = if config.compare_filename_sources {
+                        // Legacy names must be decoded like the selected name if they are the selected one
+                        let legacy_encoding = match predetect.kind {
+                            InspectedFilenameFieldKind::CdhFilename
+                            | InspectedFilenameFieldKind::LfhFilename => {
+                                overall_encoding.or(cluster_encoding)
+                            }
+                            InspectedFilenameFieldKind::CdhUnicodePathExtraField
+                            | InspectedFilenameFieldKind::LfhUnicodePathExtraField => {
+                                legacy_filename_encoding
+                            }
+                        };
+                        compare_filename_sources(
+                            entry,
+                            predetect.kind,
+                            ignore_utf8_flag,
+                            |bytes, is_utf8| {
+                                decode_field(bytes, is_utf8, legacy_encoding, encoding_override).1
+                            },
+                        )
+                    } else {
+                        (Vec::new(), Vec::new())
+                    };
+
+                    let original_bytes = if config.needs_original_bytes {
+                        Some(predetect.original_bytes.to_vec())
+                    } else {
+                        None
+                    };
+
+                    // Double encoding has to be detected on the raw decoded string, as normalization
+                    // would break the round trip through the wrong encoding
+                    let mut double_encoding_repair = decoded
+                        .as_ref()
+                        .filter(|d| !d.has_errors)
+                        .and_then(|d| detect_double_encoding(&d.string));
+
+                    let mut normalized = false;
+                    if let Some(decoded) = &mut decoded
+                        && let Some(string) =
+                            config.unicode_normalization.normalize(&decoded.string)
+                    {
+                        // Replacement characters are never composed nor reordered, so only their indices shift
+                        let old_indices = replacement_char_indices(&decoded.string);
+                        let new_indices = replacement_char_indices(&string);
+                        for error in &mut decoded.errors {
+                            if let Some(index) = old_indices
+                                .iter()
+                                .position(|&i| i == error.char_index)
+                                .and_then(|ordinal| new_indices.get(ordinal))
+                            {
+                                error.char_index = *index;
+                            }
+                        }
+                        decoded.string = string;
+                        normalized = true;
+                    }
+                    if let Some(repair) = &mut double_encoding_repair
+                        && let Some(string) =
+                            config.unicode_normalization.normalize(&repair.repaired)
+                    {
+                        repair.repaired = string;
+                    }
+
+                    // Checking the decoded string rather than the bytes, as 0x5C is also a trail byte in DBCS
+                    // encodings such as Shift_JIS, GBK and Big5
+                    let has_backslash = decoded.as_ref().is_some_and(|d| d.string.contains('\\'));
+                    if config.backslash_handling == BackslashHandling::ConvertToSlash {
+                        if let Some(decoded) = &mut decoded {
+                            decoded.string = decoded.string.replace('\\', "/");
+                        }
+                        if let Some(repair) = &mut double_encoding_repair {
+                            repair.repaired = repair.repaired.replace('\\', "/");
+                        }
+                    }
+
+                    let encoding_candidates =
+                        if config.entry_candidates && config.candidate_count > 0 {
+                            top_encoding_candidates(
+                                predetect.original_bytes,
+                                predetect.original_bytes,
+                                config.candidate_count,
+                                &detection_hint,
+                            )
+                        } else {
+                            Vec::new()
+                        };
+
+                    DecodedFilenameField {
+                        field: InspectedFilenameField {
+                            kind: predetect.kind,
+                            utf8_flag: predetect.utf8_flag,
+                            original_bytes,
+                            detected_encoding,
+                            decoded,
+                            double_encoding_repair,
+                            encoding_candidates,
+                            sources,
+                            mismatches,
+                        },
+                        normalized,
+                        has_backslash,
+                        substitutions,
+                        potential_substitutions,
+                    }
+                },
+            );
+
+        // Comments are only stored in the CDH, and entries without any comment have no field selected
+        let comment_predetect_entries = zip_file
+            .entries
+            .iter()
+            .map(|entry| {
+                let use_unicode = matches!(
+                    config.comment_field_selection_strategy,
+                    CommentFieldSelectionStrategy::CdhUnicodeThenCdh
+                );
+                if use_unicode
+                    && let Some(uc) = &entry.cdh.unicode_comment
+                    && (config.ignore_crc32_mismatch || uc.crc32_matched)
+                {
+                    return Some(FieldSelectedCommentEntry {
+                        kind: InspectedCommentFieldKind::CdhUnicodeCommentExtraField,
+                        utf8_flag: true,
+                        original_bytes: &uc.data,
+                    });
                 }
+
+                (!entry.cdh.file_comment.is_empty()).then(|| FieldSelectedCommentEntry {
+                    kind: InspectedCommentFieldKind::CdhComment,
+                    utf8_flag: entry.cdh.flags.is_utf8(),
+                    original_bytes: &entry.cdh.file_comment,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        // Only comments that need detection are considered, as UTF-8 ones would skew the result
+        let concatenated_comment_bytes = comment_predetect_entries
+            .iter()
+            .zip(&entry_overrides)
+            .filter(|(_, entry_override)| entry_override.is_none())
+            .filter_map(|(entry, _)| entry.as_ref())
+            .filter(|entry| {
+                entry.kind == InspectedCommentFieldKind::CdhComment
+                    && (ignore_utf8_flag || !entry.utf8_flag)
             })
-            .zip(zip_file.entries.iter())
-            .map(|(filename_field, entry)| InspectedEntry {
+            .flat_map(|entry| entry.original_bytes)
+            .chain(&zip_file.eocd.comment)
+            .copied()
+            .collect::<Vec<_>>();
+        let overall_comment_encoding =
+            detect_encoding(&concatenated_comment_bytes, &detection_hint);
+
+        // The archive comment has no UTF-8 flag, so it always goes through detection
+        let comment = (!zip_file.eocd.comment.is_empty()).then(|| {
+            let (detected_encoding, decoded, _, _) = decode_field(
+                &zip_file.eocd.comment,
+                false,
+                overall_comment_encoding,
+                None,
+            );
+
+            let original_bytes = if config.needs_original_bytes {
+                Some(zip_file.eocd.comment.clone())
+            } else {
+                None
+            };
+
+            InspectedArchiveComment {
+                original_bytes,
+                detected_encoding,
+                decoded,
+            }
+        });
+
+        let comment_fields = comment_predetect_entries
+            .into_iter()
+            .zip(&entry_overrides)
+            .map(|(predetect, &encoding_override)| {
+                predetect.map(|predetect| {
+                    let is_utf8 = (!ignore_utf8_flag && predetect.utf8_flag)
+                        || predetect.kind == InspectedCommentFieldKind::CdhUnicodeCommentExtraField;
+                    let (detected_encoding, decoded, _, _) = decode_field(
+                        predetect.original_bytes,
+                        is_utf8,
+                        overall_comment_encoding,
+                        encoding_override,
+                    );
+
+                    let original_bytes = if config.needs_original_bytes {
+                        Some(predetect.original_bytes.to_vec())
+                    } else {
+                        None
+                    };
+
+                    InspectedCommentField {
+                        kind: predetect.kind,
+                        utf8_flag: predetect.utf8_flag,
+                        original_bytes,
+                        detected_encoding,
+                        decoded,
+                    }
+                })
+            });
+
+        let CollectedEntries {
+            entries,
+            substitution_hits,
+            potential_substitutions,
+            unicode_normalized_entries,
+            backslash_entries,
+            mismatched_source_entries,
+        } = collect_entries(
+            zip_file,
+            filename_fields,
+            comment_fields,
+            &substitution_tables,
+            &known_mappings,
+        );
+
+        Ok(Self {
+            overall_encoding: overall_encoding.map(|e| e.name().to_string()),
+            overall_encoding_score,
+            entries,
+            comment,
+            encoding_candidates,
+            substitution_hits,
+            potential_substitutions,
+            unicode_normalized_entries,
+            backslash_entries,
+            mismatched_source_entries,
+            encoding_clusters,
+        })
+    }
+}
+
+/// Resolves the encoding overrides to the encoding of each entry, the first override matching an entry winning
+///
+/// Overrides are resolved on the raw bytes, so that overridden entries can be excluded from detection.
+fn resolve_encoding_overrides(
+    encoding_overrides: &[EncodingOverride],
+    predetect_entries: &[FieldSelectedFileEntry],
+) -> Result<Vec<Option<EncodingOrAscii>>, ZipInspectError> {
+    let mut entry_overrides = vec![None; predetect_entries.len()];
+    for encoding_override in encoding_overrides {
+        let encoding = EncodingOrAscii::for_label(&encoding_override.encoding)
+            .ok_or_else(|| ZipInspectError::EncodingNotFound(encoding_override.encoding.clone()))?;
+        match &encoding_override.target {
+            EncodingOverrideTarget::Index { index } => {
+                entry_overrides
+                    .get_mut(*index as usize)
+                    .ok_or(ZipInspectError::EntryIndexOutOfRange(*index))?
+                    .get_or_insert(encoding);
+            }
+            EncodingOverrideTarget::PathPrefix { prefix } => {
+                let prefix_bytes =
+                    encoding
+                        .encode(prefix)
+                        .ok_or_else(|| ZipInspectError::PrefixNotEncodable {
+                            prefix: prefix.clone(),
+                            encoding: encoding.name().to_string(),
+                        })?;
+                for (entry_override, predetect) in entry_overrides.iter_mut().zip(predetect_entries)
+                {
+                    if predetect.original_bytes.starts_with(&prefix_bytes) {
+                        entry_override.get_or_insert(encoding);
+                    }
+                }
+            }
+        }
+    }
+    Ok(entry_overrides)
+}
+
+/// Detects the encoding of each top-level directory, returning the clusters and the encoding of each entry
+///
+/// Archives assembled from several sources usually keep each source in its own top-level directory.
+/// Since code pages decode almost anything, mixed encodings rarely make the overall detection fail,
+/// so each directory is detected on its own and compared against the overall encoding. Both are empty
+/// (or None) unless the directories disagree with the overall encoding.
+fn detect_encoding_clusters(
+    predetect_entries: &[FieldSelectedFileEntry],
+    entry_overrides: &[Option<EncodingOrAscii>],
+    ignore_utf8_flag: bool,
+    overall_encoding: Option<EncodingOrAscii>,
+    detection_hint: &ResolvedDetectionHint,
+) -> (Vec<EncodingCluster>, Vec<Option<EncodingOrAscii>>) {
+    let mut cluster_encodings = vec![None; predetect_entries.len()];
+    let mut encoding_clusters = Vec::new();
+
+    let mut clusters: Vec<(&[u8], Vec<usize>)> = Vec::new();
+    for (index, (predetect, entry_override)) in
+        predetect_entries.iter().zip(entry_overrides).enumerate()
+    {
+        if entry_override.is_some()
+            || predetect.is_utf8(ignore_utf8_flag)
+            || predetect.original_bytes.is_ascii()
+        {
+            continue;
+        }
+        // 0x2F is never a trail byte in DBCS encodings, unlike 0x5C
+        let bytes = predetect.original_bytes;
+        let directory = bytes
+            .iter()
+            .position(|&b| b == b'/')
+            .map_or(&[][..], |pos| &bytes[..pos]);
+        match clusters.iter_mut().find(|(d, _)| *d == directory) {
+            Some((_, indices)) => indices.push(index),
+            None => clusters.push((directory, vec![index])),
+        }
+    }
+
+    let clusters = clusters
+        .into_iter()
+        .map(|(directory, indices)| {
+            let concatenated_bytes = indices
+                .iter()
+                .flat_map(|&index| predetect_entries[index].original_bytes)
+                .copied()
+                .collect::<Vec<_>>();
+            // Short names are easily misdetected, so a cluster only keeps its own encoding if
+            // the names look more plausible with it than with the overall one
+            let encoding = match (
+                overall_encoding,
+                detect_encoding(&concatenated_bytes, detection_hint),
+            ) {
+                (Some(overall), Some(detected))
+                    if score_encoding(&concatenated_bytes, detected)
+                        <= score_encoding(&concatenated_bytes, overall) =>
+                {
+                    Some(overall)
+                }
+                (overall, detected) => detected.or(overall),
+            };
+            (directory, indices, encoding)
+        })
+        .collect::<Vec<_>>();
+
+    let is_mixed = overall_encoding.is_none()
+        || clusters
+            .iter()
+            .any(|(_, _, encoding)| *encoding != overall_encoding);
+    if is_mixed {
+        for (directory, indices, encoding) in clusters {
+            for &index in &indices {
+                cluster_encodings[index] = encoding;
+            }
+            encoding_clusters.push(EncodingCluster {
+                directory: match encoding {
+                    Some(encoding) => encoding.decode(directory).0,
+                    None => String::from_utf8_lossy(directory).into_owned(),
+                },
+                encoding: encoding.map(|e| e.name().to_string()),
+                entries: indices.into_iter().map(|index| index as u64).collect(),
+            });
+        }
+    }
+
+    (encoding_clusters, cluster_encodings)
+}
+
+/// Resolves the substitution tables to apply, in order
+///
+/// The Wave Dash options are shorthands for substitution tables applied before the configured ones.
+fn resolve_substitution_tables(
+    config: &InspectConfig,
+) -> Result<Vec<ResolvedSubstitutionTable>, ZipInspectError> {
+    wave_dash_substitution_tables(config.wave_dash_handling, config.wave_dash_normalization)
+        .into_iter()
+        .chain(config.substitution_tables.iter().cloned())
+        .map(|table| {
+            let source_encodings = resolve_source_encodings(&table)?;
+            Ok((table, source_encodings))
+        })
+        .collect()
+}
+
+/// Every known mapping with the source encodings it applies to, to report which ones could apply even if
+/// their table is not enabled
+///
+/// Covers the built-in presets, the Wave Dash options and the given tables.
+fn known_substitution_mappings(
+    substitution_tables: &[ResolvedSubstitutionTable],
+) -> Result<Vec<KnownMapping>, ZipInspectError> {
+    let builtin_tables = SubstitutionPreset::ALL
+        .iter()
+        .map(|preset| preset.table())
+        .chain(wave_dash_substitution_tables(
+            WaveDashHandling::DecodeToWaveDash,
+            WaveDashNormalization::NormalizeToWaveDash,
+        ))
+        .chain(wave_dash_substitution_tables(
+            WaveDashHandling::DecodeToWaveDash,
+            WaveDashNormalization::NormalizeToFullwidthTilde,
+        ))
+        .map(|table| {
+            let source_encodings = resolve_source_encodings(&table)?;
+            Ok((table, source_encodings))
+        })
+        .collect::<Result<Vec<_>, ZipInspectError>>()?;
+
+    let mut mappings = Vec::<KnownMapping>::new();
+    for (table, source_encodings) in builtin_tables.iter().chain(substitution_tables) {
+        for &mapping in &table.mappings {
+            match mappings.iter_mut().find(|(m, _)| *m == mapping) {
+                Some((_, known_encodings)) => {
+                    if source_encodings.is_empty() {
+                        known_encodings.clear();
+                    } else if !known_encodings.is_empty() {
+                        for encoding in source_encodings {
+                            if !known_encodings.contains(encoding) {
+                                known_encodings.push(encoding);
+                            }
+                        }
+                    }
+                }
+                None => mappings.push((mapping, source_encodings.clone())),
+            }
+        }
+    }
+    Ok(mappings)
+}
+
+/// Builds the inspected entries and aggregates the per-entry findings into per-archive reports
+fn collect_entries(
+    zip_file: &ZipFile,
+    filename_fields: impl Iterator<Item = DecodedFilenameField>,
+    comment_fields: impl Iterator<Item = Option<InspectedCommentField>>,
+    substitution_tables: &[ResolvedSubstitutionTable],
+    known_mappings: &[KnownMapping],
+) -> CollectedEntries {
+    let mut unicode_normalized_entries = Vec::new();
+    let mut backslash_entries = Vec::new();
+    let mut mismatched_source_entries = Vec::new();
+    let mut substitution_entries = substitution_tables
+        .iter()
+        .map(|(table, _)| vec![Vec::new(); table.mappings.len()])
+        .collect::<Vec<_>>();
+    // Known mapping index, source encoding and entries
+    let mut potential_entries: Vec<(usize, String, Vec<u64>)> = Vec::new();
+    let entries = filename_fields
+        .zip(comment_fields)
+        .zip(zip_file.entries.iter())
+        .enumerate()
+        .map(|(index, ((filename_field, comment_field), entry))| {
+            if filename_field.normalized {
+                unicode_normalized_entries.push(index as u64);
+            }
+            if filename_field.has_backslash {
+                backslash_entries.push(index as u64);
+            }
+            if !filename_field.field.mismatches.is_empty() {
+                mismatched_source_entries.push(index as u64);
+            }
+            for (table_index, mapping_index) in filename_field.substitutions {
+                substitution_entries[table_index][mapping_index].push(index as u64);
+            }
+            if let Some(decoded) = &filename_field.field.decoded {
+                for mapping_index in filename_field.potential_substitutions {
+                    match potential_entries
+                        .iter_mut()
+                        .find(|(m, e, _)| *m == mapping_index && *e == decoded.encoding_used)
+                    {
+                        Some((_, _, entries)) => entries.push(index as u64),
+                        None => potential_entries.push((
+                            mapping_index,
+                            decoded.encoding_used.clone(),
+                            vec![index as u64],
+                        )),
+                    }
+                }
+            }
+            InspectedEntry {
                 uncompressed_size: entry
                     .cdh
                     .zip64
@@ -433,142 +1560,804 @@ impl InspectedArchive {
                     .zip64
                     .and_then(|z| z.compressed_size)
                     .unwrap_or(entry.cdh.compressed_size as u64),
-                filename: filename_field,
-            })
-            .collect::<Vec<_>>();
-
-        let mut contains_sjis_wave_dash = false;
-        let mut contains_other_wave_dash = false;
-        let mut contains_other_fullwidth_tilde = false;
-
-        for entry in &entries {
-            if let Some(decoded) = &entry.filename.decoded {
-                let has_wd = decoded.string.contains('\u{301C}');
-                let has_ft = decoded.string.contains('\u{FF5E}');
-                if has_wd || has_ft {
-                    let encoding = &decoded.encoding_used;
-                    let is_sjis = encoding == "Shift_JIS";
-
-                    if is_sjis {
-                        contains_sjis_wave_dash = true;
-                    } else if has_wd {
-                        contains_other_wave_dash = true;
-                    } else if has_ft {
-                        contains_other_fullwidth_tilde = true;
-                    }
-                }
+                timestamps: EntryTimestamps::resolve(entry),
+                filename: filename_field.field,
+                comment: comment_field,
             }
-        }
+        })
+        .collect::<Vec<_>>();
+
+    let substitution_hits = substitution_tables
+        .iter()
+        .zip(substitution_entries)
+        .flat_map(|((table, _), entries)| {
+            table
+                .mappings
+                .iter()
+                .zip(entries)
+                .filter(|(_, entries)| !entries.is_empty())
+                .map(|(mapping, entries)| SubstitutionHit {
+                    table: table.name.clone(),
+                    mapping: *mapping,
+                    entries,
+                })
+        })
+        .collect();
+
+    potential_entries.sort_by_key(|(mapping_index, _, _)| *mapping_index);
+    let potential_substitutions = potential_entries
+        .into_iter()
+        .map(
+            |(mapping_index, source_encoding, entries)| PotentialSubstitution {
+                mapping: known_mappings[mapping_index].0,
+                source_encoding,
+                entries,
+            },
+        )
+        .collect();
+
+    CollectedEntries {
+        entries,
+        substitution_hits,
+        potential_substitutions,
+        unicode_normalized_entries,
+        backslash_entries,
+        mismatched_source_entries,
+    }
+}
 
-        Ok(Self {
-            overall_encoding: overall_encoding.map(|e| e.name().to_string()),
-            entries,
-            contains_sjis_wave_dash,
-            contains_other_wave_dash,
-            contains_other_fullwidth_tilde,
+/// Decode every filename source of the entry and list the disagreements between them
+fn compare_filename_sources(
+    entry: &ZipFileEntry,
+    selected_kind: InspectedFilenameFieldKind,
+    ignore_utf8_flag: bool,
+    decode: impl Fn(&[u8], bool) -> Option<DecodedString>,
+) -> (Vec<FilenameSource>, Vec<FilenameMismatch>) {
+    let legacy_sources = [
+        (
+            InspectedFilenameFieldKind::CdhFilename,
+            &entry.cdh.filename,
+            entry.cdh.flags.is_utf8(),
+        ),
+        (
+            InspectedFilenameFieldKind::LfhFilename,
+            &entry.lfh.filename,
+            entry.lfh.flags.is_utf8(),
+        ),
+    ]
+    .map(|(kind, bytes, utf8_flag)| FilenameSource {
+        kind,
+        utf8_flag,
+        crc32_matched: None,
+        decoded: decode(bytes, !ignore_utf8_flag && utf8_flag),
+    });
+    let unicode_path_sources = [
+        (
+            InspectedFilenameFieldKind::CdhUnicodePathExtraField,
+            &entry.cdh.unicode_path,
+        ),
+        (
+            InspectedFilenameFieldKind::LfhUnicodePathExtraField,
+            &entry.lfh.unicode_path,
+        ),
+    ]
+    .into_iter()
+    .filter_map(|(kind, unicode_path)| {
+        unicode_path.as_ref().map(|up| FilenameSource {
+            kind,
+            utf8_flag: true,
+            crc32_matched: Some(up.crc32_matched),
+            decoded: decode(&up.data, true),
         })
+    });
+    let sources = legacy_sources
+        .into_iter()
+        .chain(unicode_path_sources)
+        .collect::<Vec<_>>();
+
+    let mut mismatches = Vec::new();
+    if entry.cdh.filename != entry.lfh.filename {
+        mismatches.push(FilenameMismatch::CdhLfhFilename);
+    }
+    if entry.cdh.flags.is_utf8() != entry.lfh.flags.is_utf8() {
+        mismatches.push(FilenameMismatch::CdhLfhUtf8Flag);
+    }
+    let unicode_path_data =
+        |up: &Option<UnicodePathExtraField>| up.as_ref().map(|up| up.data.clone());
+    if (entry.cdh.unicode_path.is_some() || entry.lfh.unicode_path.is_some())
+        && unicode_path_data(&entry.cdh.unicode_path) != unicode_path_data(&entry.lfh.unicode_path)
+    {
+        mismatches.push(FilenameMismatch::CdhLfhUnicodePath);
+    }
+    mismatches.extend(
+        sources
+            .iter()
+            .filter(|source| source.crc32_matched == Some(false))
+            .map(|source| FilenameMismatch::UnicodePathCrc32 { kind: source.kind }),
+    );
+
+    let selected_name = sources
+        .iter()
+        .find(|source| source.kind == selected_kind)
+        .and_then(|source| source.decoded.as_ref())
+        .map(|decoded| &decoded.string);
+    mismatches.extend(
+        sources
+            .iter()
+            .filter(|source| source.decoded.as_ref().map(|d| &d.string) != selected_name)
+            .map(|source| FilenameMismatch::DecodedName { kind: source.kind }),
+    );
+
+    (sources, mismatches)
+}
+
+/// Indices of the replacement characters in the string, counted in characters
+fn replacement_char_indices(string: &str) -> Vec<u64> {
+    string
+        .chars()
+        .enumerate()
+        .filter(|&(_, c)| c == char::REPLACEMENT_CHARACTER)
+        .map(|(index, _)| index as u64)
+        .collect()
+}
+
+/// Builds the substitution tables equivalent to the Wave Dash options
+fn wave_dash_substitution_tables(
+    wave_dash_handling: WaveDashHandling,
+    normalization: WaveDashNormalization,
+) -> Vec<SubstitutionTable> {
+    let wave_dash = CharacterMapping {
+        from: '\u{FF5E}',
+        to: '\u{301C}',
+    };
+    let fullwidth_tilde = CharacterMapping {
+        from: '\u{301C}',
+        to: '\u{FF5E}',
+    };
+
+    let mut tables = Vec::new();
+    // Shift_JIS 0x8160 is decoded to Fullwidth Tilde as per CP932
+    if wave_dash_handling == WaveDashHandling::DecodeToWaveDash {
+        tables.push(SubstitutionTable {
+            name: "Shift_JIS Wave Dash".to_string(),
+            source_encodings: vec!["Shift_JIS".to_string()],
+            mappings: vec![wave_dash],
+        });
+    }
+    let normalization_mapping = match normalization {
+        WaveDashNormalization::Preserve => None,
+        WaveDashNormalization::NormalizeToFullwidthTilde => Some(fullwidth_tilde),
+        WaveDashNormalization::NormalizeToWaveDash => Some(wave_dash),
+    };
+    if let Some(mapping) = normalization_mapping {
+        tables.push(SubstitutionTable {
+            name: "Wave Dash normalization".to_string(),
+            source_encodings: Vec::new(),
+            mappings: vec![mapping],
+        });
     }
+    tables
 }
 
-/// Decode bytes using a specific encoding from encoding_rs
+/// Looks up the source encodings of a substitution table, returning their canonical names
+fn resolve_source_encodings(
+    table: &SubstitutionTable,
+) -> Result<Vec<&'static str>, ZipInspectError> {
+    table
+        .source_encodings
+        .iter()
+        .map(|label| {
+            EncodingOrAscii::for_label(label)
+                .map(|encoding| encoding.name())
+                .ok_or_else(|| ZipInspectError::EncodingNotFound(label.clone()))
+        })
+        .collect()
+}
+
+/// Decode bytes using a specific encoding from encoding_rs or a built-in code page
 fn decode_with_encoding(
     data: &[u8],
-    encoding: &'static Encoding,
+    encoding: EncodingOrAscii,
     force: bool,
-    wave_dash_handling: WaveDashHandling,
-    normalization: WaveDashNormalization,
-) -> Option<(String, bool, &'static Encoding)> {
-    let (result, encoding_used, has_errors) = encoding.decode(data);
-    if force || !has_errors {
-        let mut string = result.into_owned();
-
-        // Handle Shift_JIS specific wave dash handling
-        if encoding_used.name() == "Shift_JIS"
-            && matches!(wave_dash_handling, WaveDashHandling::DecodeToWaveDash)
-        {
-            string = string.replace('\u{FF5E}', "\u{301C}");
-        }
+) -> Option<(String, bool, EncodingOrAscii)> {
+    let (string, has_errors, encoding_used) = encoding.decode(data);
+    (force || !has_errors).then_some((string, has_errors, encoding_used))
+}
 
-        // Apply normalization
-        match normalization {
-            WaveDashNormalization::NormalizeToFullwidthTilde => {
-                string = string.replace('\u{301C}', "\u{FF5E}");
-            }
-            WaveDashNormalization::NormalizeToWaveDash => {
-                string = string.replace('\u{FF5E}', "\u{301C}");
-            }
-            WaveDashNormalization::Preserve => {}
-        }
+/// Detection hint with the locale converted to a TLD and the allowed encodings looked up
+#[derive(Debug, Default)]
+struct ResolvedDetectionHint {
+    tld: Option<String>,
+    allowed_encodings: Vec<EncodingOrAscii>,
+    /// Whether the user gave a fallback encoding, which takes precedence over guessing a DOS code page
+    has_fallback: bool,
+}
 
-        Some((string, has_errors, encoding_used))
-    } else {
-        None
+impl ResolvedDetectionHint {
+    fn resolve(hint: &DetectionHint, has_fallback: bool) -> Result<Self, ZipInspectError> {
+        let tld = hint
+            .locale
+            .as_ref()
+            .map(|locale| {
+                locale_to_tld(locale).ok_or_else(|| ZipInspectError::InvalidLocale(locale.clone()))
+            })
+            .transpose()?;
+        let allowed_encodings = hint
+            .allowed_encodings
+            .iter()
+            .map(|label| {
+                EncodingOrAscii::for_label(label)
+                    .ok_or_else(|| ZipInspectError::EncodingNotFound(label.clone()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            tld,
+            allowed_encodings,
+            has_fallback,
+        })
+    }
+
+    /// Whether detection may pick the encoding
+    fn allows(&self, encoding: EncodingOrAscii) -> bool {
+        self.allowed_encodings.is_empty() || self.allowed_encodings.contains(&encoding)
     }
 }
 
-/// Auto-detect encoding using chardetng and encoding_rs
+/// Convert a TLD or a BCP 47 language tag to the TLD expected by chardetng
+fn locale_to_tld(locale: &str) -> Option<String> {
+    let locale = locale.trim().trim_start_matches('.').to_ascii_lowercase();
+    let mut subtags = locale.split(['-', '_']);
+    let language = subtags.next()?;
+    let subtags = subtags.collect::<Vec<_>>();
+
+    let tld = if let Some(region) = subtags
+        .iter()
+        .find(|s| s.len() == 2 && s.bytes().all(|b| b.is_ascii_alphabetic()))
+    {
+        // Regions are country codes, which most country TLDs are
+        region
+    } else if subtags.contains(&"hant") {
+        "tw"
+    } else {
+        match language {
+            "ja" => "jp",
+            "ko" => "kr",
+            "zh" => "cn",
+            "el" => "gr",
+            "he" | "iw" => "il",
+            "uk" => "ua",
+            "cs" => "cz",
+            "vi" => "vn",
+            "fa" => "ir",
+            "be" => "by",
+            "kk" => "kz",
+            "sr" => "rs",
+            "sl" => "si",
+            "et" => "ee",
+            "da" => "dk",
+            "sv" => "se",
+            other => other,
+        }
+    };
+
+    // chardetng panics on anything that does not look like a lowercase DNS label
+    (!tld.is_empty() && tld.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-'))
+        .then(|| tld.to_string())
+}
+
+/// Auto-detect encoding using chardetng and encoding_rs, only picking encodings allowed by the hint
 ///
 /// Returns detected encoding or None if decoding failed
-fn detect_encoding(data: &[u8]) -> Option<EncodingOrAscii> {
+fn detect_encoding(data: &[u8], hint: &ResolvedDetectionHint) -> Option<EncodingOrAscii> {
     // Try UTF-8 (most common for modern zips)
+    let allow_utf8 = hint.allows(EncodingOrAscii::Encoding(UTF_8));
     if let Ok(decoded) = str::from_utf8(data)
         && !decoded.contains('\0')
     {
-        let encoding = if data.iter().all(|&b| b.is_ascii()) {
-            EncodingOrAscii::Ascii
-        } else {
-            EncodingOrAscii::Encoding(UTF_8)
-        };
-        return Some(encoding);
+        if data.iter().all(|&b| b.is_ascii()) {
+            return Some(EncodingOrAscii::Ascii);
+        }
+        if allow_utf8 {
+            return Some(EncodingOrAscii::Encoding(UTF_8));
+        }
     }
 
     // Use chardetng for general encoding detection
     let mut detector = EncodingDetector::new();
     detector.feed(data, true);
 
-    let detected_encoding = detector.guess(None, true);
-    if let Some((_, has_errors, encoding)) = decode_with_encoding(
-        data,
-        detected_encoding,
-        false,
-        WaveDashHandling::default(),
-        WaveDashNormalization::default(),
-    ) && !has_errors
+    // Single-byte encodings such as Windows-1252 decode unassigned bytes to C1 controls instead of
+    // reporting errors, but those never appear in real text
+    let is_clean = |string: &str| !string.chars().any(|c| ('\u{80}'..='\u{9F}').contains(&c));
+    let detected_encoding = EncodingOrAscii::Encoding(
+        detector.guess(hint.tld.as_deref().map(str::as_bytes), allow_utf8),
+    );
+    // Short CJK names are often valid in several encodings, leaving chardetng with too little to go on
+    if let Some(encoding) = pick_by_dictionary(data, hint, detected_encoding) {
+        return Some(encoding);
+    }
+    if hint.allows(detected_encoding)
+        && let Some((string, has_errors, encoding)) =
+            decode_with_encoding(data, detected_encoding, false)
+        && !has_errors
+        && is_clean(&string)
     {
-        return Some(EncodingOrAscii::Encoding(encoding));
+        return Some(encoding);
     }
 
-    None
-}
+    // chardetng cannot be restricted to the allowed encodings, so take the best ranked one instead
+    if !hint.allowed_encodings.is_empty() {
+        return rank_encodings(data, hint)
+            .into_iter()
+            .find(|&(encoding, _, error_count)| {
+                error_count == 0 && is_clean(&encoding.decode(data).0)
+            })
+            .map(|(encoding, _, _)| encoding);
+    }
 
-/// Encoding or ASCII marker
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum EncodingOrAscii {
-    Ascii,
-    Encoding(&'static Encoding),
+    // A code page would always be found, so it must not shadow the fallback chosen by the user
+    if hint.has_fallback {
+        return None;
+    }
+
+    // chardetng does not know DOS code pages. Since they decode almost any byte sequence, pick the one
+    // that yields the most letters, preferring earlier ones (CP437 first, as per APPNOTE) on ties
+    CODE_PAGES
+        .iter()
+        .filter_map(|code_page| {
+            let (string, has_errors) = code_page.decode(data);
+            let letters = string
+                .chars()
+                .filter(|c| !c.is_ascii() && c.is_alphabetic())
+                .count();
+            (!has_errors).then_some((letters, code_page))
+        })
+        .min_by_key(|(letters, _)| std::cmp::Reverse(*letters))
+        .map(|(_, code_page)| EncodingOrAscii::CodePage(code_page))
 }
 
-impl EncodingOrAscii {
-    fn name(&self) -> &'static str {
-        match self {
-            EncodingOrAscii::Ascii => "ASCII",
-            EncodingOrAscii::Encoding(enc) => enc.name(),
-        }
-    }
+/// CJK encodings compared with the dictionary, in order of preference on ties
+#[cfg(feature = "dictionary")]
+static DICTIONARY_ENCODINGS: [&Encoding; 5] = [SHIFT_JIS, GBK, BIG5, EUC_KR, EUC_JP];
 
-    fn encoding(&self) -> &'static Encoding {
-        match self {
-            EncodingOrAscii::Ascii => UTF_8,
-            EncodingOrAscii::Encoding(enc) => enc,
+/// Minimum dictionary score for a CJK encoding to be picked over the guess of chardetng
+#[cfg(feature = "dictionary")]
+const DICTIONARY_THRESHOLD: f64 = 0.8;
+
+/// Pick the CJK encoding whose decoded string uses the most common characters of its language
+///
+/// Returns None unless it scores above the threshold and better than the guessed encoding.
+#[cfg(feature = "dictionary")]
+fn pick_by_dictionary(
+    data: &[u8],
+    hint: &ResolvedDetectionHint,
+    guessed_encoding: EncodingOrAscii,
+) -> Option<EncodingOrAscii> {
+    let score = |encoding: EncodingOrAscii| {
+        let (string, has_errors, _) = encoding.decode(data);
+        if has_errors {
+            return None;
         }
-    }
+        dictionary_score(&string, encoding)
+    };
+    let guessed_score = score(guessed_encoding);
+    DICTIONARY_ENCODINGS
+        .iter()
+        .map(|&encoding| EncodingOrAscii::Encoding(encoding))
+        .filter(|&encoding| hint.allows(encoding))
+        .filter_map(|encoding| score(encoding).map(|s| (encoding, s)))
+        .reduce(|best, candidate| {
+            if candidate.1 > best.1 {
+                candidate
+            } else {
+                best
+            }
+        })
+        .filter(|&(_, s)| s >= DICTIONARY_THRESHOLD && guessed_score.is_none_or(|g| s > g))
+        .map(|(encoding, _)| encoding)
 }
 
-#[derive(Debug, thiserror::Error)]
-#[error("Zip inspect error: {0}")]
-pub enum ZipInspectError {
+#[cfg(not(feature = "dictionary"))]
+fn pick_by_dictionary(
+    _data: &[u8],
+    _hint: &ResolvedDetectionHint,
+    _guessed_encoding: EncodingOrAscii,
+) -> Option<EncodingOrAscii> {
+    None
+}
+
+/// Score the share of common characters in a string decoded with a CJK encoding
+///
+/// Returns None for other encodings and for strings without CJK characters.
+#[cfg(feature = "dictionary")]
+fn dictionary_score(string: &str, encoding: EncodingOrAscii) -> Option<f64> {
+    match encoding {
+        EncodingOrAscii::Encoding(encoding) => Language::for_encoding(encoding)?.score(string),
+        EncodingOrAscii::Ascii | EncodingOrAscii::CodePage(_) => None,
+    }
+}
+
+#[cfg(not(feature = "dictionary"))]
+fn dictionary_score(_string: &str, _encoding: EncodingOrAscii) -> Option<f64> {
+    None
+}
+
+/// Encodings considered when ranking candidates, in order of preference on ties
+static CANDIDATE_ENCODINGS: [EncodingOrAscii; 20] = [
+    EncodingOrAscii::Encoding(UTF_8),
+    EncodingOrAscii::Encoding(SHIFT_JIS),
+    EncodingOrAscii::Encoding(EUC_JP),
+    EncodingOrAscii::Encoding(GBK),
+    EncodingOrAscii::Encoding(BIG5),
+    EncodingOrAscii::Encoding(EUC_KR),
+    EncodingOrAscii::Encoding(WINDOWS_1252),
+    EncodingOrAscii::Encoding(WINDOWS_1250),
+    EncodingOrAscii::Encoding(WINDOWS_1251),
+    EncodingOrAscii::Encoding(WINDOWS_1253),
+    EncodingOrAscii::Encoding(WINDOWS_1254),
+    EncodingOrAscii::Encoding(WINDOWS_1255),
+    EncodingOrAscii::Encoding(WINDOWS_1256),
+    EncodingOrAscii::Encoding(WINDOWS_874),
+    EncodingOrAscii::CodePage(&IBM437),
+    EncodingOrAscii::CodePage(&IBM850),
+    EncodingOrAscii::CodePage(&IBM852),
+    EncodingOrAscii::CodePage(&IBM857),
+    EncodingOrAscii::CodePage(&IBM862),
+    EncodingOrAscii::CodePage(&IBM865),
+];
+
+/// Share of the candidate score given to the encoding guessed by chardetng
+const GUESS_WEIGHT: f64 = 0.2;
+
+/// Rank the candidate encodings for the data, best first
+///
+/// Only the encodings allowed by the hint are ranked, or all the built-in candidates without an allowlist.
+/// Returns each encoding with its score between 0 and 1 and the number of decoding errors.
+fn rank_encodings(data: &[u8], hint: &ResolvedDetectionHint) -> Vec<(EncodingOrAscii, f64, u64)> {
+    if data.is_ascii() {
+        return vec![(EncodingOrAscii::Ascii, 1.0, 0)];
+    }
+
+    let guessed_encoding = guess_encoding(data, hint);
+    let candidates = if hint.allowed_encodings.is_empty() {
+        &CANDIDATE_ENCODINGS[..]
+    } else {
+        &hint.allowed_encodings[..]
+    };
+    let mut ranked = candidates
+        .iter()
+        .map(|&encoding| {
+            let (score, error_count) = score_candidate(data, encoding, guessed_encoding);
+            (encoding, score, error_count)
+        })
+        .collect::<Vec<_>>();
+    // Stable sort, so ties keep the order of preference
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+    ranked
+}
+
+/// The guess of chardetng for the data, which candidates decoding without errors get a bonus for
+fn guess_encoding(data: &[u8], hint: &ResolvedDetectionHint) -> &'static Encoding {
+    let mut detector = EncodingDetector::new();
+    detector.feed(data, true);
+    detector.guess(
+        hint.tld.as_deref().map(str::as_bytes),
+        hint.allows(EncodingOrAscii::Encoding(UTF_8)),
+    )
+}
+
+/// Score a candidate encoding for the data, returning the score between 0 and 1 and the number of
+/// decoding errors
+fn score_candidate(
+    data: &[u8],
+    encoding: EncodingOrAscii,
+    guessed_encoding: &'static Encoding,
+) -> (f64, u64) {
+    if data.is_ascii() {
+        return (1.0, 0);
+    }
+    let (string, _, _) = encoding.decode(data);
+    let error_count = string.chars().filter(|&c| c == '\u{FFFD}').count() as u64;
+    // chardetng uses frequency statistics that character classes cannot capture
+    let guessed = encoding == EncodingOrAscii::Encoding(guessed_encoding) && error_count == 0;
+    // Decodes using rare characters are implausible even if they form valid letters
+    let plausibility = score_decoded(&string) * dictionary_score(&string, encoding).unwrap_or(1.0);
+    let score = plausibility * (1.0 - GUESS_WEIGHT) + if guessed { GUESS_WEIGHT } else { 0.0 };
+    (score, error_count)
+}
+
+/// Build the top candidates for the data, with previews decoded from the preview bytes
+fn top_encoding_candidates(
+    data: &[u8],
+    preview_bytes: &[u8],
+    count: usize,
+    hint: &ResolvedDetectionHint,
+) -> Vec<EncodingCandidate> {
+    rank_encodings(data, hint)
+        .into_iter()
+        .take(count)
+        .map(|(encoding, score, error_count)| EncodingCandidate {
+            encoding: encoding.name().to_string(),
+            score,
+            error_count,
+            preview: encoding.decode(preview_bytes).0,
+        })
+        .collect()
+}
+
+/// Writing system of a letter, used to penalize decoded text mixing unrelated scripts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Script {
+    Latin,
+    Greek,
+    Cyrillic,
+    Hebrew,
+    Arabic,
+    Thai,
+    Hangul,
+    /// Han and Kana, as both appear together in Japanese
+    Cjk,
+    Other,
+}
+
+impl Script {
+    fn of(c: char) -> Self {
+        match c as u32 {
+            0x00C0..=0x024F | 0x1E00..=0x1EFF => Script::Latin,
+            0x0370..=0x03FF => Script::Greek,
+            0x0400..=0x052F => Script::Cyrillic,
+            0x0590..=0x05FF => Script::Hebrew,
+            0x0600..=0x06FF | 0x0750..=0x077F | 0xFB50..=0xFDFF | 0xFE70..=0xFEFF => Script::Arabic,
+            0x0E00..=0x0E7F => Script::Thai,
+            0x1100..=0x11FF | 0x3130..=0x318F | 0xAC00..=0xD7AF => Script::Hangul,
+            0x3040..=0x30FF
+            | 0x31F0..=0x31FF
+            | 0x3400..=0x4DBF
+            | 0x4E00..=0x9FFF
+            | 0xF900..=0xFAFF
+            | 0xFF66..=0xFF9F
+            | 0x20000..=0x3FFFF => Script::Cjk,
+            _ => Script::Other,
+        }
+    }
+}
+
+/// Score how plausible the data decoded with the encoding is, None if decoding fails
+fn score_encoding(data: &[u8], encoding: EncodingOrAscii) -> Option<f64> {
+    let (string, has_errors, _) = encoding.decode(data);
+    (!has_errors).then(|| score_decoded(&string))
+}
+
+/// Score how plausible a decoded string is as human-written text, between 0 and 1
+///
+/// Only non-ASCII characters are considered, as all candidates decode ASCII the same way.
+fn score_decoded(string: &str) -> f64 {
+    let mut total = 0usize;
+    let mut weight = 0.0;
+    let mut scripts: Vec<(Script, usize)> = Vec::new();
+    let mut previous_script = None;
+    for c in string.chars() {
+        if c.is_ascii() {
+            previous_script = None;
+            continue;
+        }
+        total += 1;
+        let script = c.is_alphabetic().then(|| Script::of(c));
+        weight += if c == '\u{FFFD}' || c.is_control() || ('\u{E000}'..='\u{F8FF}').contains(&c) {
+            -1.0
+        } else if let Some(script) = script {
+            match scripts.iter_mut().find(|(s, _)| *s == script) {
+                Some((_, count)) => *count += 1,
+                None => scripts.push((script, 1)),
+            }
+            // Halfwidth Katakana is rare in names, but common when decoding other DBCS as Shift_JIS.
+            // Likewise, accented Latin letters are usually surrounded by ASCII ones, while text in
+            // other scripts decoded as Latin yields runs of them.
+            if ('\u{FF66}'..='\u{FF9F}').contains(&c)
+                || (script == Script::Latin && previous_script == Some(Script::Latin))
+            {
+                0.5
+            } else {
+                1.0
+            }
+        } else if c.is_numeric() {
+            1.0
+        } else {
+            // Symbols and punctuation
+            0.25
+        };
+        previous_script = script;
+    }
+    if total == 0 {
+        return 1.0;
+    }
+
+    let letters = scripts.iter().map(|(_, count)| count).sum::<usize>();
+    let consistency = scripts
+        .iter()
+        .map(|(_, count)| *count)
+        .max()
+        .map_or(1.0, |dominant| dominant as f64 / letters as f64);
+    (weight / total as f64).max(0.0) * consistency
+}
+
+/// Maximum number of double encoding layers to undo
+const MAX_DOUBLE_ENCODING_DEPTH: usize = 3;
+
+/// Detect a name that was decoded with a wrong encoding and re-encoded as UTF-8, possibly repeatedly
+///
+/// Returns the repaired string and the encodings involved, or None if the name does not look double-encoded.
+fn detect_double_encoding(string: &str) -> Option<DoubleEncodingRepair> {
+    let mut repaired = string.to_string();
+    let mut encoding_chain = Vec::new();
+    while encoding_chain.len() < MAX_DOUBLE_ENCODING_DEPTH
+        && let Some((next, encoding)) = undo_double_encoding(&repaired)
+    {
+        repaired = next;
+        encoding_chain.push(encoding.name().to_string());
+    }
+
+    (!encoding_chain.is_empty()).then_some(DoubleEncodingRepair {
+        repaired,
+        encoding_chain,
+    })
+}
+
+/// Undo one layer of double encoding
+///
+/// The string is encoded back with each candidate encoding in turn. A candidate is accepted only if
+/// the resulting bytes are valid non-ASCII UTF-8 and decoding them with the candidate gives back the
+/// original string, which is very unlikely for names that are not double-encoded.
+fn undo_double_encoding(string: &str) -> Option<(String, EncodingOrAscii)> {
+    if string.is_ascii() {
+        return None;
+    }
+
+    let candidates = [
+        EncodingOrAscii::Encoding(WINDOWS_1252),
+        EncodingOrAscii::CodePage(&IBM437),
+        EncodingOrAscii::CodePage(&IBM850),
+        EncodingOrAscii::Encoding(SHIFT_JIS),
+        EncodingOrAscii::Encoding(GBK),
+        EncodingOrAscii::Encoding(EUC_KR),
+        EncodingOrAscii::Encoding(BIG5),
+    ];
+    candidates.into_iter().find_map(|encoding| {
+        let bytes = encoding.encode(string)?;
+        let repaired = str::from_utf8(&bytes).ok()?;
+        if repaired.is_ascii() || repaired.chars().any(char::is_control) {
+            return None;
+        }
+
+        let (decoded, has_errors, _) = encoding.decode(&bytes);
+        (!has_errors && decoded == string).then(|| (repaired.to_string(), encoding))
+    })
+}
+
+/// Encoding or ASCII marker
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum EncodingOrAscii {
+    Ascii,
+    Encoding(&'static Encoding),
+    CodePage(&'static CodePage),
+}
+
+impl EncodingOrAscii {
+    /// Look up an encoding by label, trying encoding_rs first and then the built-in code pages
+    pub(crate) fn for_label(label: &str) -> Option<Self> {
+        Encoding::for_label(label.as_bytes())
+            .map(EncodingOrAscii::Encoding)
+            .or_else(|| CodePage::for_label(label.as_bytes()).map(EncodingOrAscii::CodePage))
+    }
+
+    /// Decode bytes, returning the string, whether there were errors, and the encoding actually used
+    fn decode(&self, data: &[u8]) -> (String, bool, EncodingOrAscii) {
+        let encoding = match self {
+            EncodingOrAscii::Ascii => UTF_8,
+            EncodingOrAscii::Encoding(enc) => enc,
+            EncodingOrAscii::CodePage(code_page) => {
+                let (string, has_errors) = code_page.decode(data);
+                return (string, has_errors, *self);
+            }
+        };
+
+        let (result, encoding_used, has_errors) = encoding.decode(data);
+        (
+            result.into_owned(),
+            has_errors,
+            EncodingOrAscii::Encoding(encoding_used),
+        )
+    }
+
+    /// Decode bytes like [`EncodingOrAscii::decode`], locating the malformed byte sequences
+    fn decode_with_errors(&self, data: &[u8]) -> (String, Vec<DecodingError>, EncodingOrAscii) {
+        let encoding = match self {
+            EncodingOrAscii::Ascii => UTF_8,
+            EncodingOrAscii::Encoding(enc) => enc,
+            EncodingOrAscii::CodePage(code_page) => {
+                // Single-byte, so characters and bytes line up
+                let (string, _) = code_page.decode(data);
+                let errors = string
+                    .chars()
+                    .enumerate()
+                    .filter(|&(_, c)| c == char::REPLACEMENT_CHARACTER)
+                    .map(|(index, _)| DecodingError {
+                        byte_start: index as u64,
+                        byte_end: index as u64 + 1,
+                        bytes: vec![data[index]],
+                        char_index: index as u64,
+                    })
+                    .collect();
+                return (string, errors, *self);
+            }
+        };
+
+        // Same BOM sniffing as Encoding::decode
+        let (encoding, bom_length) = Encoding::for_bom(data).unwrap_or((encoding, 0));
+        let mut decoder = encoding.new_decoder_without_bom_handling();
+        let mut string = String::with_capacity(data.len());
+        let mut errors = Vec::new();
+        let mut char_count = 0;
+        let mut read = bom_length;
+        loop {
+            let before = string.len();
+            let (result, bytes_read) =
+                decoder.decode_to_string_without_replacement(&data[read..], &mut string, true);
+            read += bytes_read;
+            char_count += string[before..].chars().count();
+            match result {
+                DecoderResult::InputEmpty => break,
+                DecoderResult::OutputFull => string.reserve(data.len() - read + 16),
+                DecoderResult::Malformed(malformed, consumed) => {
+                    // The malformed sequence ends before the bytes consumed after it
+                    let end = read - consumed as usize;
+                    let start = end - malformed as usize;
+                    errors.push(DecodingError {
+                        byte_start: start as u64,
+                        byte_end: end as u64,
+                        bytes: data[start..end].to_vec(),
+                        char_index: char_count as u64,
+                    });
+                    string.push(char::REPLACEMENT_CHARACTER);
+                    char_count += 1;
+                }
+            }
+        }
+        (string, errors, EncodingOrAscii::Encoding(encoding))
+    }
+
+    /// Encode a string, returning None if it contains unmappable characters
+    pub(crate) fn encode(&self, string: &str) -> Option<Vec<u8>> {
+        match self {
+            EncodingOrAscii::Ascii => string.is_ascii().then(|| string.as_bytes().to_vec()),
+            EncodingOrAscii::Encoding(enc) => {
+                let (bytes, _, has_unmappable) = enc.encode(string);
+                (!has_unmappable).then(|| bytes.into_owned())
+            }
+            EncodingOrAscii::CodePage(code_page) => code_page.encode(string),
+        }
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            EncodingOrAscii::Ascii => "ASCII",
+            EncodingOrAscii::Encoding(enc) => enc.name(),
+            EncodingOrAscii::CodePage(code_page) => code_page.name(),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Zip inspect error: {0}")]
+pub enum ZipInspectError {
     #[error("Encoding '{0}' not found")]
     EncodingNotFound(String),
+    #[error("Invalid locale hint '{0}'")]
+    InvalidLocale(String),
+    #[error("Entry index {0} is out of range")]
+    EntryIndexOutOfRange(u64),
+    #[error("Path prefix '{prefix}' cannot be encoded in {encoding}")]
+    PrefixNotEncodable { prefix: String, encoding: String },
 }
 
 #[cfg(test)]
@@ -576,8 +2365,9 @@ mod tests {
     use super::*;
     use crate::zip::parse::{
         CentralDirectoryHeader, EndOfCentralDirectory, GeneralPurposeBitFlag, LocalFileHeader,
-        UnicodePathExtraField, ZipFile, ZipFileEntry,
+        UnicodeCommentExtraField, UnicodePathExtraField, ZipFile, ZipFileEntry,
     };
+    use crate::zip::substitution::SubstitutionPreset;
 
     fn create_mock_entry(
         filename: &[u8],
@@ -608,6 +2398,7 @@ mod tests {
             file_comment: vec![],
             zip64: None,
             unicode_path: unicode_path.clone(),
+            unicode_comment: None,
         };
         let lfh = LocalFileHeader {
             signature: 0x04034b50,
@@ -639,6 +2430,7 @@ mod tests {
     fn create_mock_zip(entries: Vec<ZipFileEntry>) -> ZipFile {
         ZipFile {
             size: 0,
+            prefix_length: 0,
             eocd: EndOfCentralDirectory {
                 signature: 0x06054b50,
                 disk_number: 0,
@@ -656,112 +2448,941 @@ mod tests {
     }
 
     #[test]
-    fn test_inspect_empty_zip() {
-        let zip = create_mock_zip(vec![]);
+    fn test_inspect_empty_zip() {
+        let zip = create_mock_zip(vec![]);
+        let config = InspectConfig {
+            encoding: EncodingSelectionStrategy::EntryDetected {
+                fallback_encoding: None,
+                ignore_utf8_flag: false,
+                detection_hint: DetectionHint::default(),
+            },
+            field_selection_strategy: FieldSelectionStrategy::default(),
+            ignore_crc32_mismatch: false,
+            needs_original_bytes: false,
+            wave_dash_handling: WaveDashHandling::default(),
+            wave_dash_normalization: WaveDashNormalization::default(),
+            ..Default::default()
+        };
+        let result = InspectedArchive::inspect(&zip, &config);
+        assert!(result.is_ok());
+        let inspected = result.unwrap();
+        assert_eq!(inspected.entries.len(), 0);
+    }
+
+    #[test]
+    fn test_inspect_utf8_entry() {
+        let entry = create_mock_entry("test.txt".as_bytes(), true, None);
+        let zip = create_mock_zip(vec![entry]);
+        let config = InspectConfig {
+            encoding: EncodingSelectionStrategy::EntryDetected {
+                fallback_encoding: None,
+                ignore_utf8_flag: false,
+                detection_hint: DetectionHint::default(),
+            },
+            field_selection_strategy: FieldSelectionStrategy::default(),
+            ignore_crc32_mismatch: false,
+            needs_original_bytes: false,
+            wave_dash_handling: WaveDashHandling::default(),
+            wave_dash_normalization: WaveDashNormalization::default(),
+            ..Default::default()
+        };
+        let result = InspectedArchive::inspect(&zip, &config);
+        assert!(result.is_ok());
+        let inspected = result.unwrap();
+        assert_eq!(inspected.entries.len(), 1);
+        let filename = &inspected.entries[0].filename;
+        assert!(filename.utf8_flag);
+        assert_eq!(filename.decoded.as_ref().unwrap().string, "test.txt");
+        assert_eq!(filename.decoded.as_ref().unwrap().encoding_used, "UTF-8");
+    }
+
+    #[test]
+    fn test_inspect_sjis_entry() {
+        // "テスト.txt" in Shift_JIS
+        let sjis_bytes = b"\x83\x65\x83\x58\x83\x67.txt";
+        let entry = create_mock_entry(sjis_bytes, false, None);
+        let zip = create_mock_zip(vec![entry]);
+        let config = InspectConfig {
+            encoding: EncodingSelectionStrategy::EntryDetected {
+                fallback_encoding: Some("Shift_JIS".to_string()),
+                ignore_utf8_flag: false,
+                detection_hint: DetectionHint::default(),
+            },
+            field_selection_strategy: FieldSelectionStrategy::default(),
+            ignore_crc32_mismatch: false,
+            needs_original_bytes: false,
+            wave_dash_handling: WaveDashHandling::default(),
+            wave_dash_normalization: WaveDashNormalization::default(),
+            ..Default::default()
+        };
+        let result = InspectedArchive::inspect(&zip, &config);
+        assert!(result.is_ok());
+        let inspected = result.unwrap();
+        assert_eq!(inspected.entries.len(), 1);
+        let filename = &inspected.entries[0].filename;
+        assert!(!filename.utf8_flag);
+        assert_eq!(filename.decoded.as_ref().unwrap().string, "テスト.txt");
+        assert_eq!(
+            filename.decoded.as_ref().unwrap().encoding_used,
+            "Shift_JIS"
+        );
+    }
+
+    #[test]
+    fn test_inspect_fallback_over_code_page() {
+        // "テスト" in Shift_JIS followed by a truncated double-byte character, which no detected
+        // encoding decodes cleanly
+        let sjis_bytes = b"\x83\x65\x83\x58\x83\x67\x81 .txt";
+        let entry = create_mock_entry(sjis_bytes, false, None);
+        let zip = create_mock_zip(vec![entry]);
+
+        // Without a fallback, the name is decoded with a DOS code page
+        let inspected = InspectedArchive::inspect(&zip, &InspectConfig::default()).unwrap();
+        assert_eq!(inspected.overall_encoding.as_deref(), Some("IBM437"));
+
+        for encoding in [
+            EncodingSelectionStrategy::PreferOverallDetected {
+                fallback_encoding: Some("Shift_JIS".to_string()),
+                ignore_utf8_flag: false,
+                detection_hint: DetectionHint::default(),
+            },
+            EncodingSelectionStrategy::EntryDetected {
+                fallback_encoding: Some("Shift_JIS".to_string()),
+                ignore_utf8_flag: false,
+                detection_hint: DetectionHint::default(),
+            },
+        ] {
+            let config = InspectConfig {
+                encoding,
+                ..Default::default()
+            };
+            let inspected = InspectedArchive::inspect(&zip, &config).unwrap();
+            assert_eq!(inspected.overall_encoding, None);
+            let decoded = inspected.entries[0].filename.decoded.as_ref().unwrap();
+            assert_eq!(decoded.encoding_used, "Shift_JIS");
+            assert!(decoded.string.starts_with("テスト"));
+            assert!(decoded.has_errors);
+        }
+    }
+
+    #[test]
+    fn test_inspect_unicode_path_extra_field() {
+        let unicode_path = UnicodePathExtraField {
+            version: 1,
+            name_crc32: 0,
+            data: "unicode.txt".as_bytes().to_vec(),
+            decoded_string: Some("unicode.txt".to_string()),
+            crc32_matched: true,
+        };
+        let entry = create_mock_entry(b"ascii.txt", false, Some(unicode_path));
+        let zip = create_mock_zip(vec![entry]);
+        let config = InspectConfig {
+            encoding: EncodingSelectionStrategy::EntryDetected {
+                fallback_encoding: None,
+                ignore_utf8_flag: false,
+                detection_hint: DetectionHint::default(),
+            },
+            field_selection_strategy: FieldSelectionStrategy::CdhUnicodeThenCdh,
+            ignore_crc32_mismatch: false,
+            needs_original_bytes: false,
+            wave_dash_handling: WaveDashHandling::default(),
+            wave_dash_normalization: WaveDashNormalization::default(),
+            ..Default::default()
+        };
+        let result = InspectedArchive::inspect(&zip, &config);
+        assert!(result.is_ok());
+        let inspected = result.unwrap();
+        assert_eq!(inspected.entries.len(), 1);
+        let filename = &inspected.entries[0].filename;
+        assert_eq!(
+            filename.kind,
+            InspectedFilenameFieldKind::CdhUnicodePathExtraField
+        );
+        assert_eq!(filename.decoded.as_ref().unwrap().string, "unicode.txt");
+    }
+
+    #[test]
+    fn test_inspect_compare_filename_sources() {
+        // "資料.txt" in Shift_JIS with a matching Unicode Path extra field in both headers
+        let unicode_path = UnicodePathExtraField {
+            version: 1,
+            name_crc32: 0,
+            data: "資料.txt".as_bytes().to_vec(),
+            decoded_string: Some("資料.txt".to_string()),
+            crc32_matched: true,
+        };
+        let entry1 = create_mock_entry(b"\x8e\x91\x97\xbf.txt", false, Some(unicode_path));
+        // A stale Unicode Path extra field in the CDH only, and another name in the LFH
+        let unicode_path = UnicodePathExtraField {
+            version: 1,
+            name_crc32: 0,
+            data: b"old.txt".to_vec(),
+            decoded_string: Some("old.txt".to_string()),
+            crc32_matched: false,
+        };
+        let mut entry2 = create_mock_entry(b"new.txt", false, Some(unicode_path));
+        entry2.lfh.filename = b"NEW.TXT".to_vec();
+        entry2.lfh.unicode_path = None;
+        let zip = create_mock_zip(vec![entry1, entry2]);
+        let mut config = InspectConfig {
+            encoding: EncodingSelectionStrategy::ForceSpecified {
+                encoding: "Shift_JIS".to_string(),
+                ignore_utf8_flag: false,
+            },
+            ..Default::default()
+        };
+
+        let inspected = InspectedArchive::inspect(&zip, &config).unwrap();
+        assert!(inspected.entries[1].filename.sources.is_empty());
+        assert!(inspected.entries[1].filename.mismatches.is_empty());
+        assert!(inspected.mismatched_source_entries.is_empty());
+
+        config.compare_filename_sources = true;
+        let inspected = InspectedArchive::inspect(&zip, &config).unwrap();
+        assert_eq!(inspected.mismatched_source_entries, [1]);
+
+        let filename = &inspected.entries[0].filename;
+        let names = filename
+            .sources
+            .iter()
+            .map(|s| (s.kind, s.decoded.as_ref().unwrap().string.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                (InspectedFilenameFieldKind::CdhFilename, "資料.txt"),
+                (InspectedFilenameFieldKind::LfhFilename, "資料.txt"),
+                (
+                    InspectedFilenameFieldKind::CdhUnicodePathExtraField,
+                    "資料.txt"
+                ),
+                (
+                    InspectedFilenameFieldKind::LfhUnicodePathExtraField,
+                    "資料.txt"
+                ),
+            ]
+        );
+        assert!(filename.mismatches.is_empty());
+
+        let filename = &inspected.entries[1].filename;
+        assert_eq!(filename.kind, InspectedFilenameFieldKind::CdhFilename);
+        assert_eq!(filename.sources.len(), 3);
+        assert_eq!(filename.sources[2].crc32_matched, Some(false));
+        assert_eq!(
+            filename.mismatches,
+            [
+                FilenameMismatch::CdhLfhFilename,
+                FilenameMismatch::CdhLfhUnicodePath,
+                FilenameMismatch::UnicodePathCrc32 {
+                    kind: InspectedFilenameFieldKind::CdhUnicodePathExtraField
+                },
+                FilenameMismatch::DecodedName {
+                    kind: InspectedFilenameFieldKind::LfhFilename
+                },
+                FilenameMismatch::DecodedName {
+                    kind: InspectedFilenameFieldKind::CdhUnicodePathExtraField
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_inspect_compare_filename_sources_legacy_encoding() {
+        // Legacy Shift_JIS names with a matching Unicode Path extra field on every entry, as written by 7-Zip
+        let entry_with_unicode_path = |legacy_name: &[u8], name: &str| {
+            let unicode_path = UnicodePathExtraField {
+                version: 1,
+                name_crc32: 0,
+                data: name.as_bytes().to_vec(),
+                decoded_string: Some(name.to_string()),
+                crc32_matched: true,
+            };
+            create_mock_entry(legacy_name, false, Some(unicode_path))
+        };
+        let zip = create_mock_zip(vec![
+            entry_with_unicode_path(b"\x8e\x91\x97\xbf.txt", "資料.txt"),
+            entry_with_unicode_path(
+                b"\x83e\x83X\x83g\x82\xcc\x83t\x83@\x83C\x83\x8b.txt",
+                "テストのファイル.txt",
+            ),
+        ]);
+        let config = InspectConfig {
+            compare_filename_sources: true,
+            ..Default::default()
+        };
+
+        let inspected = InspectedArchive::inspect(&zip, &config).unwrap();
+        // The selected names are the Unicode Path extra fields, which are UTF-8
+        assert_eq!(inspected.overall_encoding.as_deref(), Some("UTF-8"));
+        assert!(inspected.mismatched_source_entries.is_empty());
+        for entry in &inspected.entries {
+            assert_eq!(
+                entry.filename.kind,
+                InspectedFilenameFieldKind::CdhUnicodePathExtraField
+            );
+            assert!(entry.filename.mismatches.is_empty());
+            let legacy = entry.filename.sources[0].decoded.as_ref().unwrap();
+            assert_eq!(legacy.encoding_used, "Shift_JIS");
+            assert!(!legacy.has_errors);
+        }
+    }
+
+    #[test]
+    fn test_inspect_comments() {
+        // "これはテスト用のコメントです" in Shift_JIS
+        let sjis_comment = b"\x82\xb1\x82\xea\x82\xcd\x83\x65\x83\x58\x83\x67\x97\x70\x82\xcc\x83\x52\x83\x81\x83\x93\x83\x67\x82\xc5\x82\xb7";
+        let mut sjis_entry = create_mock_entry(b"sjis.txt", false, None);
+        sjis_entry.cdh.file_comment = sjis_comment.to_vec();
+        let mut unicode_entry = create_mock_entry(b"unicode.txt", false, None);
+        unicode_entry.cdh.file_comment = b"?".to_vec();
+        unicode_entry.cdh.unicode_comment = Some(UnicodeCommentExtraField {
+            version: 1,
+            comment_crc32: 0,
+            data: "コメント".as_bytes().to_vec(),
+            decoded_string: Some("コメント".to_string()),
+            crc32_matched: true,
+        });
+        let plain_entry = create_mock_entry(b"plain.txt", false, None);
+        let zip = create_mock_zip(vec![sjis_entry, unicode_entry, plain_entry]);
+        let mut config = InspectConfig::default();
+
+        let inspected = InspectedArchive::inspect(&zip, &config).unwrap();
+        // Filenames are ASCII, but comments are detected separately
+        assert_eq!(inspected.overall_encoding.as_deref(), Some("ASCII"));
+        let comment = inspected.entries[0].comment.as_ref().unwrap();
+        assert_eq!(comment.kind, InspectedCommentFieldKind::CdhComment);
+        let decoded = comment.decoded.as_ref().unwrap();
+        assert_eq!(decoded.string, "これはテスト用のコメントです");
+        assert_eq!(decoded.encoding_used, "Shift_JIS");
+        let comment = inspected.entries[1].comment.as_ref().unwrap();
+        assert_eq!(
+            comment.kind,
+            InspectedCommentFieldKind::CdhUnicodeCommentExtraField
+        );
+        assert_eq!(comment.decoded.as_ref().unwrap().string, "コメント");
+        assert!(inspected.entries[2].comment.is_none());
+        assert!(inspected.comment.is_none());
+
+        // The Unicode Comment extra field can be ignored
+        config.comment_field_selection_strategy = CommentFieldSelectionStrategy::CdhOnly;
+        let inspected = InspectedArchive::inspect(&zip, &config).unwrap();
+        let comment = inspected.entries[1].comment.as_ref().unwrap();
+        assert_eq!(comment.kind, InspectedCommentFieldKind::CdhComment);
+        assert_eq!(comment.decoded.as_ref().unwrap().string, "?");
+    }
+
+    #[test]
+    fn test_inspect_archive_comment() {
+        let entry = create_mock_entry(b"test.txt", true, None);
+        let mut zip = create_mock_zip(vec![entry]);
+        // "これはテスト用のコメントです" in Shift_JIS
+        zip.eocd.comment = b"\x82\xb1\x82\xea\x82\xcd\x83\x65\x83\x58\x83\x67\x97\x70\x82\xcc\x83\x52\x83\x81\x83\x93\x83\x67\x82\xc5\x82\xb7".to_vec();
+        let config = InspectConfig {
+            needs_original_bytes: true,
+            ..Default::default()
+        };
+
+        let inspected = InspectedArchive::inspect(&zip, &config).unwrap();
+        let comment = inspected.comment.unwrap();
+        assert_eq!(comment.detected_encoding.as_deref(), Some("Shift_JIS"));
+        assert_eq!(comment.original_bytes.as_ref(), Some(&zip.eocd.comment));
+        let decoded = comment.decoded.unwrap();
+        assert_eq!(decoded.string, "これはテスト用のコメントです");
+        assert!(!decoded.has_errors);
+    }
+
+    #[test]
+    fn test_inspect_dos_code_page() {
+        // "für.txt" and "Übersicht.txt" in CP437, which are invalid in Windows-1252
+        let entry1 = create_mock_entry(b"f\x81r.txt", false, None);
+        let entry2 = create_mock_entry(b"\x9abersicht.txt", false, None);
+        let zip = create_mock_zip(vec![entry1, entry2]);
+        let mut config = InspectConfig::default();
+
+        let inspected = InspectedArchive::inspect(&zip, &config).unwrap();
+        assert_eq!(inspected.overall_encoding.as_deref(), Some("IBM437"));
+        let decoded = inspected.entries[1].filename.decoded.as_ref().unwrap();
+        assert_eq!(decoded.string, "Übersicht.txt");
+        assert_eq!(decoded.encoding_used, "IBM437");
+
+        // Code pages can be forced by label
+        config.encoding = EncodingSelectionStrategy::ForceSpecified {
+            encoding: "cp850".to_string(),
+            ignore_utf8_flag: false,
+        };
+        let inspected = InspectedArchive::inspect(&zip, &config).unwrap();
+        let decoded = inspected.entries[0].filename.decoded.as_ref().unwrap();
+        assert_eq!(decoded.string, "für.txt");
+        assert_eq!(decoded.encoding_used, "IBM850");
+    }
+
+    #[test]
+    fn test_detect_double_encoding() {
+        let repair = |s| detect_double_encoding(s).map(|r| (r.repaired, r.encoding_chain));
+        assert_eq!(
+            repair("cafÃ©.txt"),
+            Some(("café.txt".to_string(), vec!["windows-1252".to_string()]))
+        );
+        assert_eq!(
+            repair("Gr├╝├ƒe.txt"),
+            Some(("Grüße.txt".to_string(), vec!["IBM437".to_string()]))
+        );
+        assert_eq!(
+            repair("繝輔ぃ繧､繝ｫ.txt"),
+            Some(("ファイル.txt".to_string(), vec!["Shift_JIS".to_string()]))
+        );
+        assert_eq!(
+            repair("cafÃƒÂ©.txt"),
+            Some((
+                "café.txt".to_string(),
+                vec!["windows-1252".to_string(), "windows-1252".to_string()]
+            ))
+        );
+
+        // Correctly encoded names are left alone
+        assert_eq!(repair("café.txt"), None);
+        assert_eq!(repair("ファイル.txt"), None);
+        assert_eq!(repair("Łódź.txt"), None);
+        assert_eq!(repair("readme.txt"), None);
+    }
+
+    #[test]
+    fn test_inspect_double_encoding() {
+        let entry1 = create_mock_entry("蜀咏悄/縺ｧ.txt".as_bytes(), true, None);
+        let entry2 = create_mock_entry("写真/で.txt".as_bytes(), true, None);
+        let zip = create_mock_zip(vec![entry1, entry2]);
+        let config = InspectConfig::default();
+
+        let inspected = InspectedArchive::inspect(&zip, &config).unwrap();
+        let repair = inspected.entries[0]
+            .filename
+            .double_encoding_repair
+            .as_ref()
+            .unwrap();
+        assert_eq!(repair.repaired, "写真/で.txt");
+        assert_eq!(repair.encoding_chain, ["Shift_JIS"]);
+        assert!(
+            inspected.entries[1]
+                .filename
+                .double_encoding_repair
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_inspect_unicode_normalization() {
+        // "が.txt" decomposed as written by macOS, and the same name precomposed
+        let entry1 = create_mock_entry("\u{304B}\u{3099}.txt".as_bytes(), true, None);
+        let entry2 = create_mock_entry("\u{304C}.txt".as_bytes(), true, None);
+        let zip = create_mock_zip(vec![entry1, entry2]);
+        let mut config = InspectConfig {
+            unicode_normalization: UnicodeNormalization::Preserve,
+            ..Default::default()
+        };
+
+        let decoded_names = |inspected: &InspectedArchive| {
+            inspected
+                .entries
+                .iter()
+                .map(|e| e.filename.decoded.as_ref().unwrap().string.clone())
+                .collect::<Vec<_>>()
+        };
+
+        let inspected = InspectedArchive::inspect(&zip, &config).unwrap();
+        assert_eq!(
+            decoded_names(&inspected),
+            ["\u{304B}\u{3099}.txt", "\u{304C}.txt"]
+        );
+        assert!(inspected.unicode_normalized_entries.is_empty());
+
+        config.unicode_normalization = UnicodeNormalization::Nfc;
+        let inspected = InspectedArchive::inspect(&zip, &config).unwrap();
+        assert_eq!(decoded_names(&inspected), ["\u{304C}.txt", "\u{304C}.txt"]);
+        assert_eq!(inspected.unicode_normalized_entries, [0]);
+
+        config.unicode_normalization = UnicodeNormalization::Nfd;
+        let inspected = InspectedArchive::inspect(&zip, &config).unwrap();
+        assert_eq!(
+            decoded_names(&inspected),
+            ["\u{304B}\u{3099}.txt", "\u{304B}\u{3099}.txt"]
+        );
+        assert_eq!(inspected.unicode_normalized_entries, [1]);
+    }
+
+    #[test]
+    fn test_unicode_normalization_nfkc() {
+        assert_eq!(
+            UnicodeNormalization::Nfkc.normalize("\u{FF76}\u{FF9E}\u{2460}"),
+            Some("\u{30AC}1".to_string())
+        );
+        assert_eq!(UnicodeNormalization::Nfkc.normalize("abc"), None);
+        assert_eq!(UnicodeNormalization::Preserve.normalize("\u{FF76}"), None);
+    }
+
+    #[test]
+    fn test_inspect_backslash_separator() {
+        // "表\表.txt" and "表.txt" in Shift_JIS, where "表" is 0x95 0x5C
+        let entry1 = create_mock_entry(b"\x95\x5c\x5c\x95\x5c.txt", false, None);
+        let entry2 = create_mock_entry(b"\x95\x5c.txt", false, None);
+        let zip = create_mock_zip(vec![entry1, entry2]);
+        let mut config = InspectConfig {
+            encoding: EncodingSelectionStrategy::ForceSpecified {
+                encoding: "Shift_JIS".to_string(),
+                ignore_utf8_flag: false,
+            },
+            backslash_handling: BackslashHandling::Preserve,
+            ..Default::default()
+        };
+
+        let inspected = InspectedArchive::inspect(&zip, &config).unwrap();
+        assert_eq!(
+            inspected.entries[0]
+                .filename
+                .decoded
+                .as_ref()
+                .unwrap()
+                .string,
+            "表\\表.txt"
+        );
+        assert_eq!(inspected.backslash_entries, [0]);
+
+        config.backslash_handling = BackslashHandling::ConvertToSlash;
+        let inspected = InspectedArchive::inspect(&zip, &config).unwrap();
+        assert_eq!(
+            inspected.entries[0]
+                .filename
+                .decoded
+                .as_ref()
+                .unwrap()
+                .string,
+            "表/表.txt"
+        );
+        assert_eq!(
+            inspected.entries[1]
+                .filename
+                .decoded
+                .as_ref()
+                .unwrap()
+                .string,
+            "表.txt"
+        );
+        assert_eq!(inspected.backslash_entries, [0]);
+    }
+
+    #[test]
+    fn test_inspect_encoding_overrides() {
+        // "写真/a.txt" in Shift_JIS, merged with "中文/文件.txt" and "中文/b.txt" in GBK
+        let entry1 = create_mock_entry(b"\x8e\xca\x90^/a.txt", false, None);
+        let entry2 = create_mock_entry(b"\xd6\xd0\xce\xc4/\xce\xc4\xbc\xfe.txt", false, None);
+        let entry3 = create_mock_entry(b"\xd6\xd0\xce\xc4/b.txt", false, None);
+        let zip = create_mock_zip(vec![entry1, entry2, entry3]);
         let config = InspectConfig {
-            encoding: EncodingSelectionStrategy::EntryDetected {
-                fallback_encoding: None,
+            encoding: EncodingSelectionStrategy::ForceSpecified {
+                encoding: "Shift_JIS".to_string(),
                 ignore_utf8_flag: false,
             },
-            field_selection_strategy: FieldSelectionStrategy::default(),
-            ignore_crc32_mismatch: false,
-            needs_original_bytes: false,
-            wave_dash_handling: WaveDashHandling::default(),
-            wave_dash_normalization: WaveDashNormalization::default(),
+            encoding_overrides: vec![
+                EncodingOverride {
+                    target: EncodingOverrideTarget::Index { index: 2 },
+                    encoding: "windows-1252".to_string(),
+                },
+                EncodingOverride {
+                    target: EncodingOverrideTarget::PathPrefix {
+                        prefix: "中文/".to_string(),
+                    },
+                    encoding: "GBK".to_string(),
+                },
+            ],
+            ..Default::default()
         };
-        let result = InspectedArchive::inspect(&zip, &config);
-        assert!(result.is_ok());
-        let inspected = result.unwrap();
-        assert_eq!(inspected.entries.len(), 0);
+
+        let inspected = InspectedArchive::inspect(&zip, &config).unwrap();
+        let decoded = inspected
+            .entries
+            .iter()
+            .map(|e| {
+                let decoded = e.filename.decoded.as_ref().unwrap();
+                (decoded.string.as_str(), decoded.encoding_used.as_str())
+            })
+            .collect::<Vec<_>>();
+        // The first matching override wins
+        assert_eq!(
+            decoded,
+            [
+                ("写真/a.txt", "Shift_JIS"),
+                ("中文/文件.txt", "GBK"),
+                ("ÖÐÎÄ/b.txt", "windows-1252"),
+            ]
+        );
+
+        let out_of_range = InspectConfig {
+            encoding_overrides: vec![EncodingOverride {
+                target: EncodingOverrideTarget::Index { index: 3 },
+                encoding: "GBK".to_string(),
+            }],
+            ..config.clone()
+        };
+        assert!(matches!(
+            InspectedArchive::inspect(&zip, &out_of_range),
+            Err(ZipInspectError::EntryIndexOutOfRange(3))
+        ));
+
+        let not_encodable = InspectConfig {
+            encoding_overrides: vec![EncodingOverride {
+                target: EncodingOverrideTarget::PathPrefix {
+                    prefix: "中文/".to_string(),
+                },
+                encoding: "IBM437".to_string(),
+            }],
+            ..config
+        };
+        assert!(matches!(
+            InspectedArchive::inspect(&zip, &not_encodable),
+            Err(ZipInspectError::PrefixNotEncodable { .. })
+        ));
     }
 
     #[test]
-    fn test_inspect_utf8_entry() {
-        let entry = create_mock_entry("test.txt".as_bytes(), true, None);
+    fn test_rank_encodings() {
+        let rank = |data: &[u8]| {
+            rank_encodings(data, &ResolvedDetectionHint::default())
+                .into_iter()
+                .map(|(encoding, score, error_count)| (encoding.name(), score, error_count))
+                .collect::<Vec<_>>()
+        };
+
+        // "写真/夏休みの思い出.jpg" in Shift_JIS also decodes without errors as GBK, but less plausibly
+        let ranked =
+            rank(b"\x8e\xca\x90^/\x89\xc4\x8b\x78\x82\xdd\x82\xcc\x8e\x76\x82\xa2\x8f\x6f.jpg");
+        assert_eq!(ranked.len(), CANDIDATE_ENCODINGS.len());
+        assert_eq!(ranked[0].0, "Shift_JIS");
+        assert_eq!(ranked[0].2, 0);
+        let gbk = ranked.iter().find(|r| r.0 == "GBK").unwrap();
+        assert_eq!(gbk.2, 0);
+        assert!(ranked[0].1 > gbk.1);
+        assert!(ranked.windows(2).all(|w| w[0].1 >= w[1].1));
+
+        // "Документы" in windows-1251 looks like mojibake in Latin code pages
+        let ranked = rank(b"\xc4\xee\xea\xf3\xec\xe5\xed\xf2\xfb");
+        assert_eq!(ranked[0].0, "windows-1251");
+        let windows_1252 = ranked.iter().find(|r| r.0 == "windows-1252").unwrap();
+        assert!(windows_1252.1 < 0.8);
+
+        assert_eq!(rank(b"plain.txt"), [("ASCII", 1.0, 0)]);
+    }
+
+    #[test]
+    fn test_inspect_encoding_candidates() {
+        // "한국어.hwp" in EUC-KR, and an ASCII name
+        let entry1 = create_mock_entry(b"readme.txt", false, None);
+        let entry2 = create_mock_entry(b"\xc7\xd1\xb1\xb9\xbe\xee.hwp", false, None);
+        let zip = create_mock_zip(vec![entry1, entry2]);
+        let mut config = InspectConfig::default();
+
+        let inspected = InspectedArchive::inspect(&zip, &config).unwrap();
+        assert!(inspected.encoding_candidates.is_empty());
+
+        config.candidate_count = 3;
+        let inspected = InspectedArchive::inspect(&zip, &config).unwrap();
+        assert_eq!(inspected.encoding_candidates.len(), 3);
+        let best = &inspected.encoding_candidates[0];
+        assert_eq!(best.encoding, "EUC-KR");
+        assert_eq!(best.error_count, 0);
+        // The preview shows the first name that is not plain ASCII
+        assert_eq!(best.preview, "한국어.hwp");
+        assert!(inspected.entries[1].filename.encoding_candidates.is_empty());
+        // The overall encoding is scored the same way as the candidates
+        assert_eq!(inspected.overall_encoding.as_deref(), Some("EUC-KR"));
+        assert_eq!(inspected.overall_encoding_score, Some(best.score));
+
+        config.entry_candidates = true;
+        let inspected = InspectedArchive::inspect(&zip, &config).unwrap();
+        let candidates = &inspected.entries[0].filename.encoding_candidates;
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].encoding, "ASCII");
+        assert_eq!(candidates[0].preview, "readme.txt");
+        let candidates = &inspected.entries[1].filename.encoding_candidates;
+        assert_eq!(candidates.len(), 3);
+        assert_eq!(candidates[0].encoding, "EUC-KR");
+    }
+
+    #[test]
+    fn test_inspect_detection_hint() {
+        // "資料.txt" in Shift_JIS is too short for chardetng to tell apart from a single-byte encoding
+        let entry = create_mock_entry(b"\x8e\x91\x97\xbf.txt", false, None);
         let zip = create_mock_zip(vec![entry]);
-        let config = InspectConfig {
-            encoding: EncodingSelectionStrategy::EntryDetected {
+        let config_with_hint = |detection_hint| InspectConfig {
+            encoding: EncodingSelectionStrategy::PreferOverallDetected {
                 fallback_encoding: None,
                 ignore_utf8_flag: false,
+                detection_hint,
             },
-            field_selection_strategy: FieldSelectionStrategy::default(),
-            ignore_crc32_mismatch: false,
-            needs_original_bytes: false,
-            wave_dash_handling: WaveDashHandling::default(),
-            wave_dash_normalization: WaveDashNormalization::default(),
+            ..Default::default()
         };
-        let result = InspectedArchive::inspect(&zip, &config);
-        assert!(result.is_ok());
-        let inspected = result.unwrap();
-        assert_eq!(inspected.entries.len(), 1);
-        let filename = &inspected.entries[0].filename;
-        assert!(filename.utf8_flag);
-        assert_eq!(filename.decoded.as_ref().unwrap().string, "test.txt");
-        assert_eq!(filename.decoded.as_ref().unwrap().encoding_used, "UTF-8");
+
+        // Without the dictionary, chardetng alone guesses a single-byte encoding
+        if !cfg!(feature = "dictionary") {
+            let inspected =
+                InspectedArchive::inspect(&zip, &config_with_hint(DetectionHint::default()))
+                    .unwrap();
+            assert_ne!(inspected.overall_encoding.as_deref(), Some("Shift_JIS"));
+        }
+
+        // A language tag is mapped to the TLD of the country
+        let inspected = InspectedArchive::inspect(
+            &zip,
+            &config_with_hint(DetectionHint {
+                locale: Some("ja".to_string()),
+                allowed_encodings: Vec::new(),
+            }),
+        )
+        .unwrap();
+        assert_eq!(inspected.overall_encoding.as_deref(), Some("Shift_JIS"));
+        assert_eq!(
+            inspected.entries[0]
+                .filename
+                .decoded
+                .as_ref()
+                .unwrap()
+                .string,
+            "資料.txt"
+        );
+
+        // The allowlist restricts detection even against the guess of chardetng
+        let inspected = InspectedArchive::inspect(
+            &zip,
+            &config_with_hint(DetectionHint {
+                locale: None,
+                allowed_encodings: vec!["Shift_JIS".to_string(), "EUC-KR".to_string()],
+            }),
+        )
+        .unwrap();
+        assert_eq!(inspected.overall_encoding.as_deref(), Some("Shift_JIS"));
+
+        let result = InspectedArchive::inspect(
+            &zip,
+            &config_with_hint(DetectionHint {
+                locale: Some("日本".to_string()),
+                allowed_encodings: Vec::new(),
+            }),
+        );
+        assert!(matches!(result, Err(ZipInspectError::InvalidLocale(_))));
+
+        let result = InspectedArchive::inspect(
+            &zip,
+            &config_with_hint(DetectionHint {
+                locale: None,
+                allowed_encodings: vec!["unknown".to_string()],
+            }),
+        );
+        assert!(matches!(result, Err(ZipInspectError::EncodingNotFound(_))));
     }
 
     #[test]
-    fn test_inspect_sjis_entry() {
-        // "テスト.txt" in Shift_JIS
-        let sjis_bytes = b"\x83\x65\x83\x58\x83\x67.txt";
-        let entry = create_mock_entry(sjis_bytes, false, None);
-        let zip = create_mock_zip(vec![entry]);
+    fn test_locale_to_tld() {
+        assert_eq!(locale_to_tld("jp").as_deref(), Some("jp"));
+        assert_eq!(locale_to_tld("ja").as_deref(), Some("jp"));
+        assert_eq!(locale_to_tld("ja-JP").as_deref(), Some("jp"));
+        assert_eq!(locale_to_tld("zh_TW").as_deref(), Some("tw"));
+        assert_eq!(locale_to_tld("zh-Hant").as_deref(), Some("tw"));
+        assert_eq!(locale_to_tld("zh-Hans").as_deref(), Some("cn"));
+        assert_eq!(locale_to_tld(".RU").as_deref(), Some("ru"));
+        assert_eq!(locale_to_tld("co.jp"), None);
+        assert_eq!(locale_to_tld(""), None);
+    }
+
+    #[test]
+    fn test_inspect_encoding_clusters() {
+        // A Shift_JIS directory and a UTF-8 directory without the flag, which no single encoding decodes
+        let entries = vec![
+            create_mock_entry(b"readme.txt", false, None),
+            create_mock_entry(
+                b"\x93\xfa\x96{\x8c\xea\x82\xcc\x8e\x91\x97\xbf/\x83e\x83X\x83g\x8c\x8b\x89\xca.txt",
+                false,
+                None,
+            ),
+            create_mock_entry(
+                b"\xe5\x86\x99\xe7\x9c\x9f/\xe5\xa4\x8f\xe4\xbc\x91\xe3\x81\xbf.jpg",
+                false,
+                None,
+            ),
+            create_mock_entry(
+                b"\x93\xfa\x96{\x8c\xea\x82\xcc\x8e\x91\x97\xbf/\x95\xf1\x8d\x90\x8f\x91.doc",
+                false,
+                None,
+            ),
+            create_mock_entry(
+                b"\xe5\x86\x99\xe7\x9c\x9f/\xe9\x81\x8b\xe5\x8b\x95\xe4\xbc\x9a.jpg",
+                false,
+                None,
+            ),
+        ];
+        let zip = create_mock_zip(entries);
+        let mut config = InspectConfig::default();
+
+        let inspected = InspectedArchive::inspect(&zip, &config).unwrap();
+        assert_eq!(inspected.overall_encoding, None);
+        assert_eq!(inspected.encoding_clusters.len(), 2);
+        let cluster = &inspected.encoding_clusters[0];
+        assert_eq!(cluster.directory, "日本語の資料");
+        assert_eq!(cluster.encoding.as_deref(), Some("Shift_JIS"));
+        assert_eq!(cluster.entries, [1, 3]);
+        let cluster = &inspected.encoding_clusters[1];
+        assert_eq!(cluster.directory, "写真");
+        assert_eq!(cluster.encoding.as_deref(), Some("UTF-8"));
+        assert_eq!(cluster.entries, [2, 4]);
+
+        let names = inspected
+            .entries
+            .iter()
+            .map(|entry| entry.filename.decoded.as_ref().unwrap().string.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                "readme.txt",
+                "日本語の資料/テスト結果.txt",
+                "写真/夏休み.jpg",
+                "日本語の資料/報告書.doc",
+                "写真/運動会.jpg",
+            ]
+        );
+
+        // A single source is not split even if a directory alone would be detected differently
+        let single_source = create_mock_zip(vec![
+            create_mock_entry(
+                b"\x93\xfa\x96{\x8c\xea\x82\xcc\x8e\x91\x97\xbf/\x83e\x83X\x83g\x8c\x8b\x89\xca.txt",
+                false,
+                None,
+            ),
+            create_mock_entry(b"\x8e\x91\x97\xbf/a.txt", false, None),
+        ]);
+        let inspected = InspectedArchive::inspect(&single_source, &config).unwrap();
+        assert_eq!(inspected.overall_encoding.as_deref(), Some("Shift_JIS"));
+        assert!(inspected.encoding_clusters.is_empty());
+
+        // Other strategies do not cluster
+        config.encoding = EncodingSelectionStrategy::EntryDetected {
+            fallback_encoding: None,
+            ignore_utf8_flag: false,
+            detection_hint: DetectionHint::default(),
+        };
+        let inspected = InspectedArchive::inspect(&zip, &config).unwrap();
+        assert!(inspected.encoding_clusters.is_empty());
+    }
+
+    #[cfg(feature = "dictionary")]
+    #[test]
+    fn test_inspect_dictionary_short_names() {
+        // Two-character names valid in several CJK encodings
+        let entries = vec![
+            // "資料.txt" in Shift_JIS
+            create_mock_entry(b"\x8e\x91\x97\xbf.txt", false, None),
+            // "写真.jpg" in Shift_JIS
+            create_mock_entry(b"\x8e\xca\x90^.jpg", false, None),
+            // "中文.txt" in GBK
+            create_mock_entry(b"\xd6\xd0\xce\xc4.txt", false, None),
+            // "報告.txt" in Big5
+            create_mock_entry(b"\xb3\xf8\xa7i.txt", false, None),
+        ];
+        let zip = create_mock_zip(entries);
         let config = InspectConfig {
             encoding: EncodingSelectionStrategy::EntryDetected {
-                fallback_encoding: Some("Shift_JIS".to_string()),
+                fallback_encoding: None,
                 ignore_utf8_flag: false,
+                detection_hint: DetectionHint::default(),
             },
-            field_selection_strategy: FieldSelectionStrategy::default(),
-            ignore_crc32_mismatch: false,
-            needs_original_bytes: false,
-            wave_dash_handling: WaveDashHandling::default(),
-            wave_dash_normalization: WaveDashNormalization::default(),
+            ..Default::default()
         };
-        let result = InspectedArchive::inspect(&zip, &config);
-        assert!(result.is_ok());
-        let inspected = result.unwrap();
-        assert_eq!(inspected.entries.len(), 1);
-        let filename = &inspected.entries[0].filename;
-        assert!(!filename.utf8_flag);
-        assert_eq!(filename.decoded.as_ref().unwrap().string, "テスト.txt");
+
+        let inspected = InspectedArchive::inspect(&zip, &config).unwrap();
+        let names = inspected
+            .entries
+            .iter()
+            .map(|entry| {
+                let decoded = entry.filename.decoded.as_ref().unwrap();
+                (decoded.encoding_used.as_str(), decoded.string.as_str())
+            })
+            .collect::<Vec<_>>();
         assert_eq!(
-            filename.decoded.as_ref().unwrap().encoding_used,
-            "Shift_JIS"
+            names,
+            [
+                ("Shift_JIS", "資料.txt"),
+                ("Shift_JIS", "写真.jpg"),
+                ("GBK", "中文.txt"),
+                ("Big5", "報告.txt"),
+            ]
         );
     }
 
     #[test]
-    fn test_inspect_unicode_path_extra_field() {
-        let unicode_path = UnicodePathExtraField {
-            version: 1,
-            name_crc32: 0,
-            data: "unicode.txt".as_bytes().to_vec(),
-            decoded_string: Some("unicode.txt".to_string()),
-            crc32_matched: true,
-        };
-        let entry = create_mock_entry(b"ascii.txt", false, Some(unicode_path));
-        let zip = create_mock_zip(vec![entry]);
-        let config = InspectConfig {
-            encoding: EncodingSelectionStrategy::EntryDetected {
-                fallback_encoding: None,
+    fn test_inspect_decoding_errors() {
+        // Shift_JIS with a lead byte followed by a space, and a byte that is never valid
+        let entry1 = create_mock_entry(b"\x83e\x81 \x83X\xff.txt", false, None);
+        // "é" in UTF-8 followed by an invalid byte
+        let entry2 = create_mock_entry(b"caf\xc3\xa9\xff%\\.txt", true, None);
+        let zip = create_mock_zip(vec![entry1, entry2]);
+        let mut config = InspectConfig {
+            encoding: EncodingSelectionStrategy::ForceSpecified {
+                encoding: "Shift_JIS".to_string(),
                 ignore_utf8_flag: false,
             },
-            field_selection_strategy: FieldSelectionStrategy::CdhUnicodeThenCdh,
-            ignore_crc32_mismatch: false,
-            needs_original_bytes: false,
-            wave_dash_handling: WaveDashHandling::default(),
-            wave_dash_normalization: WaveDashNormalization::default(),
+            ..Default::default()
         };
-        let result = InspectedArchive::inspect(&zip, &config);
-        assert!(result.is_ok());
-        let inspected = result.unwrap();
-        assert_eq!(inspected.entries.len(), 1);
-        let filename = &inspected.entries[0].filename;
+
+        let inspected = InspectedArchive::inspect(&zip, &config).unwrap();
+        let decoded = inspected.entries[0].filename.decoded.as_ref().unwrap();
+        assert_eq!(decoded.string, "テ\u{FFFD} ス\u{FFFD}.txt");
+        assert!(decoded.has_errors);
         assert_eq!(
-            filename.kind,
-            InspectedFilenameFieldKind::CdhUnicodePathExtraField
+            decoded.errors,
+            [
+                DecodingError {
+                    byte_start: 2,
+                    byte_end: 3,
+                    bytes: vec![0x81],
+                    char_index: 1,
+                },
+                DecodingError {
+                    byte_start: 6,
+                    byte_end: 7,
+                    bytes: vec![0xFF],
+                    char_index: 4,
+                },
+            ]
         );
-        assert_eq!(filename.decoded.as_ref().unwrap().string, "unicode.txt");
+        assert_eq!(
+            decoded.escaped(EscapeStyle::Backslash),
+            "テ\\x81 ス\\xFF.txt"
+        );
+        assert_eq!(decoded.escaped(EscapeStyle::Percent), "テ%81 ス%FF.txt");
+
+        // Literal escape characters are escaped too
+        let decoded = inspected.entries[1].filename.decoded.as_ref().unwrap();
+        assert_eq!(
+            decoded.escaped(EscapeStyle::Backslash),
+            "café\\xFF%\\\\.txt"
+        );
+        assert_eq!(decoded.escaped(EscapeStyle::Percent), "café%FF%25\\.txt");
+
+        // Normalization shifts the replacement characters
+        config.unicode_normalization = UnicodeNormalization::Nfd;
+        let inspected = InspectedArchive::inspect(&zip, &config).unwrap();
+        let decoded = inspected.entries[1].filename.decoded.as_ref().unwrap();
+        assert_eq!(decoded.errors[0].byte_start, 5);
+        assert_eq!(decoded.errors[0].char_index, 5);
+        assert_eq!(
+            decoded.escaped(EscapeStyle::Percent),
+            "cafe\u{301}%FF%25\\.txt"
+        );
+
+        // Names without errors escape the escape character all the same
+        let entry = create_mock_entry(b"100%\\ok.txt", true, None);
+        let inspected = InspectedArchive::inspect(&create_mock_zip(vec![entry]), &config).unwrap();
+        let decoded = inspected.entries[0].filename.decoded.as_ref().unwrap();
+        assert!(decoded.errors.is_empty());
+        assert_eq!(decoded.escaped(EscapeStyle::Backslash), "100%\\\\ok.txt");
+        assert_eq!(decoded.escaped(EscapeStyle::Percent), "100%25\\ok.txt");
     }
 
     #[test]
@@ -780,6 +3401,7 @@ mod tests {
             needs_original_bytes: false,
             wave_dash_handling: WaveDashHandling::default(),
             wave_dash_normalization: WaveDashNormalization::default(),
+            ..Default::default()
         };
         let result = InspectedArchive::inspect(&zip, &config);
         assert!(result.is_ok());
@@ -793,6 +3415,21 @@ mod tests {
         );
     }
 
+    /// Source character, source encoding and entries of the potential substitutions
+    fn potential_substitutions(archive: &InspectedArchive) -> Vec<(char, &str, &[u64])> {
+        archive
+            .potential_substitutions
+            .iter()
+            .map(|p| {
+                (
+                    p.mapping.from,
+                    p.source_encoding.as_str(),
+                    p.entries.as_slice(),
+                )
+            })
+            .collect()
+    }
+
     #[test]
     fn test_sjis_wave_dash_behavior() {
         // Wave Dash in Shift_JIS is 0x8160
@@ -809,6 +3446,7 @@ mod tests {
             needs_original_bytes: false,
             wave_dash_handling: WaveDashHandling::DecodeToFullwidthTilde,
             wave_dash_normalization: WaveDashNormalization::Preserve,
+            ..Default::default()
         };
 
         let entry = create_mock_entry(sjis_bytes, false, None);
@@ -823,7 +3461,11 @@ mod tests {
             .string
             .as_str();
         assert_eq!(decoded, "\u{FF5E}"); // Fullwidth Tilde
-        assert!(result.contains_sjis_wave_dash);
+        assert!(result.substitution_hits.is_empty());
+        assert_eq!(
+            potential_substitutions(&result),
+            [('\u{FF5E}', "Shift_JIS", &[0][..])]
+        );
 
         // DecodeToWaveDash
         let config_wave_dash = InspectConfig {
@@ -836,6 +3478,7 @@ mod tests {
             needs_original_bytes: false,
             wave_dash_handling: WaveDashHandling::DecodeToWaveDash,
             wave_dash_normalization: WaveDashNormalization::Preserve,
+            ..Default::default()
         };
 
         let result = InspectedArchive::inspect(&zip, &config_wave_dash).unwrap();
@@ -847,7 +3490,14 @@ mod tests {
             .string
             .as_str();
         assert_eq!(decoded, "\u{301C}"); // Wave Dash
-        assert!(result.contains_sjis_wave_dash);
+        assert_eq!(result.substitution_hits.len(), 1);
+        assert_eq!(result.substitution_hits[0].table, "Shift_JIS Wave Dash");
+        assert_eq!(result.substitution_hits[0].entries, [0]);
+        // Potential substitutions are matched before substitution
+        assert_eq!(
+            potential_substitutions(&result),
+            [('\u{FF5E}', "Shift_JIS", &[0][..])]
+        );
     }
 
     #[test]
@@ -867,12 +3517,14 @@ mod tests {
             encoding: EncodingSelectionStrategy::EntryDetected {
                 fallback_encoding: None,
                 ignore_utf8_flag: false,
+                detection_hint: DetectionHint::default(),
             },
             field_selection_strategy: FieldSelectionStrategy::default(),
             ignore_crc32_mismatch: false,
             needs_original_bytes: false,
             wave_dash_handling: WaveDashHandling::default(),
             wave_dash_normalization: WaveDashNormalization::NormalizeToFullwidthTilde,
+            ..Default::default()
         };
 
         let result = InspectedArchive::inspect(&zip, &config_norm_ft).unwrap();
@@ -890,12 +3542,14 @@ mod tests {
             encoding: EncodingSelectionStrategy::EntryDetected {
                 fallback_encoding: None,
                 ignore_utf8_flag: false,
+                detection_hint: DetectionHint::default(),
             },
             field_selection_strategy: FieldSelectionStrategy::default(),
             ignore_crc32_mismatch: false,
             needs_original_bytes: false,
             wave_dash_handling: WaveDashHandling::default(),
             wave_dash_normalization: WaveDashNormalization::NormalizeToWaveDash,
+            ..Default::default()
         };
 
         let result = InspectedArchive::inspect(&zip, &config_norm_wd).unwrap();
@@ -920,19 +3574,22 @@ mod tests {
             encoding: EncodingSelectionStrategy::PreferOverallDetected {
                 fallback_encoding: None,
                 ignore_utf8_flag: false,
+                detection_hint: DetectionHint::default(),
             },
             field_selection_strategy: FieldSelectionStrategy::default(),
             ignore_crc32_mismatch: false,
             needs_original_bytes: false,
             wave_dash_handling: WaveDashHandling::default(),
             wave_dash_normalization: WaveDashNormalization::Preserve,
+            ..Default::default()
         };
 
         let result = InspectedArchive::inspect(&zip, &config).unwrap();
 
-        assert!(result.contains_other_wave_dash);
-        assert!(!result.contains_other_fullwidth_tilde);
-        assert!(!result.contains_sjis_wave_dash);
+        assert_eq!(
+            potential_substitutions(&result),
+            [('\u{301C}', "UTF-8", &[0][..])]
+        );
     }
 
     #[test]
@@ -946,18 +3603,301 @@ mod tests {
             encoding: EncodingSelectionStrategy::PreferOverallDetected {
                 fallback_encoding: None,
                 ignore_utf8_flag: false,
+                detection_hint: DetectionHint::default(),
             },
             field_selection_strategy: FieldSelectionStrategy::default(),
             ignore_crc32_mismatch: false,
             needs_original_bytes: false,
             wave_dash_handling: WaveDashHandling::default(),
             wave_dash_normalization: WaveDashNormalization::Preserve,
+            ..Default::default()
+        };
+
+        let result = InspectedArchive::inspect(&zip, &config).unwrap();
+
+        assert_eq!(
+            potential_substitutions(&result),
+            [('\u{FF5E}', "UTF-8", &[0][..])]
+        );
+    }
+
+    #[test]
+    fn test_inspect_wave_dash_normalization_hit() {
+        // UTF-8 Wave Dash (U+301C) is not affected by the Shift_JIS handling
+        let wave_dash_str = "\u{301C}";
+        let entry = create_mock_entry(wave_dash_str.as_bytes(), true, None);
+        let zip = create_mock_zip(vec![entry]);
+
+        let config = InspectConfig {
+            encoding: EncodingSelectionStrategy::PreferOverallDetected {
+                fallback_encoding: None,
+                ignore_utf8_flag: false,
+                detection_hint: DetectionHint::default(),
+            },
+            field_selection_strategy: FieldSelectionStrategy::default(),
+            ignore_crc32_mismatch: false,
+            needs_original_bytes: false,
+            wave_dash_handling: WaveDashHandling::DecodeToWaveDash,
+            wave_dash_normalization: WaveDashNormalization::NormalizeToFullwidthTilde,
+            ..Default::default()
+        };
+
+        let result = InspectedArchive::inspect(&zip, &config).unwrap();
+
+        assert_eq!(
+            result.entries[0].filename.decoded.as_ref().unwrap().string,
+            "\u{FF5E}"
+        );
+        assert_eq!(result.substitution_hits.len(), 1);
+        assert_eq!(result.substitution_hits[0].table, "Wave Dash normalization");
+        assert_eq!(
+            result.substitution_hits[0].mapping,
+            CharacterMapping {
+                from: '\u{301C}',
+                to: '\u{FF5E}',
+            }
+        );
+    }
+
+    #[test]
+    fn test_inspect_substitution_tables() {
+        // "1－2‖" in Shift_JIS, decoded as CP932 Fullwidth Hyphen-Minus and Parallel To
+        let entry1 = create_mock_entry(b"1\x81\x7c2\x81\x61", false, None);
+        // The same characters in UTF-8 are left alone, as the preset only applies to Shift_JIS
+        let entry2 = create_mock_entry("3\u{FF0D}4".as_bytes(), true, None);
+        let zip = create_mock_zip(vec![entry1, entry2]);
+
+        let config = InspectConfig {
+            encoding: EncodingSelectionStrategy::ForceSpecified {
+                encoding: "Shift_JIS".to_string(),
+                ignore_utf8_flag: false,
+            },
+            substitution_tables: vec![SubstitutionPreset::Cp932ToJis.table()],
+            ..Default::default()
+        };
+
+        let result = InspectedArchive::inspect(&zip, &config).unwrap();
+        assert_eq!(
+            result.entries[0].filename.decoded.as_ref().unwrap().string,
+            "1\u{2212}2\u{2016}"
+        );
+        assert_eq!(
+            result.entries[1].filename.decoded.as_ref().unwrap().string,
+            "3\u{FF0D}4"
+        );
+
+        let hits = result
+            .substitution_hits
+            .iter()
+            .map(|hit| (hit.mapping.from, hit.entries.as_slice()))
+            .collect::<Vec<_>>();
+        assert_eq!(hits, [('\u{FF0D}', &[0][..]), ('\u{2225}', &[0][..])]);
+        // Nor could the mapping change the UTF-8 name if the table was enabled
+        assert_eq!(
+            potential_substitutions(&result),
+            [
+                ('\u{FF0D}', "Shift_JIS", &[0][..]),
+                ('\u{2225}', "Shift_JIS", &[0][..]),
+            ]
+        );
+
+        // Source encodings must be known
+        let config = InspectConfig {
+            substitution_tables: vec![SubstitutionTable {
+                name: "unknown".to_string(),
+                source_encodings: vec!["no-such-encoding".to_string()],
+                mappings: Vec::new(),
+            }],
+            ..config
+        };
+        assert!(matches!(
+            InspectedArchive::inspect(&zip, &config),
+            Err(ZipInspectError::EncodingNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_inspect_jis_to_cp932_source_encodings() {
+        // "£5" in Windows-1252, and "£〜5" in UTF-8 with the JIS X 0208 Pound Sign and Wave Dash
+        let latin1 = create_mock_entry(b"\xa35.txt", false, None);
+        let utf8 = create_mock_entry("\u{A3}\u{301C}5.txt".as_bytes(), true, None);
+        let zip = create_mock_zip(vec![latin1, utf8]);
+        let config = InspectConfig {
+            encoding: EncodingSelectionStrategy::ForceSpecified {
+                encoding: "windows-1252".to_string(),
+                ignore_utf8_flag: false,
+            },
+            substitution_tables: vec![SubstitutionPreset::JisToCp932.table()],
+            ..Default::default()
         };
 
         let result = InspectedArchive::inspect(&zip, &config).unwrap();
+        // The Latin-1 sign is left untouched and not even reported
+        assert_eq!(
+            result.entries[0].filename.decoded.as_ref().unwrap().string,
+            "\u{A3}5.txt"
+        );
+        assert_eq!(
+            result.entries[1].filename.decoded.as_ref().unwrap().string,
+            "\u{FFE1}\u{FF5E}5.txt"
+        );
+        let hits = result
+            .substitution_hits
+            .iter()
+            .map(|hit| (hit.mapping.from, hit.entries.as_slice()))
+            .collect::<Vec<_>>();
+        assert_eq!(hits, [('\u{301C}', &[1][..]), ('\u{A3}', &[1][..])]);
+        assert_eq!(
+            potential_substitutions(&result),
+            [
+                ('\u{301C}', "UTF-8", &[1][..]),
+                ('\u{A3}', "UTF-8", &[1][..]),
+            ]
+        );
+    }
+
+    fn field_selected(original_bytes: &[u8], utf8_flag: bool) -> FieldSelectedFileEntry<'_> {
+        FieldSelectedFileEntry {
+            kind: InspectedFilenameFieldKind::CdhFilename,
+            utf8_flag,
+            original_bytes,
+        }
+    }
+
+    #[test]
+    fn test_resolve_encoding_overrides() {
+        let predetect_entries = [
+            field_selected(b"a/x.txt", false),
+            field_selected(b"b/y.txt", false),
+            field_selected(b"a/z.txt", false),
+        ];
+        let encoding_override = |target, encoding: &str| EncodingOverride {
+            target,
+            encoding: encoding.to_string(),
+        };
+        let overrides = [
+            encoding_override(EncodingOverrideTarget::Index { index: 2 }, "GBK"),
+            encoding_override(
+                EncodingOverrideTarget::PathPrefix {
+                    prefix: "a/".to_string(),
+                },
+                "Shift_JIS",
+            ),
+        ];
+
+        // The first override matching an entry wins
+        let names = resolve_encoding_overrides(&overrides, &predetect_entries)
+            .unwrap()
+            .into_iter()
+            .map(|encoding| encoding.map(|e| e.name()))
+            .collect::<Vec<_>>();
+        assert_eq!(names, [Some("Shift_JIS"), None, Some("GBK")]);
+
+        assert!(matches!(
+            resolve_encoding_overrides(
+                &[encoding_override(
+                    EncodingOverrideTarget::Index { index: 3 },
+                    "GBK"
+                )],
+                &predetect_entries
+            ),
+            Err(ZipInspectError::EntryIndexOutOfRange(3))
+        ));
+    }
+
+    #[test]
+    fn test_detect_encoding_clusters() {
+        let predetect_entries = [
+            // "日本語の資料/テスト結果.txt" in Shift_JIS
+            field_selected(
+                b"\x93\xfa\x96{\x8c\xea\x82\xcc\x8e\x91\x97\xbf/\x83e\x83X\x83g\x8c\x8b\x89\xca.txt",
+                false,
+            ),
+            // "写真/夏休み.jpg" in UTF-8 without the flag
+            field_selected(
+                b"\xe5\x86\x99\xe7\x9c\x9f/\xe5\xa4\x8f\xe4\xbc\x91\xe3\x81\xbf.jpg",
+                false,
+            ),
+            // Entries with the UTF-8 flag, an override or ASCII names are left out
+            field_selected("写真/運動会.jpg".as_bytes(), true),
+            field_selected(b"\x8e\x91\x97\xbf/a.txt", false),
+            field_selected(b"readme.txt", false),
+        ];
+        let entry_overrides = [None, None, None, Some(EncodingOrAscii::Encoding(GBK)), None];
+        let hint = ResolvedDetectionHint::default();
+
+        let (clusters, encodings) =
+            detect_encoding_clusters(&predetect_entries, &entry_overrides, false, None, &hint);
+        let clusters = clusters
+            .iter()
+            .map(|c| {
+                (
+                    c.directory.as_str(),
+                    c.encoding.as_deref(),
+                    c.entries.as_slice(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            clusters,
+            [
+                ("日本語の資料", Some("Shift_JIS"), &[0][..]),
+                ("写真", Some("UTF-8"), &[1][..]),
+            ]
+        );
+        let encodings = encodings
+            .into_iter()
+            .map(|encoding| encoding.map(|e| e.name()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            encodings,
+            [Some("Shift_JIS"), Some("UTF-8"), None, None, None]
+        );
+
+        // Nothing is reported if every directory agrees with the overall encoding
+        let (clusters, encodings) = detect_encoding_clusters(
+            &predetect_entries[..1],
+            &entry_overrides[..1],
+            false,
+            Some(EncodingOrAscii::Encoding(SHIFT_JIS)),
+            &hint,
+        );
+        assert!(clusters.is_empty());
+        assert_eq!(encodings, [None]);
+    }
+
+    #[test]
+    fn test_known_substitution_mappings() {
+        let table = |source_encodings: &[&str]| SubstitutionTable {
+            name: String::new(),
+            source_encodings: source_encodings.iter().map(|e| e.to_string()).collect(),
+            mappings: vec![CharacterMapping {
+                from: '\u{FF0D}',
+                to: '\u{2212}',
+            }],
+        };
+        let source_encodings = |tables: Vec<SubstitutionTable>| {
+            let tables = tables
+                .into_iter()
+                .map(|table| {
+                    let source_encodings = resolve_source_encodings(&table).unwrap();
+                    (table, source_encodings)
+                })
+                .collect::<Vec<_>>();
+            known_substitution_mappings(&tables)
+                .unwrap()
+                .into_iter()
+                .find(|(mapping, _)| mapping.from == '\u{FF0D}')
+                .unwrap()
+                .1
+        };
 
-        assert!(result.contains_other_fullwidth_tilde);
-        assert!(!result.contains_other_wave_dash);
-        assert!(!result.contains_sjis_wave_dash);
+        // The preset maps the same character for Shift_JIS only
+        assert_eq!(source_encodings(Vec::new()), ["Shift_JIS"]);
+        assert_eq!(
+            source_encodings(vec![table(&["gbk"])]),
+            ["Shift_JIS", "GBK"]
+        );
+        assert!(source_encodings(vec![table(&["gbk"]), table(&[])]).is_empty());
     }
 }
@@ -1,4 +1,10 @@
+pub mod codepage;
 pub mod compatibility;
+#[cfg(feature = "dictionary")]
+pub mod dictionary;
 pub mod inspect;
 pub mod parse;
 pub mod rebuild;
+pub mod rename;
+pub mod substitution;
+pub mod timestamp;
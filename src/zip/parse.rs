@@ -11,6 +11,84 @@ pub trait ZipReader {
         offset: u64,
         size: u64,
     ) -> impl std::future::Future<Output = Result<Vec<u8>, io::Error>>;
+
+    /// Get the offset where the given disk (volume) starts within the data source
+    ///
+    /// Returns None if the disk is not available. Single-file sources only have disk 0.
+    fn get_disk_offset(&self, disk_number: u32) -> Option<u64> {
+        (disk_number == 0).then_some(0)
+    }
+}
+
+/// ZipReader that concatenates the volumes of a split or spanned archive
+///
+/// Volumes must be given in order, e.g. `.z01`, `.z02`, ..., `.zip` for spanned archives, or
+/// `.001`, `.002`, ... for archives split by size. Disk numbers recorded in the archive are mapped
+/// to the offset where the corresponding volume starts.
+#[derive(Debug)]
+pub struct MultiVolumeReader<Reader: ZipReader> {
+    /// Volumes with their start offsets and sizes
+    volumes: Vec<(u64, u64, Reader)>,
+}
+
+impl<Reader: ZipReader> MultiVolumeReader<Reader> {
+    /// Create a reader from volumes in order
+    pub async fn new(volumes: Vec<Reader>) -> Result<Self, io::Error> {
+        let mut offset = 0;
+        let mut sized_volumes = Vec::with_capacity(volumes.len());
+        for mut volume in volumes {
+            let size = volume.get_size().await?;
+            sized_volumes.push((offset, size, volume));
+            offset += size;
+        }
+
+        Ok(Self {
+            volumes: sized_volumes,
+        })
+    }
+
+    /// Get the number of volumes
+    pub fn volume_count(&self) -> usize {
+        self.volumes.len()
+    }
+}
+
+impl<Reader: ZipReader> ZipReader for MultiVolumeReader<Reader> {
+    async fn get_size(&mut self) -> Result<u64, io::Error> {
+        Ok(self
+            .volumes
+            .last()
+            .map_or(0, |(offset, size, _)| offset + size))
+    }
+
+    async fn read(&mut self, offset: u64, size: u64) -> Result<Vec<u8>, io::Error> {
+        let end = offset.saturating_add(size);
+
+        let mut buffer = Vec::new();
+        for (volume_offset, volume_size, volume) in &mut self.volumes {
+            let volume_end = *volume_offset + *volume_size;
+            if volume_end <= offset || *volume_offset >= end {
+                continue;
+            }
+
+            let read_start = std::cmp::max(offset, *volume_offset);
+            let read_size = std::cmp::min(end, volume_end) - read_start;
+            let data = volume.read(read_start - *volume_offset, read_size).await?;
+            let is_short = (data.len() as u64) < read_size;
+            buffer.extend_from_slice(&data);
+            if is_short {
+                break;
+            }
+        }
+
+        Ok(buffer)
+    }
+
+    fn get_disk_offset(&self, disk_number: u32) -> Option<u64> {
+        self.volumes
+            .get(disk_number as usize)
+            .map(|(offset, _, _)| *offset)
+    }
 }
 
 /// Representation of a parsed zip file
@@ -18,6 +96,10 @@ pub trait ZipReader {
 pub struct ZipFile {
     /// The overall size of the zip file
     pub size: u64,
+    /// Length of data prepended to the archive, such as a self-extractor stub
+    ///
+    /// Offsets recorded in the archive are relative to this position.
+    pub prefix_length: u64,
     /// End of Central Directory
     pub eocd: EndOfCentralDirectory,
     /// Optional Zip64 End of Central Directory
@@ -38,12 +120,12 @@ impl ZipFile {
     ) -> Result<Self, ZipParseError> {
         let size = reader.get_size().await?;
 
-        let (eocd, zip64_eocd, entries) = parse_zip(
+        let (eocd, zip64_eocd, prefix_length, entries) = parse_zip(
             reader,
             DEFAULT_ZIP64_FALLBACK_SEARCH_SIZE,
             ignore_data_descriptor,
-            |eocd, zip64_eocd| Ok((eocd, zip64_eocd, Vec::new())),
-            |(_, _, entries), entry| {
+            |eocd, zip64_eocd, prefix_length| Ok((eocd, zip64_eocd, prefix_length, Vec::new())),
+            |(_, _, _, entries), entry| {
                 entries.push(entry);
                 Ok(())
             },
@@ -53,6 +135,7 @@ impl ZipFile {
 
         Ok(ZipFile {
             size,
+            prefix_length,
             eocd,
             zip64_eocd,
             entries,
@@ -67,12 +150,12 @@ impl ZipFile {
         let size = reader.get_size().await?;
 
         let mut warnings = Vec::new();
-        let (eocd, zip64_eocd, entries) = parse_zip(
+        let (eocd, zip64_eocd, prefix_length, entries) = parse_zip(
             reader,
             DEFAULT_ZIP64_FALLBACK_SEARCH_SIZE,
             ignore_data_descriptor,
-            |eocd, zip64_eocd| Ok((eocd, zip64_eocd, Vec::new())),
-            |(_, _, entries), entry| {
+            |eocd, zip64_eocd, prefix_length| Ok((eocd, zip64_eocd, prefix_length, Vec::new())),
+            |(_, _, _, entries), entry| {
                 entries.push(entry);
                 Ok(())
             },
@@ -86,6 +169,7 @@ impl ZipFile {
         Ok((
             ZipFile {
                 size,
+                prefix_length,
                 eocd,
                 zip64_eocd,
                 entries,
@@ -93,6 +177,74 @@ impl ZipFile {
             warnings,
         ))
     }
+
+    /// Recover a zip file from a ZipReader by scanning Local File Headers
+    ///
+    /// The EOCD and central directory are not read at all, so this works for archives whose trailing
+    /// structures are damaged or truncated. Central Directory Headers and the EOCD of the returned
+    /// zip file are synthesized from the recovered entries.
+    pub async fn recover<Reader: ZipReader>(
+        reader: &mut Reader,
+        on_warning: impl FnMut(Option<u64>, ZipParseError) -> Result<(), ZipParseError>,
+    ) -> Result<Self, ZipParseError> {
+        let size = reader.get_size().await?;
+
+        let (prefix_length, entries, end_offset) = recover_zip(reader, on_warning).await?;
+
+        Ok(Self::from_recovered_entries(
+            size,
+            prefix_length,
+            entries,
+            end_offset,
+        ))
+    }
+
+    /// Recover a zip file from a ZipReader by scanning Local File Headers
+    pub async fn recover_with_warnings<Reader: ZipReader>(
+        reader: &mut Reader,
+    ) -> Result<(Self, Vec<(Option<u64>, ZipParseError)>), ZipParseError> {
+        let size = reader.get_size().await?;
+
+        let mut warnings = Vec::new();
+        let (prefix_length, entries, end_offset) = recover_zip(reader, |idx, warning| {
+            warnings.push((idx, warning));
+            Ok(())
+        })
+        .await?;
+
+        Ok((
+            Self::from_recovered_entries(size, prefix_length, entries, end_offset),
+            warnings,
+        ))
+    }
+
+    /// Build a zip file with a synthetic EOCD from recovered entries
+    fn from_recovered_entries(
+        size: u64,
+        prefix_length: u64,
+        entries: Vec<ZipFileEntry>,
+        end_offset: u64,
+    ) -> Self {
+        let total_entries = std::cmp::min(entries.len(), 0xFFFF) as u16;
+        let end_offset = end_offset - prefix_length;
+        ZipFile {
+            size,
+            prefix_length,
+            eocd: EndOfCentralDirectory {
+                signature: 0x06054b50,
+                disk_number: 0,
+                disk_number_with_eocd: 0,
+                entries_on_disk: total_entries,
+                total_entries,
+                central_directory_size: 0,
+                central_directory_offset: std::cmp::min(end_offset, 0xFFFFFFFF) as u32,
+                comment_length: 0,
+                comment: Vec::new(),
+            },
+            zip64_eocd: None,
+            entries,
+        }
+    }
 }
 
 /// Zip file entry combining CDH, LFH, and optional Data Descriptor
@@ -112,6 +264,42 @@ pub struct ZipFileEntry {
 
 pub const DEFAULT_ZIP64_FALLBACK_SEARCH_SIZE: u64 = 1024 * 1024; // 1 MiB
 
+/// Maximum number of bytes of the central directory read at once, unless a single CDH is larger
+const CENTRAL_DIRECTORY_WINDOW_SIZE: u64 = 1024 * 1024; // 1 MiB
+
+/// Part of the central directory currently read, so that memory usage does not grow with its size
+struct CentralDirectoryWindow {
+    /// Offset of the central directory in the reader
+    start: u64,
+    /// Size of the central directory
+    size: u64,
+    /// Offset of `data` within the central directory
+    offset: u64,
+    data: Vec<u8>,
+}
+
+impl CentralDirectoryWindow {
+    /// Returns the data from `offset` within the central directory to the end of the window, reading a new
+    /// window unless it already holds `length` bytes from there
+    ///
+    /// Fewer bytes are returned if the central directory or the file ends before.
+    async fn read<Reader: ZipReader>(
+        &mut self,
+        reader: &mut Reader,
+        offset: u64,
+        length: u64,
+    ) -> Result<&[u8], io::Error> {
+        if offset < self.offset || offset + length > self.offset + self.data.len() as u64 {
+            let size = length
+                .max(CENTRAL_DIRECTORY_WINDOW_SIZE)
+                .min(self.size.saturating_sub(offset));
+            self.data = reader.read(self.start + offset, size).await?;
+            self.offset = offset;
+        }
+        Ok(&self.data[(offset - self.offset) as usize..])
+    }
+}
+
 /// Parse a complete zip file
 async fn parse_zip<Reader: ZipReader, State: Sized>(
     reader: &mut Reader,
@@ -123,6 +311,7 @@ async fn parse_zip<Reader: ZipReader, State: Sized>(
             Zip64EndOfCentralDirectory,
             Option<Zip64EndOfCentralDirectoryLocator>,
         )>,
+        u64,
     ) -> Result<State, ZipParseError>,
     mut on_entry: impl FnMut(&mut State, ZipFileEntry) -> Result<(), ZipParseError>,
     mut on_warning: impl FnMut(
@@ -184,7 +373,11 @@ async fn parse_zip<Reader: ZipReader, State: Sized>(
             if parse_u32_le(&zip64_eocd_locator_data[0..4]) == 0x07064b50 {
                 let zip64_eocd_locator =
                     Zip64EndOfCentralDirectoryLocator::parse(&zip64_eocd_locator_data)?;
-                if zip64_eocd_locator.total_disks >= 2 {
+                if zip64_eocd_locator.total_disks >= 2
+                    && reader
+                        .get_disk_offset(zip64_eocd_locator.total_disks - 1)
+                        .is_none()
+                {
                     // We allow "0" since some zip creators may write "0" for single-disk zips.
                     return Err(ZipParseError::MultipleDiskNotSupported);
                 }
@@ -196,8 +389,19 @@ async fn parse_zip<Reader: ZipReader, State: Sized>(
             }
         };
 
-        let zip64_eocd_offset = if let Some(locator) = &zip64_eocd_locator {
-            locator.eocd_offset
+        // The locator offset is not adjusted for prepended data, so verify the signature before trusting it
+        let locator_eocd_offset = if let Some(locator) = &zip64_eocd_locator {
+            let offset =
+                reader.get_disk_offset(locator.disk_with_eocd).unwrap_or(0) + locator.eocd_offset;
+            let signature_data = reader.read(offset, 4).await?;
+            (signature_data.len() == 4 && parse_u32_le(&signature_data) == 0x06064b50)
+                .then_some(offset)
+        } else {
+            None
+        };
+
+        let zip64_eocd_offset = if let Some(offset) = locator_eocd_offset {
+            offset
         } else {
             // Find Zip64 EOCD signature before EOCD
             let search_start = eocd_offset.saturating_sub(zip64_fallback_search_size);
@@ -241,66 +445,131 @@ async fn parse_zip<Reader: ZipReader, State: Sized>(
             header: zip64_eocd_header,
             extensible_data,
         };
-        Some((zip64_eocd, zip64_eocd_locator))
+        Some((zip64_eocd, zip64_eocd_locator, zip64_eocd_offset))
     } else {
         None
     };
 
+    // The central directory should end right where the (Zip64) EOCD begins
+    let central_directory_end = eocd_zip64
+        .as_ref()
+        .map_or(eocd_offset, |(_, _, offset)| *offset);
+    let eocd_zip64 = eocd_zip64.map(|(zip64_eocd, locator, _)| (zip64_eocd, locator));
+
     // Determine effective central directory parameters
     let (
+        disk_number,
+        central_directory_disk_number,
         effective_central_directory_offset,
         effective_central_directory_size,
         effective_total_entries,
     ) = if let Some((zip64_eocd, _)) = &eocd_zip64 {
-        if zip64_eocd.header.disk_number != zip64_eocd.header.disk_number_with_eocd
-            || zip64_eocd.header.total_entries != zip64_eocd.header.total_entries_on_disk
-        {
-            return Err(ZipParseError::MultipleDiskNotSupported);
-        }
-
         (
+            zip64_eocd.header.disk_number,
+            zip64_eocd.header.disk_number_with_eocd,
             zip64_eocd.header.central_directory_offset,
             zip64_eocd.header.central_directory_size,
             zip64_eocd.header.total_entries,
         )
     } else {
-        if eocd.disk_number != eocd.disk_number_with_eocd
-            || eocd.total_entries != eocd.entries_on_disk
-        {
-            return Err(ZipParseError::MultipleDiskNotSupported);
-        }
-
         (
+            eocd.disk_number as u32,
+            eocd.disk_number_with_eocd as u32,
             eocd.central_directory_offset as u64,
             eocd.central_directory_size as u64,
             eocd.total_entries as u64,
         )
     };
 
-    // Invoke EOCD callback
-    let mut state = on_eocd(eocd, eocd_zip64)?;
+    // The central directory is spread over multiple disks if it does not start on this disk.
+    // A non-zero disk number on an unmappable disk means the preceding volumes are missing.
+    let is_multiple_disk = disk_number != central_directory_disk_number
+        || (disk_number != 0 && reader.get_disk_offset(disk_number).is_none());
+    // Otherwise this disk holds the whole central directory, so both entry counts must agree
+    let entries_on_disk = eocd_zip64
+        .as_ref()
+        .map_or(eocd.entries_on_disk as u64, |(zip64_eocd, _)| {
+            zip64_eocd.header.total_entries_on_disk
+        });
+    if !is_multiple_disk && effective_total_entries != entries_on_disk {
+        on_warning(
+            None,
+            None,
+            ZipParseError::InconsistentValue {
+                name: "Entries on Disk",
+                expected: effective_total_entries,
+                found: entries_on_disk,
+            },
+        )?;
+    }
+    let central_directory_disk_offset = match reader.get_disk_offset(central_directory_disk_number)
+    {
+        Some(offset) if reader.get_disk_offset(disk_number).is_some() => offset,
+        _ if is_multiple_disk => return Err(ZipParseError::MultipleDiskNotSupported),
+        // The central directory is entirely on the only disk we have
+        _ => 0,
+    };
 
-    // Read central directory
-    let central_dir_data = reader
-        .read(
+    // Prepended data can only be detected for single-disk archives
+    let prefix_length = if central_directory_disk_number == 0 && disk_number == 0 {
+        detect_prefix_length(
+            reader,
             effective_central_directory_offset,
             effective_central_directory_size,
+            effective_total_entries,
+            central_directory_end,
         )
-        .await?;
+        .await?
+    } else {
+        0
+    };
+    if prefix_length != 0 {
+        on_warning(
+            None,
+            None,
+            ZipParseError::PrependedData {
+                size: prefix_length,
+            },
+        )?;
+    }
+
+    // Invoke EOCD callback
+    let mut state = on_eocd(eocd, eocd_zip64, prefix_length)?;
+
+    let mut central_directory = CentralDirectoryWindow {
+        start: prefix_length + central_directory_disk_offset + effective_central_directory_offset,
+        size: effective_central_directory_size,
+        offset: 0,
+        data: Vec::new(),
+    };
 
     // Parse all CDH entries and LFH entries
     let mut cdh_offset = 0;
     for idx in 0..effective_total_entries {
-        // Parse CDH
-        if cdh_offset + CentralDirectoryHeader::MIN_SIZE > central_dir_data.len() {
+        // Parse CDH, whose fixed part tells the length of the variable part
+        let cdh_data = central_directory
+            .read(
+                reader,
+                cdh_offset as u64,
+                CentralDirectoryHeader::MIN_SIZE as u64,
+            )
+            .await?;
+        if cdh_data.len() < CentralDirectoryHeader::MIN_SIZE {
             return Err(ZipParseError::LengthTooShort {
                 name: "CDH",
                 expected: cdh_offset + CentralDirectoryHeader::MIN_SIZE,
-                found: central_dir_data.len(),
+                found: cdh_offset + cdh_data.len(),
             });
         }
+        let cdh_length = CentralDirectoryHeader::MIN_SIZE
+            + parse_u16_le(&cdh_data[28..30]) as usize
+            + parse_u16_le(&cdh_data[30..32]) as usize
+            + parse_u16_le(&cdh_data[32..34]) as usize;
+        let cdh_data = central_directory
+            .read(reader, cdh_offset as u64, cdh_length as u64)
+            .await?;
 
-        let cdh = CentralDirectoryHeader::parse(&central_dir_data[cdh_offset..], |warning| {
+        let cdh = CentralDirectoryHeader::parse(cdh_data, |warning| {
             on_warning(Some(&mut state), Some(idx), warning)
         })?;
         cdh_offset += cdh.len();
@@ -309,40 +578,33 @@ async fn parse_zip<Reader: ZipReader, State: Sized>(
             return Err(ZipParseError::CentralDirectoryEncryptionNotSupported);
         }
 
-        // Parse LFH
-        let lfh_full_data = {
-            // To reduce number of reads, first read an assumed size of LFH
-            const ASSUMED_LFH_SIZE: u64 = 256;
-
-            let lfh_data = reader
-                .read(cdh.local_header_offset as u64, ASSUMED_LFH_SIZE)
-                .await?;
-            if lfh_data.len() < LocalFileHeader::MIN_SIZE {
-                return Err(ZipParseError::LengthTooShort {
-                    name: "LFH",
-                    expected: LocalFileHeader::MIN_SIZE,
-                    found: lfh_data.len(),
-                });
-            }
-
-            let filename_len = parse_u16_le(&lfh_data[26..28]) as usize;
-            let extra_field_len = parse_u16_le(&lfh_data[28..30]) as usize;
-            let lfh_full_size = LocalFileHeader::MIN_SIZE + filename_len + extra_field_len;
-
-            if lfh_full_size as u64 <= ASSUMED_LFH_SIZE {
-                lfh_data[..lfh_full_size].to_vec()
-            } else {
-                // Read full LFH
-                reader
-                    .read(cdh.local_header_offset as u64, lfh_full_size as u64)
-                    .await?
+        let disk_number_start = cdh.get_disk_number_start();
+        let disk_offset = match reader.get_disk_offset(disk_number_start) {
+            Some(offset) => offset,
+            None => {
+                // The disk is not available, which usually means the archive is a single file and the zip creator wrote
+                // garbage here. Since the offset is relative to the only disk we have, continue with a warning.
+                on_warning(
+                    Some(&mut state),
+                    Some(idx),
+                    ZipParseError::UnexpectedValue {
+                        name: "CDH Disk Number Start",
+                        expected: 0,
+                        found: disk_number_start as u64,
+                    },
+                )?;
+                0
             }
         };
-        let lfh = LocalFileHeader::parse(&lfh_full_data, |warning| {
+        let local_header_offset = prefix_length + disk_offset + cdh.get_local_header_offset();
+
+        // Parse LFH
+        let lfh = read_local_file_header(reader, local_header_offset, |warning| {
             on_warning(Some(&mut state), Some(idx), warning)
-        })?;
+        })
+        .await?;
 
-        let file_offset = cdh.local_header_offset as u64 + lfh.len() as u64;
+        let file_offset = local_header_offset + lfh.len() as u64;
         let file_size = cdh
             .zip64
             .and_then(|zip64| zip64.compressed_size)
@@ -514,10 +776,314 @@ async fn parse_zip<Reader: ZipReader, State: Sized>(
     Ok(state)
 }
 
-/// Trait for parsing zip files
+/// Detect the length of data prepended to the archive, such as a self-extractor stub
+///
+/// Like Info-ZIP, this compares where the central directory actually ends (right before the EOCD)
+/// with where the recorded offset and size say it ends. A positive difference is accepted as the
+/// prefix length only if the recorded offset does not point to a CDH while the adjusted one does.
+async fn detect_prefix_length<Reader: ZipReader>(
+    reader: &mut Reader,
+    central_directory_offset: u64,
+    central_directory_size: u64,
+    total_entries: u64,
+    central_directory_end: u64,
+) -> Result<u64, ZipParseError> {
+    let recorded_end = central_directory_offset.saturating_add(central_directory_size);
+    if total_entries == 0 || central_directory_end <= recorded_end {
+        return Ok(0);
+    }
+
+    let delta = central_directory_end - recorded_end;
+    for (offset, prefix_length) in [
+        (central_directory_offset, 0),
+        (central_directory_offset + delta, delta),
+    ] {
+        let signature_data = reader.read(offset, 4).await?;
+        if signature_data.len() == 4 && parse_u32_le(&signature_data) == 0x02014b50 {
+            return Ok(prefix_length);
+        }
+    }
+
+    Ok(0)
+}
+
+/// Read and parse a Local File Header at the given offset
+async fn read_local_file_header<Reader: ZipReader>(
+    reader: &mut Reader,
+    offset: u64,
+    on_warning: impl FnMut(ZipParseError) -> Result<(), ZipParseError>,
+) -> Result<LocalFileHeader, ZipParseError> {
+    // To reduce number of reads, first read an assumed size of LFH
+    const ASSUMED_LFH_SIZE: u64 = 256;
+
+    let lfh_data = reader.read(offset, ASSUMED_LFH_SIZE).await?;
+    if lfh_data.len() < LocalFileHeader::MIN_SIZE {
+        return Err(ZipParseError::LengthTooShort {
+            name: "LFH",
+            expected: LocalFileHeader::MIN_SIZE,
+            found: lfh_data.len(),
+        });
+    }
+
+    let filename_len = parse_u16_le(&lfh_data[26..28]) as usize;
+    let extra_field_len = parse_u16_le(&lfh_data[28..30]) as usize;
+    let lfh_full_size = LocalFileHeader::MIN_SIZE + filename_len + extra_field_len;
+
+    let lfh_full_data = if lfh_full_size <= lfh_data.len() {
+        lfh_data[..lfh_full_size].to_vec()
+    } else {
+        // Read full LFH
+        reader.read(offset, lfh_full_size as u64).await?
+    };
+
+    LocalFileHeader::parse(&lfh_full_data, on_warning)
+}
+
+/// Recover entries by scanning Local File Headers from the beginning of the data
+///
+/// Data before the first recovered LFH is treated as prepended data, and the offsets in the synthesized
+/// CDHs are relative to it.
+/// Returns the prefix length, the recovered entries and the offset where the scan stopped.
+async fn recover_zip<Reader: ZipReader>(
+    reader: &mut Reader,
+    mut on_warning: impl FnMut(Option<u64>, ZipParseError) -> Result<(), ZipParseError>,
+) -> Result<(u64, Vec<ZipFileEntry>, u64), ZipParseError> {
+    let file_size = reader.get_size().await?;
+
+    let mut prefix_length = 0;
+    let mut entries = Vec::new();
+    let mut offset = 0;
+    loop {
+        let idx = entries.len() as u64;
+
+        // Find the next LFH, stopping at the central directory or EOCD
+        let Some((lfh_offset, signature)) = find_signature(
+            reader,
+            offset,
+            file_size,
+            &[0x04034b50, 0x02014b50, 0x06054b50, 0x06064b50],
+        )
+        .await?
+        else {
+            if offset < file_size {
+                on_warning(
+                    None,
+                    ZipParseError::UnknownDataSkipped {
+                        offset,
+                        size: file_size - offset,
+                    },
+                )?;
+            }
+            offset = file_size;
+            break;
+        };
+
+        // Data before the first entry is reported as prepended data once the entry is recovered
+        if lfh_offset != offset && !(entries.is_empty() && signature == 0x04034b50) {
+            on_warning(
+                None,
+                ZipParseError::UnknownDataSkipped {
+                    offset,
+                    size: lfh_offset - offset,
+                },
+            )?;
+        }
+
+        offset = lfh_offset;
+        if signature != 0x04034b50 {
+            break;
+        }
+
+        let lfh = match read_local_file_header(reader, lfh_offset, |warning| {
+            on_warning(Some(idx), warning)
+        })
+        .await
+        {
+            Ok(lfh) => lfh,
+            Err(ZipParseError::Io(e)) => return Err(ZipParseError::Io(e)),
+            Err(e) => {
+                // Probably a signature that happens to appear in garbage data
+                on_warning(Some(idx), e)?;
+                offset = lfh_offset + 1;
+                continue;
+            }
+        };
+
+        let file_offset = lfh_offset + lfh.len() as u64;
+
+        // Determine the bounds of the compressed data
+        let bounds = if lfh.flags.has_data_descriptor() {
+            let is_zip64 = lfh
+                .extra_fields
+                .iter()
+                .any(|ef| ef.tag == Zip64ExtendedInfo::TAG);
+            locate_data_descriptor(reader, file_offset, file_size, is_zip64)
+                .await?
+                .map(|(file_size, descriptor, end)| (file_size, Some(descriptor), end))
+        } else {
+            let file_size_in_lfh = lfh
+                .zip64
+                .and_then(|zip64| zip64.compressed_size)
+                .unwrap_or(lfh.compressed_size as u64);
+            file_offset
+                .checked_add(file_size_in_lfh)
+                .filter(|&end| end <= file_size)
+                .map(|end| (file_size_in_lfh, None, end))
+        };
+
+        let Some((entry_file_size, descriptor, end)) = bounds else {
+            // Skip this entry and look for the next signature
+            on_warning(
+                Some(idx),
+                ZipParseError::UnboundedEntry { offset: lfh_offset },
+            )?;
+            offset = file_offset;
+            continue;
+        };
+
+        if entries.is_empty() {
+            prefix_length = lfh_offset;
+            if prefix_length != 0 {
+                on_warning(
+                    None,
+                    ZipParseError::PrependedData {
+                        size: prefix_length,
+                    },
+                )?;
+            }
+        }
+
+        let cdh = CentralDirectoryHeader::synthesize(
+            &lfh,
+            descriptor,
+            lfh_offset - prefix_length,
+            entry_file_size,
+        );
+        entries.push(ZipFileEntry {
+            cdh,
+            lfh,
+            descriptor,
+            file_offset,
+            file_size: entry_file_size,
+        });
+
+        offset = end;
+    }
+
+    Ok((prefix_length, entries, offset))
+}
+
+/// Scan forward for the first occurrence of any of the given signatures within `start..end`
+async fn find_signature<Reader: ZipReader>(
+    reader: &mut Reader,
+    start: u64,
+    end: u64,
+    signatures: &[u32],
+) -> Result<Option<(u64, u32)>, ZipParseError> {
+    const CHUNK_SIZE: u64 = 64 * 1024;
+
+    let mut offset = start;
+    while offset.saturating_add(4) <= end {
+        let data = reader
+            .read(offset, std::cmp::min(CHUNK_SIZE, end - offset))
+            .await?;
+        if data.len() < 4 {
+            break;
+        }
+
+        for i in 0..=data.len() - 4 {
+            let value = parse_u32_le(&data[i..i + 4]);
+            if signatures.contains(&value) {
+                return Ok(Some((offset + i as u64, value)));
+            }
+        }
+
+        // Overlap by 3 bytes so that signatures crossing chunk boundaries are found
+        offset += data.len() as u64 - 3;
+    }
+
+    Ok(None)
+}
+
+/// Locate the Data Descriptor of an entry by scanning forward from the start of its compressed data
+///
+/// A candidate is accepted only if the compressed size recorded in the descriptor matches its distance from the data start.
+/// Signature-less descriptors are found by looking back from the signature of the section that follows them.
+/// Returns the compressed size, the parsed Data Descriptor, and the offset right after the descriptor.
+async fn locate_data_descriptor<Reader: ZipReader>(
+    reader: &mut Reader,
+    file_offset: u64,
+    file_size: u64,
+    is_zip64: bool,
+) -> Result<Option<(u64, DataDescriptor, u64)>, ZipParseError> {
+    let (inspect_size, content_size) = if is_zip64 {
+        (DataDescriptor::INSPECT_SIZE_ZIP64 as u64, 20)
+    } else {
+        (DataDescriptor::INSPECT_SIZE_STANDARD as u64, 12)
+    };
+
+    let mut offset = file_offset;
+    while let Some((found, signature)) = find_signature(
+        reader,
+        offset,
+        file_size,
+        &[0x08074b50, 0x04034b50, 0x02014b50, 0x06054b50, 0x06064b50],
+    )
+    .await?
+    {
+        offset = found + 1;
+
+        let descriptor_offset = if signature == 0x08074b50 {
+            found
+        } else if found >= file_offset + content_size {
+            found - content_size
+        } else {
+            continue;
+        };
+
+        let data = reader.read(descriptor_offset, inspect_size).await?;
+        let result = if is_zip64 {
+            DataDescriptor::parse_zip64(&data)
+        } else {
+            DataDescriptor::parse_standard(&data)
+        };
+        let Ok(descriptor) = result else {
+            continue;
+        };
+
+        let compressed_size = descriptor_offset - file_offset;
+        if descriptor.get_compressed_size() != compressed_size {
+            continue;
+        }
+
+        let descriptor_size = if descriptor.get_signature().is_some() {
+            4 + content_size
+        } else {
+            content_size
+        };
+        return Ok(Some((
+            compressed_size,
+            descriptor,
+            descriptor_offset + descriptor_size,
+        )));
+    }
+
+    Ok(None)
+}
+
+/// Trait for parsing zip files incrementally with [`parse_with`]
+///
+/// Entries are passed to the parser one by one as they are read, so the whole entry list does not
+/// have to be held in memory. Returning an error from any callback aborts the parse with that error.
 pub trait ZipFileParser: Sized {
-    const ZIP64_FALLBACK_SEARCH_SIZE: u64 = 1024 * 1024; // 1 MiB
+    const ZIP64_FALLBACK_SEARCH_SIZE: u64 = DEFAULT_ZIP64_FALLBACK_SEARCH_SIZE;
 
+    /// Whether to ignore Data Descriptors when determining entry sizes
+    fn ignore_data_descriptor(&self) -> bool {
+        false
+    }
+
+    /// Called once the EOCD records are read, before any entry
     fn on_eocf(
         &mut self,
         eocd: EndOfCentralDirectory,
@@ -525,11 +1091,50 @@ pub trait ZipFileParser: Sized {
             Zip64EndOfCentralDirectory,
             Option<Zip64EndOfCentralDirectoryLocator>,
         )>,
+        prefix_length: u64,
     ) -> Result<(), ZipParseError>;
 
+    /// Called for each entry in central directory order
     fn on_entry(&mut self, entry: ZipFileEntry) -> Result<(), ZipParseError>;
 
-    fn on_warning(&mut self, warning: ZipParseError) -> Result<(), ZipParseError>;
+    /// Called for each recoverable problem, with the index of the entry it belongs to if any
+    fn on_warning(&mut self, idx: Option<u64>, warning: ZipParseError)
+    -> Result<(), ZipParseError>;
+}
+
+/// Parse a zip file from a ZipReader, passing the results to the given parser
+///
+/// Warnings found before the EOCD callback are delivered before it is invoked.
+pub async fn parse_with<Reader: ZipReader, Parser: ZipFileParser>(
+    reader: &mut Reader,
+    parser: &mut Parser,
+) -> Result<(), ZipParseError> {
+    let ignore_data_descriptor = parser.ignore_data_descriptor();
+    let early_warnings = std::cell::RefCell::new(Vec::new());
+
+    parse_zip(
+        reader,
+        Parser::ZIP64_FALLBACK_SEARCH_SIZE,
+        ignore_data_descriptor,
+        |eocd, zip64_eocd, prefix_length| {
+            for (idx, warning) in early_warnings.take() {
+                parser.on_warning(idx, warning)?;
+            }
+            parser.on_eocf(eocd, zip64_eocd, prefix_length)?;
+            Ok(parser)
+        },
+        |parser, entry| parser.on_entry(entry),
+        |parser, idx, warning| match parser {
+            Some(parser) => parser.on_warning(idx, warning),
+            None => {
+                early_warnings.borrow_mut().push((idx, warning));
+                Ok(())
+            }
+        },
+    )
+    .await?;
+
+    Ok(())
 }
 
 /// Central Directory Header (CDH)
@@ -579,12 +1184,28 @@ pub struct CentralDirectoryHeader {
     pub zip64: Option<Zip64ExtendedInfo>,
     /// Optional unicode path extra field
     pub unicode_path: Option<UnicodePathExtraField>,
+    /// Optional unicode comment extra field
+    pub unicode_comment: Option<UnicodeCommentExtraField>,
 }
 
 impl CentralDirectoryHeader {
     /// Minimum size of CDH without variable-length fields
     pub const MIN_SIZE: usize = 46;
 
+    /// Get the offset of the local header, preferring the Zip64 extended info if present
+    pub fn get_local_header_offset(&self) -> u64 {
+        self.zip64
+            .and_then(|zip64| zip64.relative_offset)
+            .unwrap_or(self.local_header_offset as u64)
+    }
+
+    /// Get the disk number where the entry starts, preferring the Zip64 extended info if present
+    pub fn get_disk_number_start(&self) -> u32 {
+        self.zip64
+            .and_then(|zip64| zip64.disk_start_number)
+            .unwrap_or(self.disk_number_start as u32)
+    }
+
     /// Get total size of CDH including variable-length fields
     pub fn len(&self) -> usize {
         Self::MIN_SIZE
@@ -598,6 +1219,112 @@ impl CentralDirectoryHeader {
         false
     }
 
+    /// Synthesize a Central Directory Header from a Local File Header
+    ///
+    /// Used when recovering entries without a central directory. Values missing from the LFH
+    /// (such as sizes and CRC32 deferred to a Data Descriptor) are taken from the descriptor.
+    /// Extra fields of the LFH that no longer fit next to the needed Zip64 field are dropped.
+    pub fn synthesize(
+        lfh: &LocalFileHeader,
+        descriptor: Option<DataDescriptor>,
+        local_header_offset: u64,
+        compressed_size: u64,
+    ) -> Self {
+        let crc32 = descriptor.map_or(lfh.crc32, |descriptor| descriptor.get_crc32());
+        let uncompressed_size = descriptor.map_or_else(
+            || {
+                lfh.zip64
+                    .and_then(|zip64| zip64.uncompressed_size)
+                    .unwrap_or(lfh.uncompressed_size as u64)
+            },
+            |descriptor| descriptor.get_uncompressed_size(),
+        );
+
+        let mut extra_fields = lfh
+            .extra_fields
+            .iter()
+            .filter(|ef| ef.tag != Zip64ExtendedInfo::TAG)
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let zip64 = Zip64ExtendedInfo {
+            uncompressed_size: (uncompressed_size >= 0xFFFFFFFF).then_some(uncompressed_size),
+            compressed_size: (compressed_size >= 0xFFFFFFFF).then_some(compressed_size),
+            relative_offset: (local_header_offset >= 0xFFFFFFFF).then_some(local_header_offset),
+            disk_start_number: None,
+        };
+        let zip64 = if zip64.uncompressed_size.is_some()
+            || zip64.compressed_size.is_some()
+            || zip64.relative_offset.is_some()
+        {
+            let data = [
+                zip64.uncompressed_size,
+                zip64.compressed_size,
+                zip64.relative_offset,
+            ]
+            .into_iter()
+            .flatten()
+            .flat_map(u64::to_le_bytes)
+            .collect::<Vec<_>>();
+            extra_fields.insert(
+                0,
+                ExtraField {
+                    tag: Zip64ExtendedInfo::TAG,
+                    size: data.len() as u16,
+                    data,
+                },
+            );
+            Some(zip64)
+        } else {
+            None
+        };
+
+        // The synthesized Zip64 field can push the fields taken from the LFH over the 16-bit length,
+        // in which case the last other fields are dropped until they fit
+        let extra_field_length =
+            |fields: &[ExtraField]| fields.iter().map(|ef| 4 + ef.size as usize).sum::<usize>();
+        while extra_field_length(&extra_fields) > 0xFFFF
+            && let Some(position) = extra_fields
+                .iter()
+                .rposition(|ef| ef.tag != Zip64ExtendedInfo::TAG)
+        {
+            extra_fields.remove(position);
+        }
+
+        // Mark directories with the MS-DOS directory attribute
+        let external_file_attributes = if lfh.filename.ends_with(b"/") {
+            0x10
+        } else {
+            0
+        };
+
+        CentralDirectoryHeader {
+            signature: 0x02014b50,
+            version_made_by: lfh.version_needed,
+            version_needed: lfh.version_needed,
+            flags: lfh.flags,
+            compression_method: lfh.compression_method,
+            last_mod_time: lfh.last_mod_time,
+            last_mod_date: lfh.last_mod_date,
+            crc32,
+            compressed_size: std::cmp::min(compressed_size, 0xFFFFFFFF) as u32,
+            uncompressed_size: std::cmp::min(uncompressed_size, 0xFFFFFFFF) as u32,
+            filename_length: lfh.filename_length,
+            extra_field_length: extra_field_length(&extra_fields) as u16,
+            file_comment_length: 0,
+            disk_number_start: 0,
+            internal_file_attributes: 0,
+            external_file_attributes,
+            local_header_offset: std::cmp::min(local_header_offset, 0xFFFFFFFF) as u32,
+            filename: lfh.filename.clone(),
+            extra_fields,
+            file_comment: Vec::new(),
+            zip64,
+            unicode_path: lfh.unicode_path.clone(),
+            unicode_comment: None,
+        }
+    }
+
     /// Parse a Central Directory Header from binary data
     pub fn parse(
         data: &[u8],
@@ -677,6 +1404,13 @@ impl CentralDirectoryHeader {
             .transpose()
             .or_else(|e| on_warning(e).map(|_| None))?;
 
+        let unicode_comment = extra_fields
+            .iter()
+            .find(|ef| ef.tag == UnicodeCommentExtraField::TAG)
+            .map(|ef| UnicodeCommentExtraField::parse(ef, &file_comment))
+            .transpose()
+            .or_else(|e| on_warning(e).map(|_| None))?;
+
         Ok(CentralDirectoryHeader {
             signature,
             version_made_by,
@@ -700,6 +1434,7 @@ impl CentralDirectoryHeader {
             file_comment,
             zip64,
             unicode_path,
+            unicode_comment,
         })
     }
 }
@@ -998,37 +1733,100 @@ impl Zip64ExtendedInfo {
             None
         };
 
-        Ok(Zip64ExtendedInfo {
-            uncompressed_size,
-            compressed_size,
-            relative_offset,
-            disk_start_number,
+        Ok(Zip64ExtendedInfo {
+            uncompressed_size,
+            compressed_size,
+            relative_offset,
+            disk_start_number,
+        })
+    }
+}
+
+/// Unicode Path Extra Field
+#[derive(Debug, Clone)]
+pub struct UnicodePathExtraField {
+    /// Version
+    pub version: u8,
+    /// Name CRC32
+    pub name_crc32: u32,
+    /// Unicode name
+    pub data: Vec<u8>,
+    /// Parsed UTF-8 string
+    pub decoded_string: Option<String>,
+    /// Whether the CRC32 of the original name matches
+    pub crc32_matched: bool,
+}
+
+impl UnicodePathExtraField {
+    pub const TAG: u16 = 0x7075;
+
+    pub fn parse(field: &ExtraField, original_name: &[u8]) -> Result<Self, ZipParseError> {
+        if field.tag != Self::TAG {
+            return Err(ZipParseError::InvalidExtraFieldTag {
+                name: "UnicodePathExtraField",
+                expected: Self::TAG,
+                found: field.tag,
+            });
+        }
+
+        if field.size < 5 {
+            return Err(ZipParseError::LengthTooShort {
+                name: "UnicodePathExtraField",
+                expected: 5,
+                found: field.size as usize,
+            });
+        }
+
+        let version = field.data[0];
+        if version != 1 {
+            return Err(ZipParseError::UnsupportedVersion {
+                name: "UnicodePathExtraField",
+                expected: 1,
+                found: version,
+            });
+        }
+
+        let name_crc32 = parse_u32_le(&field.data[1..5]);
+        let utf8_data = field.data[5..].to_vec();
+
+        let computed_crc32 =
+            crc_fast::checksum(crc_fast::CrcAlgorithm::Crc32IsoHdlc, original_name) as u32;
+        let crc32_matched = computed_crc32 == name_crc32;
+
+        let utf8_string = String::from_utf8(utf8_data.clone()).ok();
+
+        Ok(UnicodePathExtraField {
+            version,
+            name_crc32,
+            data: utf8_data,
+            decoded_string: utf8_string,
+            crc32_matched,
         })
     }
 }
 
-/// Unicode Path Extra Field
+/// Unicode Comment Extra Field
 #[derive(Debug, Clone)]
-pub struct UnicodePathExtraField {
+pub struct UnicodeCommentExtraField {
     /// Version
     pub version: u8,
-    /// Name CRC32
-    pub name_crc32: u32,
-    /// Unicode name
+    /// Comment CRC32
+    pub comment_crc32: u32,
+    /// Unicode comment
     pub data: Vec<u8>,
     /// Parsed UTF-8 string
     pub decoded_string: Option<String>,
-    /// Whether the CRC32 of the original name matches
+    /// Whether the CRC32 of the original comment matches
     pub crc32_matched: bool,
 }
 
-impl UnicodePathExtraField {
-    pub const TAG: u16 = 0x7075;
+impl UnicodeCommentExtraField {
+    pub const TAG: u16 = 0x6375;
 
-    pub fn parse(field: &ExtraField, original_name: &[u8]) -> Result<Self, ZipParseError> {
+    pub fn parse(field: &ExtraField, original_comment: &[u8]) -> Result<Self, ZipParseError> {
         if field.tag != Self::TAG {
             return Err(ZipParseError::InvalidExtraFieldTag {
-                name: "UnicodePathExtraField",
+                name: "UnicodeCommentExtraField",
                 expected: Self::TAG,
                 found: field.tag,
             });
@@ -1036,7 +1834,7 @@ impl UnicodePathExtraField {
 
         if field.size < 5 {
             return Err(ZipParseError::LengthTooShort {
-                name: "UnicodePathExtraField",
+                name: "UnicodeCommentExtraField",
                 expected: 5,
                 found: field.size as usize,
             });
@@ -1045,24 +1843,24 @@ impl UnicodePathExtraField {
         let version = field.data[0];
         if version != 1 {
             return Err(ZipParseError::UnsupportedVersion {
-                name: "UnicodePathExtraField",
+                name: "UnicodeCommentExtraField",
                 expected: 1,
                 found: version,
             });
         }
 
-        let name_crc32 = parse_u32_le(&field.data[1..5]);
+        let comment_crc32 = parse_u32_le(&field.data[1..5]);
         let utf8_data = field.data[5..].to_vec();
 
         let computed_crc32 =
-            crc_fast::checksum(crc_fast::CrcAlgorithm::Crc32IsoHdlc, original_name) as u32;
-        let crc32_matched = computed_crc32 == name_crc32;
+            crc_fast::checksum(crc_fast::CrcAlgorithm::Crc32IsoHdlc, original_comment) as u32;
+        let crc32_matched = computed_crc32 == comment_crc32;
 
         let utf8_string = String::from_utf8(utf8_data.clone()).ok();
 
-        Ok(UnicodePathExtraField {
+        Ok(UnicodeCommentExtraField {
             version,
-            name_crc32,
+            comment_crc32,
             data: utf8_data,
             decoded_string: utf8_string,
             crc32_matched,
@@ -1070,6 +1868,182 @@ impl UnicodePathExtraField {
     }
 }
 
+/// Extended Timestamp Extra Field
+///
+/// Times are in seconds since the Unix epoch. The CDH version only carries the modification time
+/// even if the flags indicate more.
+#[derive(Debug, Clone, Copy)]
+pub struct ExtendedTimestampExtraField {
+    /// Flags indicating which times are present
+    pub flags: u8,
+    /// Last modification time
+    pub modification_time: Option<i32>,
+    /// Last access time
+    pub access_time: Option<i32>,
+    /// Creation time
+    pub creation_time: Option<i32>,
+}
+
+impl ExtendedTimestampExtraField {
+    pub const TAG: u16 = 0x5455;
+
+    pub fn parse(field: &ExtraField) -> Result<Self, ZipParseError> {
+        if field.tag != Self::TAG {
+            return Err(ZipParseError::InvalidExtraFieldTag {
+                name: "ExtendedTimestampExtraField",
+                expected: Self::TAG,
+                found: field.tag,
+            });
+        }
+
+        if field.data.is_empty() {
+            return Err(ZipParseError::LengthTooShort {
+                name: "ExtendedTimestampExtraField",
+                expected: 1,
+                found: 0,
+            });
+        }
+
+        let flags = field.data[0];
+        let mut cursor = 1;
+        let mut next_time = |bit: u8| {
+            if flags & bit == 0 || cursor + 4 > field.data.len() {
+                return None;
+            }
+            let time = parse_u32_le(&field.data[cursor..cursor + 4]) as i32;
+            cursor += 4;
+            Some(time)
+        };
+        let modification_time = next_time(0x01);
+        let access_time = next_time(0x02);
+        let creation_time = next_time(0x04);
+
+        Ok(ExtendedTimestampExtraField {
+            flags,
+            modification_time,
+            access_time,
+            creation_time,
+        })
+    }
+}
+
+/// NTFS Extra Field
+///
+/// Times are Windows FILETIME values, i.e. 100-nanosecond intervals since 1601-01-01 UTC.
+#[derive(Debug, Clone, Copy)]
+pub struct NtfsExtraField {
+    /// Last modification time
+    pub modification_time: u64,
+    /// Last access time
+    pub access_time: u64,
+    /// Creation time
+    pub creation_time: u64,
+}
+
+impl NtfsExtraField {
+    pub const TAG: u16 = 0x000a;
+
+    /// Attribute tag holding the file times
+    const TIMES_ATTRIBUTE_TAG: u16 = 0x0001;
+
+    pub fn parse(field: &ExtraField) -> Result<Self, ZipParseError> {
+        if field.tag != Self::TAG {
+            return Err(ZipParseError::InvalidExtraFieldTag {
+                name: "NtfsExtraField",
+                expected: Self::TAG,
+                found: field.tag,
+            });
+        }
+
+        // Skip the reserved field, then look for the attribute holding the file times
+        let mut offset = 4;
+        while offset + 4 <= field.data.len() {
+            let tag = parse_u16_le(&field.data[offset..offset + 2]);
+            let size = parse_u16_le(&field.data[offset + 2..offset + 4]) as usize;
+            offset += 4;
+
+            if offset + size > field.data.len() {
+                break;
+            }
+
+            if tag == Self::TIMES_ATTRIBUTE_TAG {
+                if size < 24 {
+                    return Err(ZipParseError::LengthTooShort {
+                        name: "NtfsExtraField",
+                        expected: 24,
+                        found: size,
+                    });
+                }
+
+                return Ok(NtfsExtraField {
+                    modification_time: parse_u64_le(&field.data[offset..offset + 8]),
+                    access_time: parse_u64_le(&field.data[offset + 8..offset + 16]),
+                    creation_time: parse_u64_le(&field.data[offset + 16..offset + 24]),
+                });
+            }
+
+            offset += size;
+        }
+
+        Err(ZipParseError::Other(
+            "NtfsExtraField does not contain file times".to_string(),
+        ))
+    }
+}
+
+/// Info-ZIP Unix Extra Field (type 1, superseded by the Extended Timestamp Extra Field)
+///
+/// Times are in seconds since the Unix epoch. UID and GID are only present in the LFH version.
+#[derive(Debug, Clone, Copy)]
+pub struct InfoZipUnixExtraField {
+    /// Last access time
+    pub access_time: i32,
+    /// Last modification time
+    pub modification_time: i32,
+    /// User ID
+    pub uid: Option<u16>,
+    /// Group ID
+    pub gid: Option<u16>,
+}
+
+impl InfoZipUnixExtraField {
+    pub const TAG: u16 = 0x5855;
+
+    pub fn parse(field: &ExtraField) -> Result<Self, ZipParseError> {
+        if field.tag != Self::TAG {
+            return Err(ZipParseError::InvalidExtraFieldTag {
+                name: "InfoZipUnixExtraField",
+                expected: Self::TAG,
+                found: field.tag,
+            });
+        }
+
+        if field.data.len() < 8 {
+            return Err(ZipParseError::LengthTooShort {
+                name: "InfoZipUnixExtraField",
+                expected: 8,
+                found: field.data.len(),
+            });
+        }
+
+        let (uid, gid) = if field.data.len() >= 12 {
+            (
+                Some(parse_u16_le(&field.data[8..10])),
+                Some(parse_u16_le(&field.data[10..12])),
+            )
+        } else {
+            (None, None)
+        };
+
+        Ok(InfoZipUnixExtraField {
+            access_time: parse_u32_le(&field.data[0..4]) as i32,
+            modification_time: parse_u32_le(&field.data[4..8]) as i32,
+            uid,
+            gid,
+        })
+    }
+}
+
 /// Data Descriptor
 #[derive(Debug, Clone, Copy)]
 pub enum DataDescriptor {
@@ -1119,6 +2093,14 @@ impl DataDescriptor {
         }
     }
 
+    /// Get the signature, if present
+    pub fn get_signature(&self) -> Option<u32> {
+        match *self {
+            DataDescriptor::Standard { signature, .. } => signature,
+            DataDescriptor::Zip64 { signature, .. } => signature,
+        }
+    }
+
     /// Get the CRC32 value
     pub fn get_crc32(&self) -> u32 {
         match *self {
@@ -1481,7 +2463,7 @@ impl Zip64EndOfCentralDirectoryHeader {
 pub enum ZipParseError {
     #[error("Central directory encryption is not supported")]
     CentralDirectoryEncryptionNotSupported,
-    #[error("Multiple disk ZIP files are not supported")]
+    #[error("Multiple disk ZIP files are not supported unless all volumes are provided")]
     MultipleDiskNotSupported,
     #[error("Invalid signature for {name}, expected {expected:#010x} but found {found:#010x}")]
     InvalidSignature {
@@ -1536,6 +2518,14 @@ pub enum ZipParseError {
     },
     #[error("Unknown data found after Data Descriptor")]
     UnknownDataAfterDescriptor,
+    #[error("Unknown data found at offset {offset}, {size} bytes skipped")]
+    UnknownDataSkipped { offset: u64, size: u64 },
+    #[error("Could not determine the end of the entry at offset {offset}")]
+    UnboundedEntry { offset: u64 },
+    #[error(
+        "Found {size} bytes of data prepended to the archive, offsets are adjusted accordingly"
+    )]
+    PrependedData { size: u64 },
     #[error("Other error: {0}")]
     Other(String),
     #[error("IO error: {0}")]
@@ -1591,6 +2581,161 @@ mod tests {
         }
     }
 
+    /// Mock reader that records the size of every read
+    struct RecordingMockReader {
+        inner: MockReader,
+        read_sizes: Vec<u64>,
+    }
+
+    impl ZipReader for RecordingMockReader {
+        async fn get_size(&mut self) -> Result<u64, io::Error> {
+            self.inner.get_size().await
+        }
+
+        async fn read(&mut self, offset: u64, size: u64) -> Result<Vec<u8>, io::Error> {
+            self.read_sizes.push(size);
+            self.inner.read(offset, size).await
+        }
+    }
+
+    /// Mock reader that serves a large virtual file made of sparse segments, filling gaps with zeros
+    struct SparseMockReader {
+        size: u64,
+        segments: Vec<(u64, Vec<u8>)>,
+    }
+
+    impl SparseMockReader {
+        fn new(size: u64, segments: Vec<(u64, Vec<u8>)>) -> Self {
+            SparseMockReader { size, segments }
+        }
+    }
+
+    impl ZipReader for SparseMockReader {
+        async fn get_size(&mut self) -> Result<u64, io::Error> {
+            Ok(self.size)
+        }
+
+        async fn read(&mut self, offset: u64, size: u64) -> Result<Vec<u8>, io::Error> {
+            if offset >= self.size {
+                return Ok(Vec::new());
+            }
+
+            let end = std::cmp::min(offset + size, self.size);
+            let mut buffer = vec![0u8; (end - offset) as usize];
+            for (segment_offset, segment) in &self.segments {
+                let segment_end = segment_offset + segment.len() as u64;
+                if segment_end <= offset || *segment_offset >= end {
+                    continue;
+                }
+
+                let copy_start = std::cmp::max(offset, *segment_offset);
+                let copy_end = std::cmp::min(end, segment_end);
+                buffer[(copy_start - offset) as usize..(copy_end - offset) as usize]
+                    .copy_from_slice(
+                        &segment[(copy_start - segment_offset) as usize
+                            ..(copy_end - segment_offset) as usize],
+                    );
+            }
+            Ok(buffer)
+        }
+    }
+
+    /// Build a sparse Zip64 archive with a single stored entry whose LFH starts at `lfh_offset`
+    ///
+    /// The CDH records the offset only in the Zip64 extended info, optionally with a disk start number.
+    fn build_sparse_zip64_archive(
+        lfh_offset: u64,
+        content: &[u8],
+        disk_start_number: Option<u32>,
+    ) -> SparseMockReader {
+        let filename = b"large/photo.jpg";
+
+        let mut lfh = Vec::new();
+        lfh.extend_from_slice(&0x04034b50u32.to_le_bytes()); // Signature
+        lfh.extend_from_slice(&45u16.to_le_bytes()); // Version needed
+        lfh.extend_from_slice(&0u16.to_le_bytes()); // Flags
+        lfh.extend_from_slice(&0u16.to_le_bytes()); // Compression method
+        lfh.extend_from_slice(&0u16.to_le_bytes()); // Last mod time
+        lfh.extend_from_slice(&0u16.to_le_bytes()); // Last mod date
+        lfh.extend_from_slice(&0u32.to_le_bytes()); // CRC32
+        lfh.extend_from_slice(&(content.len() as u32).to_le_bytes()); // Compressed size
+        lfh.extend_from_slice(&(content.len() as u32).to_le_bytes()); // Uncompressed size
+        lfh.extend_from_slice(&(filename.len() as u16).to_le_bytes()); // Filename length
+        lfh.extend_from_slice(&0u16.to_le_bytes()); // Extra field length
+        lfh.extend_from_slice(filename); // Filename
+        lfh.extend_from_slice(content); // File data
+
+        let mut zip64_extra = Vec::new();
+        zip64_extra.extend_from_slice(&lfh_offset.to_le_bytes());
+        if let Some(disk_start_number) = disk_start_number {
+            zip64_extra.extend_from_slice(&disk_start_number.to_le_bytes());
+        }
+
+        let cd_offset = lfh_offset + lfh.len() as u64;
+        let mut cd = Vec::new();
+        cd.extend_from_slice(&0x02014b50u32.to_le_bytes()); // Signature
+        cd.extend_from_slice(&45u16.to_le_bytes()); // Version made by
+        cd.extend_from_slice(&45u16.to_le_bytes()); // Version needed
+        cd.extend_from_slice(&0u16.to_le_bytes()); // Flags
+        cd.extend_from_slice(&0u16.to_le_bytes()); // Compression method
+        cd.extend_from_slice(&0u16.to_le_bytes()); // Last mod time
+        cd.extend_from_slice(&0u16.to_le_bytes()); // Last mod date
+        cd.extend_from_slice(&0u32.to_le_bytes()); // CRC32
+        cd.extend_from_slice(&(content.len() as u32).to_le_bytes()); // Compressed size
+        cd.extend_from_slice(&(content.len() as u32).to_le_bytes()); // Uncompressed size
+        cd.extend_from_slice(&(filename.len() as u16).to_le_bytes()); // Filename length
+        cd.extend_from_slice(&(4 + zip64_extra.len() as u16).to_le_bytes()); // Extra field length
+        cd.extend_from_slice(&0u16.to_le_bytes()); // File comment length
+        cd.extend_from_slice(
+            &(if disk_start_number.is_some() {
+                0xFFFFu16
+            } else {
+                0u16
+            })
+            .to_le_bytes(),
+        ); // Disk number start
+        cd.extend_from_slice(&0u16.to_le_bytes()); // Internal file attributes
+        cd.extend_from_slice(&0u32.to_le_bytes()); // External file attributes
+        cd.extend_from_slice(&0xFFFFFFFFu32.to_le_bytes()); // Local header offset
+        cd.extend_from_slice(filename); // Filename
+        cd.extend_from_slice(&Zip64ExtendedInfo::TAG.to_le_bytes()); // Zip64 tag
+        cd.extend_from_slice(&(zip64_extra.len() as u16).to_le_bytes()); // Zip64 size
+        cd.extend_from_slice(&zip64_extra); // Zip64 data
+
+        let zip64_eocd_offset = cd_offset + cd.len() as u64;
+        let mut tail = Vec::new();
+        tail.extend_from_slice(&0x06064b50u32.to_le_bytes()); // Signature
+        tail.extend_from_slice(&44u64.to_le_bytes()); // Size of record
+        tail.extend_from_slice(&45u16.to_le_bytes()); // Version made by
+        tail.extend_from_slice(&45u16.to_le_bytes()); // Version needed
+        tail.extend_from_slice(&0u32.to_le_bytes()); // Disk number
+        tail.extend_from_slice(&0u32.to_le_bytes()); // Disk number with EOCD
+        tail.extend_from_slice(&1u64.to_le_bytes()); // Entries on disk
+        tail.extend_from_slice(&1u64.to_le_bytes()); // Total entries
+        tail.extend_from_slice(&(cd.len() as u64).to_le_bytes()); // Central directory size
+        tail.extend_from_slice(&cd_offset.to_le_bytes()); // Central directory offset
+
+        tail.extend_from_slice(&0x07064b50u32.to_le_bytes()); // Signature
+        tail.extend_from_slice(&0u32.to_le_bytes()); // Disk with Zip64 EOCD
+        tail.extend_from_slice(&zip64_eocd_offset.to_le_bytes()); // Zip64 EOCD offset
+        tail.extend_from_slice(&1u32.to_le_bytes()); // Total disks
+
+        tail.extend_from_slice(&0x06054b50u32.to_le_bytes()); // Signature
+        tail.extend_from_slice(&0u16.to_le_bytes()); // Disk number
+        tail.extend_from_slice(&0u16.to_le_bytes()); // Disk number with EOCD
+        tail.extend_from_slice(&0xFFFFu16.to_le_bytes()); // Entries on disk
+        tail.extend_from_slice(&0xFFFFu16.to_le_bytes()); // Total entries
+        tail.extend_from_slice(&0xFFFFFFFFu32.to_le_bytes()); // Central directory size
+        tail.extend_from_slice(&0xFFFFFFFFu32.to_le_bytes()); // Central directory offset
+        tail.extend_from_slice(&0u16.to_le_bytes()); // Comment length
+
+        let mut segment = lfh;
+        segment.extend_from_slice(&cd);
+        segment.extend_from_slice(&tail);
+        let size = lfh_offset + segment.len() as u64;
+        SparseMockReader::new(size, vec![(lfh_offset, segment)])
+    }
+
     #[test]
     fn test_parse_u32_le() {
         let data = [0x12, 0x34, 0x56, 0x78];
@@ -1757,6 +2902,517 @@ mod tests {
         assert_eq!(zip.entries[0].cdh.filename, b"test");
     }
 
+    #[tokio::test]
+    async fn test_parse_zip64_local_header_offset_beyond_4gib() {
+        // 5 GiB + 123 bytes; the lower 32 bits point to an all-zero region
+        let lfh_offset = 5 * 1024 * 1024 * 1024 + 123;
+        let mut reader = build_sparse_zip64_archive(lfh_offset, b"hello", None);
+
+        let (zip, warnings) = ZipFile::parse_with_warnings(&mut reader, false)
+            .await
+            .unwrap();
+        assert!(warnings.is_empty(), "unexpected warnings: {warnings:?}");
+        assert_eq!(zip.entries.len(), 1);
+
+        let entry = &zip.entries[0];
+        assert_eq!(entry.cdh.local_header_offset, 0xFFFFFFFF);
+        assert_eq!(entry.cdh.get_local_header_offset(), lfh_offset);
+        assert_eq!(entry.lfh.filename, b"large/photo.jpg");
+        assert_eq!(entry.file_offset, lfh_offset + 30 + 15);
+        assert_eq!(entry.file_size, 5);
+        assert_eq!(
+            reader
+                .read(entry.file_offset, entry.file_size)
+                .await
+                .unwrap(),
+            b"hello"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_parse_zip64_disk_start_number() {
+        let lfh_offset = 0x1_0000_0000;
+
+        // Disk start number 0 stored in Zip64 extended info is accepted silently
+        let mut reader = build_sparse_zip64_archive(lfh_offset, b"data", Some(0));
+        let (zip, warnings) = ZipFile::parse_with_warnings(&mut reader, false)
+            .await
+            .unwrap();
+        assert!(warnings.is_empty(), "unexpected warnings: {warnings:?}");
+        assert_eq!(zip.entries[0].cdh.disk_number_start, 0xFFFF);
+        assert_eq!(zip.entries[0].cdh.get_disk_number_start(), 0);
+        assert_eq!(zip.entries[0].file_offset, lfh_offset + 30 + 15);
+
+        // Non-zero disk start number is reported as a warning
+        let mut reader = build_sparse_zip64_archive(lfh_offset, b"data", Some(3));
+        let (zip, warnings) = ZipFile::parse_with_warnings(&mut reader, false)
+            .await
+            .unwrap();
+        assert_eq!(zip.entries[0].cdh.get_disk_number_start(), 3);
+        assert!(matches!(
+            warnings.as_slice(),
+            [(
+                Some(0),
+                ZipParseError::UnexpectedValue {
+                    name: "CDH Disk Number Start",
+                    expected: 0,
+                    found: 3,
+                }
+            )]
+        ));
+    }
+
+    /// How a test entry records its sizes and CRC32
+    #[derive(Clone, Copy)]
+    enum TestDescriptor {
+        None,
+        WithSignature,
+        WithoutSignature,
+    }
+
+    /// Build a stored LFH followed by its data and optional Data Descriptor
+    fn build_local_entry(
+        filename: &[u8],
+        content: &[u8],
+        crc32: u32,
+        descriptor: TestDescriptor,
+    ) -> Vec<u8> {
+        let has_descriptor = !matches!(descriptor, TestDescriptor::None);
+        let (lfh_crc32, lfh_size) = if has_descriptor {
+            (0, 0)
+        } else {
+            (crc32, content.len() as u32)
+        };
+
+        let mut data = Vec::new();
+        data.extend_from_slice(&0x04034b50u32.to_le_bytes()); // Signature
+        data.extend_from_slice(&20u16.to_le_bytes()); // Version needed
+        data.extend_from_slice(&(if has_descriptor { 0x0008u16 } else { 0 }).to_le_bytes()); // Flags
+        data.extend_from_slice(&0u16.to_le_bytes()); // Compression method
+        data.extend_from_slice(&0u16.to_le_bytes()); // Last mod time
+        data.extend_from_slice(&0u16.to_le_bytes()); // Last mod date
+        data.extend_from_slice(&lfh_crc32.to_le_bytes()); // CRC32
+        data.extend_from_slice(&lfh_size.to_le_bytes()); // Compressed size
+        data.extend_from_slice(&lfh_size.to_le_bytes()); // Uncompressed size
+        data.extend_from_slice(&(filename.len() as u16).to_le_bytes()); // Filename length
+        data.extend_from_slice(&0u16.to_le_bytes()); // Extra field length
+        data.extend_from_slice(filename); // Filename
+        data.extend_from_slice(content); // File data
+
+        if matches!(descriptor, TestDescriptor::WithSignature) {
+            data.extend_from_slice(&0x08074b50u32.to_le_bytes()); // Signature
+        }
+        if has_descriptor {
+            data.extend_from_slice(&crc32.to_le_bytes()); // CRC32
+            data.extend_from_slice(&(content.len() as u32).to_le_bytes()); // Compressed size
+            data.extend_from_slice(&(content.len() as u32).to_le_bytes()); // Uncompressed size
+        }
+
+        data
+    }
+
+    /// Build a complete archive of stored entries without Data Descriptors
+    fn build_stored_archive(files: &[(&[u8], &[u8])]) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut cd = Vec::new();
+        for (filename, content) in files {
+            let crc32 = crc_fast::checksum(crc_fast::CrcAlgorithm::Crc32IsoHdlc, content) as u32;
+            let lfh_offset = data.len() as u32;
+            data.extend(build_local_entry(
+                filename,
+                content,
+                crc32,
+                TestDescriptor::None,
+            ));
+
+            cd.extend_from_slice(&0x02014b50u32.to_le_bytes()); // Signature
+            cd.extend_from_slice(&20u16.to_le_bytes()); // Version made by
+            cd.extend_from_slice(&20u16.to_le_bytes()); // Version needed
+            cd.extend_from_slice(&0u16.to_le_bytes()); // Flags
+            cd.extend_from_slice(&0u16.to_le_bytes()); // Compression method
+            cd.extend_from_slice(&0u16.to_le_bytes()); // Last mod time
+            cd.extend_from_slice(&0u16.to_le_bytes()); // Last mod date
+            cd.extend_from_slice(&crc32.to_le_bytes()); // CRC32
+            cd.extend_from_slice(&(content.len() as u32).to_le_bytes()); // Compressed size
+            cd.extend_from_slice(&(content.len() as u32).to_le_bytes()); // Uncompressed size
+            cd.extend_from_slice(&(filename.len() as u16).to_le_bytes()); // Filename length
+            cd.extend_from_slice(&0u16.to_le_bytes()); // Extra field length
+            cd.extend_from_slice(&0u16.to_le_bytes()); // File comment length
+            cd.extend_from_slice(&0u16.to_le_bytes()); // Disk number start
+            cd.extend_from_slice(&0u16.to_le_bytes()); // Internal file attributes
+            cd.extend_from_slice(&0u32.to_le_bytes()); // External file attributes
+            cd.extend_from_slice(&lfh_offset.to_le_bytes()); // Local header offset
+            cd.extend_from_slice(filename); // Filename
+        }
+
+        let cd_offset = data.len() as u32;
+        data.extend_from_slice(&cd);
+
+        data.extend_from_slice(&0x06054b50u32.to_le_bytes()); // Signature
+        data.extend_from_slice(&0u16.to_le_bytes()); // Disk number
+        data.extend_from_slice(&0u16.to_le_bytes()); // Disk number with EOCD
+        data.extend_from_slice(&(files.len() as u16).to_le_bytes()); // Entries on disk
+        data.extend_from_slice(&(files.len() as u16).to_le_bytes()); // Total entries
+        data.extend_from_slice(&(cd.len() as u32).to_le_bytes()); // Central directory size
+        data.extend_from_slice(&cd_offset.to_le_bytes()); // Central directory offset
+        data.extend_from_slice(&0u16.to_le_bytes()); // Comment length
+
+        data
+    }
+
+    #[tokio::test]
+    async fn test_parse_central_directory_in_windows() {
+        let names = (0..5000).map(|i| format!("{i:0>200}")).collect::<Vec<_>>();
+        let files = names
+            .iter()
+            .map(|name| (name.as_bytes(), b"x".as_slice()))
+            .collect::<Vec<_>>();
+        let mut reader = RecordingMockReader {
+            inner: MockReader::new(build_stored_archive(&files)),
+            read_sizes: Vec::new(),
+        };
+
+        let zip = ZipFile::parse(&mut reader, false, |_, e| Err(e))
+            .await
+            .unwrap();
+        assert!(zip.eocd.central_directory_size as u64 > CENTRAL_DIRECTORY_WINDOW_SIZE);
+        assert_eq!(zip.entries.len(), 5000);
+        assert_eq!(zip.entries[4999].cdh.filename, names[4999].as_bytes());
+        assert!(
+            reader
+                .read_sizes
+                .iter()
+                .all(|&size| size <= CENTRAL_DIRECTORY_WINDOW_SIZE)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_parse_prepended_data() {
+        let archive = build_stored_archive(&[(b"a.txt", b"hello"), (b"b.txt", b"world")]);
+        let mut data = b"MZ self-extractor stub".to_vec();
+        let prefix_length = data.len() as u64;
+        data.extend_from_slice(&archive);
+
+        let mut reader = MockReader::new(data);
+        let (zip, warnings) = ZipFile::parse_with_warnings(&mut reader, false)
+            .await
+            .unwrap();
+        assert!(matches!(
+            warnings.as_slice(),
+            [(None, ZipParseError::PrependedData { size: 22 })]
+        ));
+        assert_eq!(zip.prefix_length, prefix_length);
+        assert_eq!(zip.entries.len(), 2);
+        assert_eq!(zip.entries[1].lfh.filename, b"b.txt");
+        assert_eq!(
+            reader
+                .read(zip.entries[1].file_offset, zip.entries[1].file_size)
+                .await
+                .unwrap(),
+            b"world"
+        );
+
+        // Archives without prepended data are not affected
+        let mut reader = MockReader::new(archive);
+        let (zip, warnings) = ZipFile::parse_with_warnings(&mut reader, false)
+            .await
+            .unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(zip.prefix_length, 0);
+        assert_eq!(zip.entries.len(), 2);
+    }
+
+    /// Streaming parser that records callbacks and stops after a number of entries
+    struct RecordingParser {
+        events: Vec<String>,
+        max_entries: usize,
+    }
+
+    impl ZipFileParser for RecordingParser {
+        fn on_eocf(
+            &mut self,
+            eocd: EndOfCentralDirectory,
+            _zip64_eocd: Option<(
+                Zip64EndOfCentralDirectory,
+                Option<Zip64EndOfCentralDirectoryLocator>,
+            )>,
+            prefix_length: u64,
+        ) -> Result<(), ZipParseError> {
+            self.events
+                .push(format!("eocd {} {prefix_length}", eocd.total_entries));
+            Ok(())
+        }
+
+        fn on_entry(&mut self, entry: ZipFileEntry) -> Result<(), ZipParseError> {
+            if self
+                .events
+                .iter()
+                .filter(|e| e.starts_with("entry"))
+                .count()
+                == self.max_entries
+            {
+                return Err(ZipParseError::Other("stop".to_string()));
+            }
+            self.events.push(format!(
+                "entry {}",
+                String::from_utf8_lossy(&entry.cdh.filename)
+            ));
+            Ok(())
+        }
+
+        fn on_warning(
+            &mut self,
+            idx: Option<u64>,
+            warning: ZipParseError,
+        ) -> Result<(), ZipParseError> {
+            self.events.push(format!("warning {idx:?} {warning}"));
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_parse_with() {
+        let mut data = b"stub".to_vec();
+        data.extend(build_stored_archive(&[
+            (b"a.txt", b"hello"),
+            (b"b.txt", b"world"),
+        ]));
+
+        let mut parser = RecordingParser {
+            events: Vec::new(),
+            max_entries: usize::MAX,
+        };
+        parse_with(&mut MockReader::new(data.clone()), &mut parser)
+            .await
+            .unwrap();
+        assert_eq!(
+            parser.events,
+            [
+                "warning None Found 4 bytes of data prepended to the archive, offsets are adjusted accordingly",
+                "eocd 2 4",
+                "entry a.txt",
+                "entry b.txt",
+            ]
+        );
+
+        // Errors from callbacks abort the parse
+        let mut parser = RecordingParser {
+            events: Vec::new(),
+            max_entries: 1,
+        };
+        let result = parse_with(&mut MockReader::new(data), &mut parser).await;
+        assert!(matches!(result, Err(ZipParseError::Other(message)) if message == "stop"));
+        assert_eq!(parser.events.last().unwrap(), "entry a.txt");
+    }
+
+    #[tokio::test]
+    async fn test_parse_multi_volume() {
+        // Split a two-entry archive so that the second entry and the central directory are on disk 1
+        let archive = build_stored_archive(&[(b"a.txt", b"hello"), (b"b.txt", b"world")]);
+        let mut first = archive.clone();
+        let mut last = first.split_off(40);
+
+        let cd_offset = 40usize;
+        let second_cdh = cd_offset + 51;
+        last[second_cdh + 34..second_cdh + 36].copy_from_slice(&1u16.to_le_bytes()); // Disk number start
+        last[second_cdh + 42..second_cdh + 46].copy_from_slice(&0u32.to_le_bytes()); // Local header offset
+        let eocd = last.len() - 22;
+        last[eocd + 4..eocd + 6].copy_from_slice(&1u16.to_le_bytes()); // Disk number
+        last[eocd + 6..eocd + 8].copy_from_slice(&1u16.to_le_bytes()); // Disk number with CD
+        last[eocd + 8..eocd + 10].copy_from_slice(&2u16.to_le_bytes()); // Entries on disk
+        last[eocd + 16..eocd + 20].copy_from_slice(&(cd_offset as u32).to_le_bytes()); // CD offset
+
+        let mut reader =
+            MultiVolumeReader::new(vec![MockReader::new(first), MockReader::new(last.clone())])
+                .await
+                .unwrap();
+        assert_eq!(reader.volume_count(), 2);
+        assert_eq!(reader.get_disk_offset(1), Some(40));
+        assert_eq!(reader.get_disk_offset(2), None);
+
+        let (zip, warnings) = ZipFile::parse_with_warnings(&mut reader, false)
+            .await
+            .unwrap();
+        assert!(warnings.is_empty(), "unexpected warnings: {warnings:?}");
+        assert_eq!(zip.entries.len(), 2);
+        assert_eq!(zip.entries[1].lfh.filename, b"b.txt");
+        assert_eq!(zip.entries[1].file_offset, 40 + 30 + 5);
+
+        // Reads spanning the volume boundary are concatenated
+        assert_eq!(
+            reader.read(35, 10).await.unwrap(),
+            b"hello\x50\x4b\x03\x04\x14"
+        );
+        assert_eq!(
+            reader
+                .read(zip.entries[1].file_offset, zip.entries[1].file_size)
+                .await
+                .unwrap(),
+            b"world"
+        );
+
+        // The last volume alone is rejected
+        let mut reader = MockReader::new(last);
+        assert!(matches!(
+            ZipFile::parse_with_warnings(&mut reader, false).await,
+            Err(ZipParseError::MultipleDiskNotSupported)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_parse_inconsistent_entries_on_disk() {
+        // A single disk claiming to hold only one of the two entries
+        let mut archive = build_stored_archive(&[(b"a.txt", b"hello"), (b"b.txt", b"world")]);
+        let eocd = archive.len() - 22;
+        archive[eocd + 8..eocd + 10].copy_from_slice(&1u16.to_le_bytes()); // Entries on disk
+
+        let mut reader = MockReader::new(archive);
+        let (zip, warnings) = ZipFile::parse_with_warnings(&mut reader, false)
+            .await
+            .unwrap();
+        assert_eq!(zip.entries.len(), 2);
+        assert!(matches!(
+            warnings.as_slice(),
+            [(
+                None,
+                ZipParseError::InconsistentValue {
+                    name: "Entries on Disk",
+                    expected: 2,
+                    found: 1,
+                }
+            )]
+        ));
+
+        assert!(matches!(
+            ZipFile::parse(&mut reader, false, |_, warning| Err(warning)).await,
+            Err(ZipParseError::InconsistentValue { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_recover_without_central_directory() {
+        let mut data = b"MZ-stub".to_vec();
+        data.extend(build_local_entry(
+            b"a.txt",
+            b"hello",
+            0x3610a686,
+            TestDescriptor::None,
+        ));
+        // The content contains a stray LFH signature which must not split the entry
+        data.extend(build_local_entry(
+            b"dir/b.txt",
+            b"PK\x03\x04 inside",
+            0x11111111,
+            TestDescriptor::WithSignature,
+        ));
+        data.extend(build_local_entry(b"dir/", b"", 0, TestDescriptor::None));
+        data.extend(build_local_entry(
+            b"c.txt",
+            b"no signature",
+            0x22222222,
+            TestDescriptor::WithoutSignature,
+        ));
+        // Truncated central directory
+        data.extend_from_slice(&0x02014b50u32.to_le_bytes());
+        data.extend_from_slice(&[0u8; 10]);
+
+        let mut reader = MockReader::new(data.clone());
+        assert!(
+            ZipFile::parse(&mut reader, false, |_, _| Ok(()))
+                .await
+                .is_err()
+        );
+
+        let (zip, warnings) = ZipFile::recover_with_warnings(&mut reader).await.unwrap();
+        assert!(matches!(
+            warnings.as_slice(),
+            [(None, ZipParseError::PrependedData { size: 7 })]
+        ));
+        assert_eq!(zip.prefix_length, 7);
+        assert_eq!(zip.entries.len(), 4);
+        assert_eq!(zip.eocd.total_entries, 4);
+
+        let a = &zip.entries[0];
+        assert_eq!(a.cdh.filename, b"a.txt");
+        assert_eq!(a.cdh.get_local_header_offset(), 0);
+        assert_eq!(a.cdh.crc32, 0x3610a686);
+        assert!(a.descriptor.is_none());
+        assert_eq!(
+            &data[a.file_offset as usize..(a.file_offset + a.file_size) as usize],
+            b"hello"
+        );
+
+        let b = &zip.entries[1];
+        assert_eq!(b.cdh.filename, b"dir/b.txt");
+        assert_eq!(b.cdh.crc32, 0x11111111);
+        assert_eq!(b.cdh.compressed_size, 11);
+        assert_eq!(b.cdh.uncompressed_size, 11);
+        assert_eq!(b.descriptor.unwrap().get_signature(), Some(0x08074b50));
+        assert_eq!(
+            &data[b.file_offset as usize..(b.file_offset + b.file_size) as usize],
+            b"PK\x03\x04 inside"
+        );
+
+        let dir = &zip.entries[2];
+        assert_eq!(dir.cdh.filename, b"dir/");
+        assert_eq!(dir.cdh.external_file_attributes, 0x10);
+        assert_eq!(dir.file_size, 0);
+
+        let c = &zip.entries[3];
+        assert_eq!(c.cdh.filename, b"c.txt");
+        assert_eq!(c.cdh.crc32, 0x22222222);
+        assert_eq!(c.descriptor.unwrap().get_signature(), None);
+        assert_eq!(
+            &data[c.file_offset as usize..(c.file_offset + c.file_size) as usize],
+            b"no signature"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_recover_truncated_entry() {
+        let mut data = build_local_entry(b"a.txt", b"hello", 0x3610a686, TestDescriptor::None);
+        let truncated = build_local_entry(
+            b"b.txt",
+            b"this entry is cut off",
+            0x33333333,
+            TestDescriptor::WithSignature,
+        );
+        data.extend_from_slice(&truncated[..truncated.len() - 20]);
+
+        let mut reader = MockReader::new(data);
+        let (zip, warnings) = ZipFile::recover_with_warnings(&mut reader).await.unwrap();
+        assert_eq!(zip.entries.len(), 1);
+        assert_eq!(zip.entries[0].cdh.filename, b"a.txt");
+        assert!(matches!(
+            warnings.first(),
+            Some((Some(1), ZipParseError::UnboundedEntry { offset: 40 }))
+        ));
+    }
+
+    #[test]
+    fn test_synthesize_cdh_extra_fields_too_long() {
+        // LFH extra fields already taking the full 65535 bytes
+        let mut lfh_data = vec![0u8; 30];
+        lfh_data[0..4].copy_from_slice(&0x04034b50u32.to_le_bytes());
+        lfh_data[26..28].copy_from_slice(&5u16.to_le_bytes());
+        lfh_data[28..30].copy_from_slice(&0xFFFFu16.to_le_bytes());
+        lfh_data.extend_from_slice(b"a.txt");
+        lfh_data.extend_from_slice(&0xCAFEu16.to_le_bytes());
+        lfh_data.extend_from_slice(&65515u16.to_le_bytes());
+        lfh_data.extend_from_slice(&[0; 65515]);
+        lfh_data.extend_from_slice(&0xBEEFu16.to_le_bytes());
+        lfh_data.extend_from_slice(&12u16.to_le_bytes());
+        lfh_data.extend_from_slice(&[0; 12]);
+        let lfh = LocalFileHeader::parse(&lfh_data, Err).unwrap();
+
+        // An offset beyond 4 GiB needs a Zip64 field in front of them
+        let cdh = CentralDirectoryHeader::synthesize(&lfh, None, 0x1_0000_0000, 0);
+        let tags = cdh.extra_fields.iter().map(|ef| ef.tag).collect::<Vec<_>>();
+        assert_eq!(tags, [Zip64ExtendedInfo::TAG, 0xCAFE]);
+        assert_eq!(cdh.extra_field_length, 12 + 65519);
+        assert_eq!(cdh.get_local_header_offset(), 0x1_0000_0000);
+    }
+
     #[test]
     fn test_parse_zip64_eocd_locator() {
         let mut data = vec![0u8; 20];
@@ -1,41 +1,371 @@
 use crate::zip::parse::{Zip64EndOfCentralDirectoryHeader, ZipFileEntry};
 
-use super::inspect::{InspectConfig, InspectedArchive, ZipInspectError};
+use super::inspect::{EncodingOrAscii, InspectConfig, InspectedArchive, ZipInspectError};
 use super::parse::{
     CentralDirectoryHeader, EndOfCentralDirectory, ExtraField, LocalFileHeader,
-    UnicodePathExtraField, Zip64EndOfCentralDirectoryLocator, Zip64ExtendedInfo, ZipFile,
+    UnicodeCommentExtraField, UnicodePathExtraField, Zip64EndOfCentralDirectoryLocator,
+    Zip64ExtendedInfo, ZipFile, ZipReader,
 };
-use std::collections::HashSet;
-use std::io::Write;
+use super::rename::{RenameConfig, RenameError};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use thiserror::Error;
 
 /// A chunk of data in the rebuilt zip file
 #[derive(Debug, Clone)]
 pub enum RebuildChunk {
-    Reference { offset: u64, size: u64 },
+    Reference {
+        offset: u64,
+        size: u64,
+    },
+    /// Data of an entry, referenced like [`RebuildChunk::Reference`]
+    EntryData {
+        offset: u64,
+        size: u64,
+    },
     Binary(Vec<u8>),
 }
 
+/// Configuration for rebuilding ZIP archives
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    derive(tsify::Tsify)
+)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+pub struct RebuildConfig {
+    /// How to handle data prepended to the archive, such as a self-extractor stub
+    #[serde(default)]
+    pub prefix_handling: PrefixHandling,
+    /// How to write the archive comment stored in the EOCD
+    #[serde(default)]
+    pub archive_comment: ArchiveCommentPolicy,
+    /// Whether to write repaired names for entries detected as double-encoded UTF-8
+    #[serde(default)]
+    pub repair_double_encoding: bool,
+    /// How to encode the names and comments of entries
+    #[serde(default)]
+    pub filename_mode: FilenameOutputMode,
+    /// Renaming applied to the decoded names before they are encoded
+    ///
+    /// Entries renamed to an empty name are left out, and the final names must be unique.
+    #[serde(default)]
+    pub rename: RenameConfig,
+}
+
+/// Encoding of entry names and comments in the rebuilt archive
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    derive(tsify::Tsify)
+)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+pub enum FilenameOutputMode {
+    /// Write UTF-8 with the UTF-8 flag set
+    #[default]
+    Utf8,
+    /// Write names and comments in a legacy encoding without the UTF-8 flag, adding a Unicode Path
+    /// extra field (0x7075) with the UTF-8 name
+    ///
+    /// For tools that ignore the UTF-8 flag, such as Windows Explorer before Windows 8. Entries that cannot
+    /// be represented in the encoding are written as UTF-8 and reported as [`RebuildWarning::NotRepresentable`].
+    LegacyWithUnicodePath {
+        /// Label of the legacy encoding, such as `Shift_JIS` or `IBM437`
+        encoding: String,
+    },
+    /// Write names and comments only in a legacy encoding without the UTF-8 flag
+    ///
+    /// For software that only reads names in a specific code page. Entries with characters that cannot be
+    /// mapped are handled according to `unmappable` and reported as [`RebuildWarning::LossyConversion`].
+    Legacy {
+        /// Label of the legacy encoding, such as `Shift_JIS` or `GBK`
+        encoding: String,
+        #[serde(default)]
+        unmappable: UnmappablePolicy,
+    },
+}
+
+/// How to handle characters that cannot be mapped to the legacy encoding
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    derive(tsify::Tsify)
+)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+pub enum UnmappablePolicy {
+    /// Fail the rebuild with [`RebuildError::NotRepresentable`]
+    #[default]
+    Fail,
+    /// Replace each unmappable character with the given string, which must be representable itself
+    Replace { replacement: String },
+    /// Replace each unmappable character with an HTML numeric character reference such as `&#54620;`
+    NumericCharacterReference,
+}
+
+/// Report of a rebuild, mapping every entry of the source archive to the rebuilt archive
+///
+/// Serialized as JSON, it serves as a manifest to undo the renaming with [`restore`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    derive(tsify::Tsify)
+)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+pub struct RebuildReport {
+    /// Every entry of the source archive, including omitted ones
+    pub entries: Vec<RebuildReportEntry>,
+    /// Archive comment bytes in the EOCD of the source archive
+    pub original_archive_comment: Vec<u8>,
+    pub warnings: Vec<RebuildWarning>,
+}
+
+/// What happened to an entry of the source archive
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    derive(tsify::Tsify)
+)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+pub struct RebuildReportEntry {
+    /// Index in the source archive
+    pub index: u64,
+    /// Filename bytes in the central directory of the source archive
+    pub original_filename: Vec<u8>,
+    pub original_utf8_flag: bool,
+    /// Filename bytes in the local file header of the source archive, which may differ from the central
+    /// directory
+    pub original_lfh_filename: Vec<u8>,
+    pub original_lfh_utf8_flag: bool,
+    /// Comment bytes in the central directory of the source archive
+    pub original_comment: Vec<u8>,
+    /// Unicode Path and Unicode Comment extra fields in the central directory of the source archive, which
+    /// the rebuild removes
+    #[serde(default)]
+    pub original_unicode_extra_fields: Vec<RecordedExtraField>,
+    /// Unicode Path and Unicode Comment extra fields in the local file header of the source archive
+    #[serde(default)]
+    pub original_lfh_unicode_extra_fields: Vec<RecordedExtraField>,
+    /// Encoding the name was decoded with, None if it could not be decoded
+    pub source_encoding: Option<String>,
+    /// The name written, None if the entry was omitted or its name could not be decoded
+    pub new_name: Option<String>,
+    /// Filename bytes written, None if the entry was omitted
+    pub new_filename: Option<Vec<u8>>,
+    /// Offset of the local file header in the source archive
+    pub old_offset: u64,
+    /// Offset of the local file header in the rebuilt archive, None if the entry was omitted
+    pub new_offset: Option<u64>,
+}
+
+/// Extra field of the source archive recorded in a [`RebuildReport`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    derive(tsify::Tsify)
+)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+pub struct RecordedExtraField {
+    pub tag: u16,
+    pub data: Vec<u8>,
+}
+
+impl RecordedExtraField {
+    /// Records the Unicode Path and Unicode Comment extra fields among the given ones
+    fn record_unicode(extra_fields: &[ExtraField]) -> Vec<Self> {
+        extra_fields
+            .iter()
+            .filter(|ef| is_unicode_extra_field(ef.tag))
+            .map(|ef| RecordedExtraField {
+                tag: ef.tag,
+                data: ef.data.clone(),
+            })
+            .collect()
+    }
+
+    /// Turns the recorded field back into an extra field of the given entry
+    fn to_extra_field(&self, index: u64) -> Result<ExtraField, RebuildError> {
+        let size =
+            u16::try_from(self.data.len()).map_err(|_| RebuildError::ExtraFieldsTooLong {
+                index,
+                length: 4 + self.data.len(),
+            })?;
+        Ok(ExtraField {
+            tag: self.tag,
+            size,
+            data: self.data.clone(),
+        })
+    }
+}
+
+/// Non-fatal issues found while rebuilding
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    derive(tsify::Tsify)
+)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+pub enum RebuildWarning {
+    /// The name or comment of the entry cannot be represented in the legacy encoding, so the entry was
+    /// written as UTF-8
+    NotRepresentable {
+        index: u64,
+        name: String,
+        encoding: String,
+    },
+    /// Characters of the name or comment of the entry could not be mapped to the legacy encoding and were
+    /// replaced
+    LossyConversion {
+        index: u64,
+        name: String,
+        /// The name as written, after replacement
+        written_name: String,
+        /// Whether the comment lost characters as well
+        comment_lossy: bool,
+        /// The unmappable characters, in order of appearance
+        unmappable: Vec<char>,
+    },
+    /// Renaming left the name of the entry empty, so the entry was left out
+    RenamedToEmpty { index: u64, name: String },
+    /// The comment of the entry could not be decoded, so its invalid bytes were replaced with U+FFFD to
+    /// write it as UTF-8
+    UndecodableComment { index: u64, name: String },
+}
+
+/// Strategy for handling data prepended to the archive
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    derive(tsify::Tsify)
+)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+pub enum PrefixHandling {
+    /// Drop the prepended data and write a plain zip file
+    #[default]
+    Drop,
+    /// Keep the prepended data in front of the rebuilt archive, with offsets adjusted to include it
+    Keep,
+}
+
+/// Policy for writing the archive comment
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    derive(tsify::Tsify)
+)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+pub enum ArchiveCommentPolicy {
+    /// Keep the original bytes as is
+    #[default]
+    Keep,
+    /// Write the decoded comment as UTF-8, keeping the original bytes if decoding failed
+    TranscodeUtf8,
+    /// Replace the comment with the given one
+    Replace { comment: String },
+    /// Remove the comment
+    Strip,
+}
+
 /// Rebuilds a zip file with UTF-8 filenames according to the provided configuration,
 /// omitting entries specified by their indices.
 pub fn rebuild(
     zip_file: &ZipFile,
     config: &InspectConfig,
+    rebuild_config: &RebuildConfig,
     omit_entries: &[u64],
 ) -> Result<(Vec<RebuildChunk>, u64), RebuildError> {
-    struct CentralDirectoryEntryInfo<'a> {
-        lfh_offset: u64,
-        filename: Vec<u8>,
-        entry: &'a ZipFileEntry,
-        uncompressed_size: u64,
-        compressed_size: u64,
-        crc32: u32,
+    rebuild_with_report(zip_file, config, rebuild_config, omit_entries)
+        .map(|(chunks, size, _)| (chunks, size))
+}
+
+/// Rebuilds a zip file like [`rebuild`], also returning a report of what happened to every entry
+pub fn rebuild_with_report(
+    zip_file: &ZipFile,
+    config: &InspectConfig,
+    rebuild_config: &RebuildConfig,
+    omit_entries: &[u64],
+) -> Result<(Vec<RebuildChunk>, u64, RebuildReport), RebuildError> {
+    let legacy_encoding = match &rebuild_config.filename_mode {
+        FilenameOutputMode::Utf8 => None,
+        FilenameOutputMode::LegacyWithUnicodePath { encoding }
+        | FilenameOutputMode::Legacy { encoding, .. } => {
+            let legacy_encoding = EncodingOrAscii::for_label(encoding)
+                .ok_or_else(|| ZipInspectError::EncodingNotFound(encoding.clone()))?;
+            // Paths are separated by ASCII slashes, and encoding_rs writes some encodings as UTF-8
+            if let EncodingOrAscii::Encoding(enc) = legacy_encoding
+                && (!enc.is_ascii_compatible() || enc.output_encoding() != enc)
+            {
+                return Err(RebuildError::UnsupportedLegacyEncoding(
+                    enc.name().to_string(),
+                ));
+            }
+            Some(legacy_encoding)
+        }
+    };
+    if let (
+        Some(encoding),
+        FilenameOutputMode::Legacy {
+            unmappable: UnmappablePolicy::Replace { replacement },
+            ..
+        },
+    ) = (legacy_encoding, &rebuild_config.filename_mode)
+        && encoding.encode(replacement).is_none()
+    {
+        return Err(RebuildError::ReplacementNotRepresentable {
+            replacement: replacement.clone(),
+            encoding: encoding.name().to_string(),
+        });
     }
+    let mut not_representable = Vec::new();
 
     let inspected = InspectedArchive::inspect(zip_file, config)?;
-    let mut chunks = Vec::new();
-    let mut current_offset = 0u64;
-    let mut new_cd_entries = Vec::new();
+    let mut warnings = Vec::new();
+    let mut headers = Vec::new();
+    // Index, name and filename bytes of the entries written, in the order of `headers`
+    let mut written = Vec::new();
+
+    if let Some(entry_override) = rebuild_config
+        .rename
+        .overrides
+        .iter()
+        .find(|o| o.index >= zip_file.entries.len() as u64)
+    {
+        return Err(ZipInspectError::EntryIndexOutOfRange(entry_override.index).into());
+    }
+    let renamer = rebuild_config.rename.compile()?;
 
     let omit_set: HashSet<u64> = omit_entries.iter().copied().collect();
 
@@ -49,19 +379,384 @@ pub fn rebuild(
             continue;
         }
 
-        let filename = inspected_entry
+        let repair = inspected_entry
             .filename
-            .decoded
+            .double_encoding_repair
             .as_ref()
-            .map(|d| d.string.as_bytes().to_vec())
-            .unwrap_or_else(|| {
-                inspected_entry
-                    .filename
-                    .original_bytes
+            .filter(|_| rebuild_config.repair_double_encoding);
+        let name = repair.map(|r| r.repaired.as_str()).or_else(|| {
+            inspected_entry
+                .filename
+                .decoded
+                .as_ref()
+                .map(|d| d.string.as_str())
+        });
+        let renamed = renamer.apply(index as u64, name);
+        if renamed.as_deref() == Some("") {
+            warnings.push(RebuildWarning::RenamedToEmpty {
+                index: index as u64,
+                name: name
+                    .map(str::to_string)
+                    .unwrap_or_else(|| String::from_utf8_lossy(&entry.cdh.filename).into_owned()),
+            });
+            continue;
+        }
+        let name = renamed.as_deref().or(name);
+        let mut filename = name.map(|n| n.as_bytes().to_vec()).unwrap_or_else(|| {
+            inspected_entry
+                .filename
+                .original_bytes
+                .clone()
+                .unwrap_or_default()
+        });
+
+        // The UTF-8 flag also applies to the comment, so it must be written as UTF-8 as well
+        let decoded_comment = inspected_entry
+            .comment
+            .as_ref()
+            .and_then(|c| c.decoded.as_ref())
+            .map(|d| d.string.as_str());
+        let mut comment = match &inspected_entry.comment {
+            Some(_) => decoded_comment
+                .map(|c| c.as_bytes().to_vec())
+                .unwrap_or_else(|| entry.cdh.file_comment.clone()),
+            None => Vec::new(),
+        };
+
+        let mut utf8_flag = true;
+        let mut unicode_path = None;
+        let mut new_name = name.map(str::to_string);
+        match (&rebuild_config.filename_mode, legacy_encoding, name) {
+            (FilenameOutputMode::LegacyWithUnicodePath { .. }, Some(encoding), Some(name)) => {
+                let legacy_comment = match decoded_comment {
+                    Some(c) => encoding.encode(c),
+                    None => Some(comment.clone()),
+                };
+                match (encoding.encode(name), legacy_comment) {
+                    (Some(legacy_name), Some(legacy_comment)) => {
+                        // ASCII names read the same everywhere and need no Unicode Path
+                        if !name.is_ascii() {
+                            unicode_path =
+                                Some(unicode_path_field(index as u64, &legacy_name, name)?);
+                        }
+                        filename = legacy_name;
+                        comment = legacy_comment;
+                        utf8_flag = false;
+                    }
+                    _ => warnings.push(RebuildWarning::NotRepresentable {
+                        index: index as u64,
+                        name: name.to_string(),
+                        encoding: encoding.name().to_string(),
+                    }),
+                }
+            }
+            (
+                FilenameOutputMode::Legacy {
+                    unmappable: policy, ..
+                },
+                Some(encoding),
+                name,
+            ) => {
+                utf8_flag = false;
+                // Names that could not be decoded are written as they were
+                let name_result = name.map(|n| encode_legacy(encoding, n, policy));
+                let comment_result = decoded_comment.map(|c| encode_legacy(encoding, c, policy));
+                let unmappable = name_result
+                    .iter()
+                    .chain(&comment_result)
+                    .flat_map(|r| r.unmappable.iter().copied())
+                    .collect::<Vec<_>>();
+                let encoded = name_result
+                    .iter()
+                    .chain(&comment_result)
+                    .all(|r| r.bytes.is_some());
+
+                if !encoded || (!unmappable.is_empty() && *policy == UnmappablePolicy::Fail) {
+                    not_representable.push(index as u64);
+                } else {
+                    let original_name = name
+                        .map(str::to_string)
+                        .unwrap_or_else(|| String::from_utf8_lossy(&filename).into_owned());
+                    let comment_lossy = comment_result
+                        .as_ref()
+                        .is_some_and(|r| !r.unmappable.is_empty());
+                    let written_name = match name_result {
+                        Some(result) => {
+                            filename = result.bytes.unwrap_or_default();
+                            result.replaced
+                        }
+                        None => original_name.clone(),
+                    };
+                    if name.is_some() {
+                        new_name = Some(written_name.clone());
+                    }
+                    if !unmappable.is_empty() {
+                        warnings.push(RebuildWarning::LossyConversion {
+                            index: index as u64,
+                            name: original_name,
+                            written_name,
+                            comment_lossy,
+                            unmappable,
+                        });
+                    }
+                    if let Some(result) = comment_result {
+                        comment = result.bytes.unwrap_or_default();
+                    }
+                }
+            }
+            _ => {}
+        }
+        // Without a decoded comment, the original bytes would be misread under the UTF-8 flag
+        if utf8_flag && decoded_comment.is_none() && str::from_utf8(&comment).is_err() {
+            comment = String::from_utf8_lossy(&comment).into_owned().into_bytes();
+            warnings.push(RebuildWarning::UndecodableComment {
+                index: index as u64,
+                name: new_name
                     .clone()
-                    .unwrap_or_default()
+                    .unwrap_or_else(|| String::from_utf8_lossy(&filename).into_owned()),
+            });
+        }
+        if comment.len() > 0xFFFF {
+            return Err(RebuildError::CommentTooLong {
+                index: index as u64,
+                length: comment.len(),
             });
+        }
+
+        written.push((index, new_name, filename.clone()));
+        headers.push(EntryHeaders {
+            index: index as u64,
+            entry,
+            lfh_filename: filename.clone(),
+            lfh_utf8_flag: utf8_flag,
+            filename,
+            comment,
+            utf8_flag,
+            lfh_unicode_extra_fields: unicode_path.iter().cloned().collect(),
+            unicode_extra_fields: unicode_path.into_iter().collect(),
+        });
+    }
 
+    if let Some(encoding) = legacy_encoding
+        && !not_representable.is_empty()
+    {
+        return Err(RebuildError::NotRepresentable {
+            encoding: encoding.name().to_string(),
+            indices: not_representable,
+        });
+    }
+
+    // Archives may already contain duplicates, so names only collide if their original bytes differ
+    let mut first_entry_by_filename = HashMap::new();
+    for (index, _, filename) in &written {
+        let original_filename = &zip_file.entries[*index].cdh.filename;
+        match first_entry_by_filename.get(filename) {
+            Some(&(first_index, first_original_filename)) => {
+                if first_original_filename != original_filename {
+                    return Err(RebuildError::DuplicateName {
+                        name: String::from_utf8_lossy(filename).into_owned(),
+                        indices: vec![first_index, *index as u64],
+                    });
+                }
+            }
+            None => {
+                first_entry_by_filename.insert(filename, (*index as u64, original_filename));
+            }
+        }
+    }
+
+    let archive_comment = match &rebuild_config.archive_comment {
+        ArchiveCommentPolicy::Keep => zip_file.eocd.comment.clone(),
+        ArchiveCommentPolicy::TranscodeUtf8 => inspected
+            .comment
+            .as_ref()
+            .and_then(|c| c.decoded.as_ref())
+            .map(|d| d.string.as_bytes().to_vec())
+            .unwrap_or_else(|| zip_file.eocd.comment.clone()),
+        ArchiveCommentPolicy::Replace { comment } => comment.as_bytes().to_vec(),
+        ArchiveCommentPolicy::Strip => Vec::new(),
+    };
+    let (chunks, size, lfh_offsets) = write_archive(
+        zip_file,
+        rebuild_config.prefix_handling,
+        archive_comment,
+        headers,
+    )?;
+
+    let mut written = written.into_iter().zip(lfh_offsets).peekable();
+    let entries = zip_file
+        .entries
+        .iter()
+        .zip(inspected.entries.iter())
+        .enumerate()
+        .map(|(index, (entry, inspected_entry))| {
+            let (new_name, new_filename, new_offset) =
+                match written.next_if(|((written_index, ..), _)| *written_index == index) {
+                    Some(((_, new_name, new_filename), new_offset)) => {
+                        (new_name, Some(new_filename), Some(new_offset))
+                    }
+                    None => (None, None, None),
+                };
+            RebuildReportEntry {
+                index: index as u64,
+                original_filename: entry.cdh.filename.clone(),
+                original_utf8_flag: entry.cdh.flags.is_utf8(),
+                original_lfh_filename: entry.lfh.filename.clone(),
+                original_lfh_utf8_flag: entry.lfh.flags.is_utf8(),
+                original_comment: entry.cdh.file_comment.clone(),
+                original_unicode_extra_fields: RecordedExtraField::record_unicode(
+                    &entry.cdh.extra_fields,
+                ),
+                original_lfh_unicode_extra_fields: RecordedExtraField::record_unicode(
+                    &entry.lfh.extra_fields,
+                ),
+                source_encoding: inspected_entry
+                    .filename
+                    .decoded
+                    .as_ref()
+                    .map(|d| d.encoding_used.clone()),
+                new_name,
+                new_filename,
+                old_offset: local_header_offset(entry),
+                new_offset,
+            }
+        })
+        .collect();
+
+    Ok((
+        chunks,
+        size,
+        RebuildReport {
+            entries,
+            original_archive_comment: zip_file.eocd.comment.clone(),
+            warnings,
+        },
+    ))
+}
+
+/// Rebuilds an archive written by [`rebuild`] with the original names from the report of that rebuild
+///
+/// Filenames, comments, UTF-8 flags and Unicode Path and Unicode Comment extra fields of both headers and the
+/// archive comment are restored byte-for-byte, while the other changes of the rebuild, such as cleared data
+/// descriptor flags, are kept. Entries are matched by the offset of their local file header, so the archive
+/// must not have been modified since the rebuild.
+pub fn restore(
+    zip_file: &ZipFile,
+    report: &RebuildReport,
+) -> Result<(Vec<RebuildChunk>, u64), RebuildError> {
+    let by_offset = report
+        .entries
+        .iter()
+        .filter_map(|e| e.new_offset.map(|offset| (offset, e)))
+        .collect::<HashMap<_, _>>();
+
+    let headers = zip_file
+        .entries
+        .iter()
+        .map(|entry| {
+            let offset = local_header_offset(entry);
+            let original = by_offset
+                .get(&offset)
+                .filter(|e| e.new_filename.as_deref() == Some(&entry.cdh.filename[..]))
+                .ok_or(RebuildError::ManifestMismatch { offset })?;
+            Ok(EntryHeaders {
+                index: original.index,
+                entry,
+                lfh_filename: original.original_lfh_filename.clone(),
+                lfh_utf8_flag: original.original_lfh_utf8_flag,
+                filename: original.original_filename.clone(),
+                comment: original.original_comment.clone(),
+                utf8_flag: original.original_utf8_flag,
+                lfh_unicode_extra_fields: original
+                    .original_lfh_unicode_extra_fields
+                    .iter()
+                    .map(|ef| ef.to_extra_field(original.index))
+                    .collect::<Result<_, _>>()?,
+                unicode_extra_fields: original
+                    .original_unicode_extra_fields
+                    .iter()
+                    .map(|ef| ef.to_extra_field(original.index))
+                    .collect::<Result<_, _>>()?,
+            })
+        })
+        .collect::<Result<Vec<_>, RebuildError>>()?;
+
+    let (chunks, size, _) = write_archive(
+        zip_file,
+        PrefixHandling::Keep,
+        report.original_archive_comment.clone(),
+        headers,
+    )?;
+    Ok((chunks, size))
+}
+
+/// Offset of the local file header of the entry within the data source
+fn local_header_offset(entry: &ZipFileEntry) -> u64 {
+    entry.file_offset.saturating_sub(entry.lfh.len() as u64)
+}
+
+/// Names and comment to write for an entry
+struct EntryHeaders<'a> {
+    /// Index of the entry in the source archive
+    index: u64,
+    entry: &'a ZipFileEntry,
+    lfh_filename: Vec<u8>,
+    lfh_utf8_flag: bool,
+    filename: Vec<u8>,
+    comment: Vec<u8>,
+    utf8_flag: bool,
+    /// Unicode Path and Unicode Comment extra fields to write in place of the original ones
+    lfh_unicode_extra_fields: Vec<ExtraField>,
+    unicode_extra_fields: Vec<ExtraField>,
+}
+
+/// Writes the entries with the given headers, followed by the central directory and the EOCD
+///
+/// Returns the chunks, the total size, and the offset of the local file header of each entry.
+fn write_archive(
+    zip_file: &ZipFile,
+    prefix_handling: PrefixHandling,
+    archive_comment: Vec<u8>,
+    headers: Vec<EntryHeaders>,
+) -> Result<(Vec<RebuildChunk>, u64, Vec<u64>), RebuildError> {
+    struct CentralDirectoryEntryInfo<'a> {
+        index: u64,
+        lfh_offset: u64,
+        filename: Vec<u8>,
+        comment: Vec<u8>,
+        utf8_flag: bool,
+        unicode_extra_fields: Vec<ExtraField>,
+        entry: &'a ZipFileEntry,
+        uncompressed_size: u64,
+        compressed_size: u64,
+        crc32: u32,
+    }
+
+    let mut chunks = Vec::new();
+    let mut current_offset = 0u64;
+    let mut new_cd_entries = Vec::new();
+    let mut lfh_offsets = Vec::new();
+
+    if prefix_handling == PrefixHandling::Keep && zip_file.prefix_length != 0 {
+        chunks.push(RebuildChunk::Reference {
+            offset: 0,
+            size: zip_file.prefix_length,
+        });
+        current_offset += zip_file.prefix_length;
+    }
+
+    for EntryHeaders {
+        index,
+        entry,
+        lfh_filename,
+        lfh_utf8_flag,
+        filename,
+        comment,
+        utf8_flag,
+        lfh_unicode_extra_fields,
+        unicode_extra_fields,
+    } in headers
+    {
         let uncompressed_size = entry
             .cdh
             .zip64
@@ -84,8 +779,9 @@ pub fn rebuild(
             .lfh
             .extra_fields
             .iter()
-            .filter(|ef| ef.tag != Zip64ExtendedInfo::TAG && ef.tag != UnicodePathExtraField::TAG)
+            .filter(|ef| ef.tag != Zip64ExtendedInfo::TAG && !is_unicode_extra_field(ef.tag))
             .cloned()
+            .chain(lfh_unicode_extra_fields)
             .collect::<Vec<_>>();
 
         let mut version_needed = version_needed;
@@ -114,7 +810,11 @@ pub fn rebuild(
         }
 
         let mut flags = entry.lfh.flags;
-        flags.0 |= 0x0800; // Set UTF-8 flag
+        if lfh_utf8_flag {
+            flags.0 |= 0x0800; // Set UTF-8 flag
+        } else {
+            flags.0 &= !0x0800;
+        }
         flags.0 &= !0x0008; // Clear data descriptor flag
 
         let lfh = LocalFileHeader {
@@ -127,9 +827,9 @@ pub fn rebuild(
             crc32,
             compressed_size: lfh_compressed_size,
             uncompressed_size: lfh_uncompressed_size,
-            filename_length: filename.len() as u16,
-            extra_field_length: lfh_extra_fields.iter().map(|ef| 4 + ef.size).sum(),
-            filename: filename.clone(),
+            filename_length: filename_length(index, &lfh_filename)?,
+            extra_field_length: extra_field_length(index, &lfh_extra_fields)?,
+            filename: lfh_filename,
             extra_fields: lfh_extra_fields,
             zip64: None,        // Not used for writing
             unicode_path: None, // Not used for writing
@@ -140,17 +840,22 @@ pub fn rebuild(
         chunks.push(RebuildChunk::Binary(lfh_bytes));
 
         let lfh_offset = current_offset;
+        lfh_offsets.push(lfh_offset);
         current_offset += lfh_size;
 
-        chunks.push(RebuildChunk::Reference {
+        chunks.push(RebuildChunk::EntryData {
             offset: entry.file_offset,
             size: compressed_size,
         });
         current_offset += compressed_size;
 
         new_cd_entries.push(CentralDirectoryEntryInfo {
+            index,
             lfh_offset,
             filename,
+            comment,
+            utf8_flag,
+            unicode_extra_fields,
             entry,
             uncompressed_size,
             compressed_size,
@@ -163,8 +868,12 @@ pub fn rebuild(
     let cd_start_offset = current_offset;
 
     for CentralDirectoryEntryInfo {
+        index,
         lfh_offset,
         filename,
+        comment,
+        utf8_flag,
+        unicode_extra_fields,
         entry,
         uncompressed_size,
         compressed_size,
@@ -175,8 +884,9 @@ pub fn rebuild(
             .cdh
             .extra_fields
             .iter()
-            .filter(|ef| ef.tag != Zip64ExtendedInfo::TAG && ef.tag != UnicodePathExtraField::TAG)
+            .filter(|ef| ef.tag != Zip64ExtendedInfo::TAG && !is_unicode_extra_field(ef.tag))
             .cloned()
+            .chain(unicode_extra_fields)
             .collect::<Vec<_>>();
 
         let version_made_by_os = entry.cdh.version_made_by & 0xFF00;
@@ -225,7 +935,11 @@ pub fn rebuild(
         }
 
         let mut flags = entry.cdh.flags;
-        flags.0 |= 0x0800; // Set UTF-8 flag
+        if utf8_flag {
+            flags.0 |= 0x0800; // Set UTF-8 flag
+        } else {
+            flags.0 &= !0x0800;
+        }
         flags.0 &= !0x0008; // Clear data descriptor flag
 
         let cdh = CentralDirectoryHeader {
@@ -239,18 +953,19 @@ pub fn rebuild(
             crc32,
             compressed_size: cdh_compressed_size,
             uncompressed_size: cdh_uncompressed_size,
-            filename_length: filename.len() as u16,
-            extra_field_length: cdh_extra_fields.iter().map(|ef| 4 + ef.size).sum(),
-            file_comment_length: entry.cdh.file_comment_length,
+            filename_length: filename_length(index, &filename)?,
+            extra_field_length: extra_field_length(index, &cdh_extra_fields)?,
+            file_comment_length: comment.len() as u16,
             disk_number_start: 0,
             internal_file_attributes: entry.cdh.internal_file_attributes,
             external_file_attributes: entry.cdh.external_file_attributes,
             local_header_offset: cdh_local_header_offset,
             filename,
             extra_fields: cdh_extra_fields,
-            file_comment: entry.cdh.file_comment.clone(),
+            file_comment: comment,
             zip64: None,
             unicode_path: None,
+            unicode_comment: None,
         };
 
         let cdh_bytes = cdh.to_bytes()?;
@@ -295,6 +1010,12 @@ pub fn rebuild(
         current_offset += 20;
     }
 
+    if archive_comment.len() > 0xFFFF {
+        return Err(RebuildError::ArchiveCommentTooLong {
+            length: archive_comment.len(),
+        });
+    }
+
     // Write EOCD
     let eocd = EndOfCentralDirectory {
         signature: 0x06054b50,
@@ -320,15 +1041,261 @@ pub fn rebuild(
         } else {
             cd_start_offset as u32
         },
-        comment_length: zip_file.eocd.comment_length,
-        comment: zip_file.eocd.comment.clone(),
+        comment_length: archive_comment.len() as u16,
+        comment: archive_comment,
     };
     let eocd_bytes = eocd.to_bytes()?;
     let eocd_size = eocd_bytes.len() as u64;
     chunks.push(RebuildChunk::Binary(eocd_bytes));
     current_offset += eocd_size;
 
-    Ok((chunks, current_offset))
+    Ok((chunks, current_offset, lfh_offsets))
+}
+
+/// A string encoded in a legacy encoding
+struct LegacyEncoded {
+    /// The encoded bytes, None if encoding failed even after replacement
+    bytes: Option<Vec<u8>>,
+    /// The string after replacing the unmappable characters
+    replaced: String,
+    unmappable: Vec<char>,
+}
+
+/// Encodes a string in a legacy encoding, replacing unmappable characters according to the policy
+///
+/// With [`UnmappablePolicy::Fail`], unmappable characters are dropped and only reported.
+fn encode_legacy(
+    encoding: EncodingOrAscii,
+    string: &str,
+    policy: &UnmappablePolicy,
+) -> LegacyEncoded {
+    if let Some(bytes) = encoding.encode(string) {
+        return LegacyEncoded {
+            bytes: Some(bytes),
+            replaced: string.to_string(),
+            unmappable: Vec::new(),
+        };
+    }
+
+    let mut replaced = String::with_capacity(string.len());
+    let mut unmappable = Vec::new();
+    for c in string.chars() {
+        if encoding.encode(c.encode_utf8(&mut [0; 4])).is_some() {
+            replaced.push(c);
+            continue;
+        }
+        unmappable.push(c);
+        match policy {
+            UnmappablePolicy::Fail => {}
+            UnmappablePolicy::Replace { replacement } => replaced.push_str(replacement),
+            UnmappablePolicy::NumericCharacterReference => {
+                replaced.push_str(&format!("&#{};", c as u32))
+            }
+        }
+    }
+
+    LegacyEncoded {
+        bytes: encoding.encode(&replaced),
+        replaced,
+        unmappable,
+    }
+}
+
+/// Whether the extra field carries a name or comment that the rebuild replaces
+fn is_unicode_extra_field(tag: u16) -> bool {
+    tag == UnicodePathExtraField::TAG || tag == UnicodeCommentExtraField::TAG
+}
+
+/// Builds a Unicode Path extra field for a name written in a legacy encoding
+fn unicode_path_field(
+    index: u64,
+    legacy_name: &[u8],
+    name: &str,
+) -> Result<ExtraField, RebuildError> {
+    let mut data = Vec::with_capacity(5 + name.len());
+    data.push(1); // Version
+    data.extend_from_slice(
+        &(crc_fast::checksum(crc_fast::CrcAlgorithm::Crc32IsoHdlc, legacy_name) as u32)
+            .to_le_bytes(),
+    );
+    data.extend_from_slice(name.as_bytes());
+    let size = u16::try_from(data.len()).map_err(|_| RebuildError::ExtraFieldsTooLong {
+        index,
+        length: 4 + data.len(),
+    })?;
+    Ok(ExtraField {
+        tag: UnicodePathExtraField::TAG,
+        size,
+        data,
+    })
+}
+
+/// Total length of the extra fields of an entry, which must fit in the 16-bit field of its header
+fn extra_field_length(index: u64, extra_fields: &[ExtraField]) -> Result<u16, RebuildError> {
+    let length = extra_fields
+        .iter()
+        .map(|ef| 4 + ef.size as usize)
+        .sum::<usize>();
+    u16::try_from(length).map_err(|_| RebuildError::ExtraFieldsTooLong { index, length })
+}
+
+/// Length of the name of an entry, which must fit in the 16-bit field of its headers
+fn filename_length(index: u64, filename: &[u8]) -> Result<u16, RebuildError> {
+    u16::try_from(filename.len()).map_err(|_| RebuildError::NameTooLong {
+        index,
+        length: filename.len(),
+    })
+}
+
+/// Maximum number of bytes read from the source at once when copying entry data
+const COPY_BUFFER_SIZE: u64 = 1024 * 1024;
+
+/// Trait for writing the rebuilt zip file to its destination
+pub trait ZipWriter {
+    /// Append data to the destination
+    fn write(&mut self, data: &[u8]) -> impl std::future::Future<Output = Result<(), io::Error>>;
+}
+
+/// Progress of writing a rebuilt zip file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    derive(tsify::Tsify)
+)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+pub struct RebuildProgress {
+    pub bytes_written: u64,
+    pub total_bytes: u64,
+    /// Number of entries whose data has been completely written
+    pub entries_written: u64,
+    pub total_entries: u64,
+}
+
+/// Limits how often progress is reported to consumers for which every report is costly
+///
+/// A report passes once per finished entry or every [`COPY_BUFFER_SIZE`] bytes. The first and the final
+/// report always pass.
+#[derive(Debug, Clone, Default)]
+pub struct ProgressThrottle {
+    last_reported: Option<RebuildProgress>,
+}
+
+impl ProgressThrottle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns whether the progress should be reported, remembering it if so
+    pub fn should_report(&mut self, progress: &RebuildProgress) -> bool {
+        let report = match &self.last_reported {
+            None => true,
+            Some(last) => {
+                progress.entries_written != last.entries_written
+                    || progress.bytes_written - last.bytes_written >= COPY_BUFFER_SIZE
+                    || progress.bytes_written == progress.total_bytes
+            }
+        };
+        if report {
+            self.last_reported = Some(*progress);
+        }
+        report
+    }
+}
+
+/// Token to cancel writing a rebuilt zip file, shared between the writer and the canceller
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Request cancellation, the write stops before the next chunk is written
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Writes the chunks returned by [`rebuild`] to the writer, copying referenced data from the reader.
+///
+/// Referenced data is copied in pieces of at most [`COPY_BUFFER_SIZE`] bytes, so memory usage does not
+/// depend on the size of the entries. `on_progress` is called once before writing and after every write.
+/// Returns the number of bytes written, or [`RebuildError::Cancelled`] if the token was cancelled, in
+/// which case the writer holds an incomplete archive.
+pub async fn rebuild_to<Reader: ZipReader, Writer: ZipWriter>(
+    reader: &mut Reader,
+    writer: &mut Writer,
+    chunks: &[RebuildChunk],
+    mut on_progress: impl FnMut(&RebuildProgress),
+    cancellation: &CancellationToken,
+) -> Result<u64, RebuildError> {
+    let mut progress = RebuildProgress {
+        bytes_written: 0,
+        total_bytes: chunks
+            .iter()
+            .map(|chunk| match chunk {
+                RebuildChunk::Reference { size, .. } | RebuildChunk::EntryData { size, .. } => {
+                    *size
+                }
+                RebuildChunk::Binary(data) => data.len() as u64,
+            })
+            .sum(),
+        entries_written: 0,
+        total_entries: chunks
+            .iter()
+            .filter(|chunk| matches!(chunk, RebuildChunk::EntryData { .. }))
+            .count() as u64,
+    };
+    on_progress(&progress);
+
+    for chunk in chunks {
+        match chunk {
+            RebuildChunk::Binary(data) => {
+                if cancellation.is_cancelled() {
+                    return Err(RebuildError::Cancelled);
+                }
+                writer.write(data).await?;
+                progress.bytes_written += data.len() as u64;
+                on_progress(&progress);
+            }
+            RebuildChunk::Reference { offset, size } | RebuildChunk::EntryData { offset, size } => {
+                let mut copied = 0;
+                while copied < *size {
+                    if cancellation.is_cancelled() {
+                        return Err(RebuildError::Cancelled);
+                    }
+                    let length = (*size - copied).min(COPY_BUFFER_SIZE);
+                    let data = reader.read(offset + copied, length).await?;
+                    if data.len() as u64 != length {
+                        return Err(RebuildError::Io(io::Error::new(
+                            io::ErrorKind::UnexpectedEof,
+                            format!("Expected {length} bytes at offset {}", offset + copied),
+                        )));
+                    }
+                    writer.write(&data).await?;
+                    copied += length;
+                    progress.bytes_written += length;
+                    if copied < *size {
+                        on_progress(&progress);
+                    }
+                }
+                if matches!(chunk, RebuildChunk::EntryData { .. }) {
+                    progress.entries_written += 1;
+                }
+                on_progress(&progress);
+            }
+        }
+    }
+
+    Ok(progress.bytes_written)
 }
 
 /// Errors that can occur during the rebuild process
@@ -336,8 +1303,35 @@ pub fn rebuild(
 pub enum RebuildError {
     #[error("Inspection failed: {0}")]
     Inspect(#[from] ZipInspectError),
+    #[error("Comment of entry {index} is too long after conversion to UTF-8 ({length} bytes)")]
+    CommentTooLong { index: u64, length: usize },
+    #[error(
+        "Extra fields of entry {index} are too long ({length} bytes), the maximum is 65535 bytes"
+    )]
+    ExtraFieldsTooLong { index: u64, length: usize },
+    #[error("Name of entry {index} is too long ({length} bytes), the maximum is 65535 bytes")]
+    NameTooLong { index: u64, length: usize },
+    #[error("Archive comment is too long ({length} bytes), the maximum is 65535 bytes")]
+    ArchiveCommentTooLong { length: usize },
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
+    #[error("Rebuild was cancelled")]
+    Cancelled,
+    #[error("Encoding '{0}' cannot be used for names")]
+    UnsupportedLegacyEncoding(String),
+    #[error("Replacement '{replacement}' cannot be represented in {encoding}")]
+    ReplacementNotRepresentable {
+        replacement: String,
+        encoding: String,
+    },
+    #[error("Entries {indices:?} cannot be represented in {encoding}")]
+    NotRepresentable { encoding: String, indices: Vec<u64> },
+    #[error("Entry at offset {offset} is not in the manifest or was renamed after the rebuild")]
+    ManifestMismatch { offset: u64 },
+    #[error("Entries {indices:?} would have the same name '{name}'")]
+    DuplicateName { name: String, indices: Vec<u64> },
+    #[error("Invalid rename rule: {0}")]
+    Rename(#[from] RenameError),
 }
 
 /// Trait for serializing zip structures to bytes
@@ -463,13 +1457,15 @@ impl ZipSerialize for EndOfCentralDirectory {
 mod tests {
     use super::*;
     use crate::zip::inspect::{
-        EncodingSelectionStrategy, FieldSelectionStrategy, InspectConfig, WaveDashHandling,
-        WaveDashNormalization,
+        BackslashHandling, DetectionHint, EncodingOverride, EncodingOverrideTarget,
+        EncodingSelectionStrategy, FieldSelectionStrategy, InspectConfig, UnicodeNormalization,
+        WaveDashHandling, WaveDashNormalization,
     };
     use crate::zip::parse::{
         CentralDirectoryHeader, EndOfCentralDirectory, GeneralPurposeBitFlag, LocalFileHeader,
         UnicodePathExtraField, ZipFile, ZipFileEntry,
     };
+    use crate::zip::rename::{RenameOverride, RenameRule, WINDOWS_ILLEGAL_CHARACTERS};
 
     fn create_mock_entry(
         filename: &[u8],
@@ -500,6 +1496,7 @@ mod tests {
             file_comment: vec![],
             zip64: None,
             unicode_path: unicode_path.clone(),
+            unicode_comment: None,
         };
         let lfh = LocalFileHeader {
             signature: 0x04034b50,
@@ -531,6 +1528,7 @@ mod tests {
     fn create_mock_zip(entries: Vec<ZipFileEntry>) -> ZipFile {
         ZipFile {
             size: 0,
+            prefix_length: 0,
             eocd: EndOfCentralDirectory {
                 signature: 0x06054b50,
                 disk_number: 0,
@@ -554,14 +1552,16 @@ mod tests {
             encoding: EncodingSelectionStrategy::EntryDetected {
                 fallback_encoding: None,
                 ignore_utf8_flag: false,
+                detection_hint: DetectionHint::default(),
             },
             field_selection_strategy: FieldSelectionStrategy::default(),
             ignore_crc32_mismatch: false,
             needs_original_bytes: false,
             wave_dash_handling: WaveDashHandling::default(),
             wave_dash_normalization: WaveDashNormalization::default(),
+            ..Default::default()
         };
-        let result = rebuild(&zip, &config, &[]);
+        let result = rebuild(&zip, &config, &RebuildConfig::default(), &[]);
         assert!(result.is_ok());
         let (chunks, size) = result.unwrap();
         // EOCD is 22 bytes
@@ -577,14 +1577,16 @@ mod tests {
             encoding: EncodingSelectionStrategy::EntryDetected {
                 fallback_encoding: None,
                 ignore_utf8_flag: false,
+                detection_hint: DetectionHint::default(),
             },
             field_selection_strategy: FieldSelectionStrategy::default(),
             ignore_crc32_mismatch: false,
             needs_original_bytes: false,
             wave_dash_handling: WaveDashHandling::default(),
             wave_dash_normalization: WaveDashNormalization::default(),
+            ..Default::default()
         };
-        let result = rebuild(&zip, &config, &[]);
+        let result = rebuild(&zip, &config, &RebuildConfig::default(), &[]);
         assert!(result.is_ok());
         let (chunks, size) = result.unwrap();
         assert!(size > 22);
@@ -600,21 +1602,1188 @@ mod tests {
             encoding: EncodingSelectionStrategy::EntryDetected {
                 fallback_encoding: None,
                 ignore_utf8_flag: false,
+                detection_hint: DetectionHint::default(),
             },
             field_selection_strategy: FieldSelectionStrategy::default(),
             ignore_crc32_mismatch: false,
             needs_original_bytes: false,
             wave_dash_handling: WaveDashHandling::default(),
             wave_dash_normalization: WaveDashNormalization::default(),
+            ..Default::default()
         };
         // Omit the first entry (index 0)
-        let result = rebuild(&zip, &config, &[0]);
+        let result = rebuild(&zip, &config, &RebuildConfig::default(), &[0]);
         assert!(result.is_ok());
         let (_chunks, size) = result.unwrap();
 
         // Should contain only one entry + EOCD
         // We can't easily check the exact size without calculating it, but we can check if it's smaller than full rebuild
-        let full_result = rebuild(&zip, &config, &[]).unwrap();
+        let full_result = rebuild(&zip, &config, &RebuildConfig::default(), &[]).unwrap();
         assert!(size < full_result.1);
     }
+
+    #[test]
+    fn test_rebuild_comment_as_utf8() {
+        // "テスト" in Shift_JIS
+        let mut entry = create_mock_entry(b"test.txt", false, None);
+        entry.cdh.file_comment = b"\x83\x65\x83\x58\x83\x67".to_vec();
+        entry.cdh.extra_fields.push(ExtraField {
+            tag: UnicodeCommentExtraField::TAG,
+            size: 0,
+            data: vec![],
+        });
+        let zip = create_mock_zip(vec![entry]);
+        let config = InspectConfig {
+            encoding: EncodingSelectionStrategy::ForceSpecified {
+                encoding: "Shift_JIS".to_string(),
+                ignore_utf8_flag: false,
+            },
+            ..Default::default()
+        };
+
+        let (chunks, _) = rebuild(&zip, &config, &RebuildConfig::default(), &[]).unwrap();
+        let RebuildChunk::Binary(cdh) = &chunks[2] else {
+            panic!("Expected CDH to be binary");
+        };
+        let comment = "テスト".as_bytes();
+        let extra_field_length = u16::from_le_bytes(cdh[30..32].try_into().unwrap());
+        let comment_length = u16::from_le_bytes(cdh[32..34].try_into().unwrap());
+        assert_eq!(extra_field_length, 0);
+        assert_eq!(comment_length as usize, comment.len());
+        assert!(cdh.ends_with(comment));
+    }
+
+    #[test]
+    fn test_rebuild_undecodable_comment() {
+        let mut entry = create_mock_entry(b"test.txt", false, None);
+        entry.cdh.file_comment = b"ok\xff\xff".to_vec();
+        let zip = create_mock_zip(vec![entry]);
+        // Nothing but Shift_JIS is allowed and there is no fallback, so the comment cannot be decoded
+        let config = InspectConfig {
+            encoding: EncodingSelectionStrategy::EntryDetected {
+                fallback_encoding: None,
+                ignore_utf8_flag: false,
+                detection_hint: DetectionHint {
+                    locale: None,
+                    allowed_encodings: vec!["Shift_JIS".to_string()],
+                },
+            },
+            ..Default::default()
+        };
+
+        let (chunks, _, report) =
+            rebuild_with_report(&zip, &config, &RebuildConfig::default(), &[]).unwrap();
+        let RebuildChunk::Binary(cdh) = &chunks[2] else {
+            panic!("Expected CDH to be binary");
+        };
+        let flags = u16::from_le_bytes(cdh[8..10].try_into().unwrap());
+        assert_ne!(flags & 0x0800, 0);
+        assert!(cdh.ends_with("ok\u{FFFD}\u{FFFD}".as_bytes()));
+        assert_eq!(
+            report.warnings,
+            [RebuildWarning::UndecodableComment {
+                index: 0,
+                name: "test.txt".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_rebuild_archive_comment_policy() {
+        let entry = create_mock_entry(b"test.txt", true, None);
+        let mut zip = create_mock_zip(vec![entry]);
+        // "テスト" in Shift_JIS
+        zip.eocd.comment = b"\x83\x65\x83\x58\x83\x67".to_vec();
+        zip.eocd.comment_length = 6;
+        let config = InspectConfig {
+            encoding: EncodingSelectionStrategy::PreferOverallDetected {
+                fallback_encoding: Some("Shift_JIS".to_string()),
+                ignore_utf8_flag: false,
+                detection_hint: DetectionHint::default(),
+            },
+            ..Default::default()
+        };
+
+        let rebuild_comment = |archive_comment| {
+            let rebuild_config = RebuildConfig {
+                archive_comment,
+                ..RebuildConfig::default()
+            };
+            let (chunks, _) = rebuild(&zip, &config, &rebuild_config, &[])?;
+            let RebuildChunk::Binary(eocd) = chunks.last().unwrap() else {
+                panic!("Expected EOCD to be binary");
+            };
+            let comment_length = u16::from_le_bytes(eocd[20..22].try_into().unwrap());
+            assert_eq!(eocd.len(), 22 + comment_length as usize);
+            Ok::<_, RebuildError>(eocd[22..].to_vec())
+        };
+
+        assert_eq!(
+            rebuild_comment(ArchiveCommentPolicy::Keep).unwrap(),
+            zip.eocd.comment
+        );
+        assert_eq!(
+            rebuild_comment(ArchiveCommentPolicy::TranscodeUtf8).unwrap(),
+            "テスト".as_bytes()
+        );
+        assert_eq!(
+            rebuild_comment(ArchiveCommentPolicy::Replace {
+                comment: "Rebuilt".to_string()
+            })
+            .unwrap(),
+            b"Rebuilt"
+        );
+        assert!(
+            rebuild_comment(ArchiveCommentPolicy::Strip)
+                .unwrap()
+                .is_empty()
+        );
+        assert!(matches!(
+            rebuild_comment(ArchiveCommentPolicy::Replace {
+                comment: "あ".repeat(0x5556)
+            }),
+            Err(RebuildError::ArchiveCommentTooLong { length: 0x10002 })
+        ));
+
+        // Each "あ" takes 2 bytes in Shift_JIS but 3 in UTF-8, so the comment no longer fits once transcoded
+        zip.eocd.comment = b"\x82\xa0".repeat(30000);
+        zip.eocd.comment_length = 60000;
+        let rebuild_config = RebuildConfig {
+            archive_comment: ArchiveCommentPolicy::TranscodeUtf8,
+            ..RebuildConfig::default()
+        };
+        assert!(matches!(
+            rebuild(&zip, &config, &rebuild_config, &[]),
+            Err(RebuildError::ArchiveCommentTooLong { length: 90000 })
+        ));
+    }
+
+    #[test]
+    fn test_rebuild_repair_double_encoding() {
+        let entry = create_mock_entry("cafÃ©.txt".as_bytes(), true, None);
+        let zip = create_mock_zip(vec![entry]);
+        let config = InspectConfig {
+            encoding: EncodingSelectionStrategy::EntryDetected {
+                fallback_encoding: None,
+                ignore_utf8_flag: false,
+                detection_hint: DetectionHint::default(),
+            },
+            ..Default::default()
+        };
+
+        let lfh_filename = |rebuild_config: &RebuildConfig| {
+            let (chunks, _) = rebuild(&zip, &config, rebuild_config, &[]).unwrap();
+            let RebuildChunk::Binary(lfh) = &chunks[0] else {
+                panic!("Expected LFH to be binary");
+            };
+            let filename_length = u16::from_le_bytes(lfh[26..28].try_into().unwrap()) as usize;
+            String::from_utf8(lfh[30..30 + filename_length].to_vec()).unwrap()
+        };
+
+        // Names are only repaired on request
+        assert_eq!(lfh_filename(&RebuildConfig::default()), "cafÃ©.txt");
+        let repair_config = RebuildConfig {
+            repair_double_encoding: true,
+            ..RebuildConfig::default()
+        };
+        assert_eq!(lfh_filename(&repair_config), "café.txt");
+    }
+
+    #[test]
+    fn test_rebuild_unicode_normalization() {
+        let entry = create_mock_entry("\u{304B}\u{3099}.txt".as_bytes(), true, None);
+        let zip = create_mock_zip(vec![entry]);
+        let config = InspectConfig {
+            encoding: EncodingSelectionStrategy::EntryDetected {
+                fallback_encoding: None,
+                ignore_utf8_flag: false,
+                detection_hint: DetectionHint::default(),
+            },
+            unicode_normalization: UnicodeNormalization::Nfc,
+            ..Default::default()
+        };
+
+        let (chunks, _) = rebuild(&zip, &config, &RebuildConfig::default(), &[]).unwrap();
+        let RebuildChunk::Binary(lfh) = &chunks[0] else {
+            panic!("Expected LFH to be binary");
+        };
+        let filename_length = u16::from_le_bytes(lfh[26..28].try_into().unwrap()) as usize;
+        assert_eq!(&lfh[30..30 + filename_length], "\u{304C}.txt".as_bytes());
+    }
+
+    #[test]
+    fn test_rebuild_backslash_separator() {
+        // "表\a.txt" in Shift_JIS, where "表" is 0x95 0x5C
+        let entry = create_mock_entry(b"\x95\x5c\x5ca.txt", false, None);
+        let zip = create_mock_zip(vec![entry]);
+        let config = InspectConfig {
+            encoding: EncodingSelectionStrategy::ForceSpecified {
+                encoding: "Shift_JIS".to_string(),
+                ignore_utf8_flag: false,
+            },
+            backslash_handling: BackslashHandling::ConvertToSlash,
+            ..Default::default()
+        };
+
+        let (chunks, _) = rebuild(&zip, &config, &RebuildConfig::default(), &[]).unwrap();
+        let RebuildChunk::Binary(lfh) = &chunks[0] else {
+            panic!("Expected LFH to be binary");
+        };
+        let filename_length = u16::from_le_bytes(lfh[26..28].try_into().unwrap()) as usize;
+        assert_eq!(&lfh[30..30 + filename_length], "表/a.txt".as_bytes());
+    }
+
+    #[test]
+    fn test_rebuild_encoding_overrides() {
+        // "写真.txt" in Shift_JIS and "中文.txt" in GBK
+        let entry1 = create_mock_entry(b"\x8e\xca\x90^.txt", false, None);
+        let entry2 = create_mock_entry(b"\xd6\xd0\xce\xc4.txt", false, None);
+        let zip = create_mock_zip(vec![entry1, entry2]);
+        let config = InspectConfig {
+            encoding: EncodingSelectionStrategy::ForceSpecified {
+                encoding: "Shift_JIS".to_string(),
+                ignore_utf8_flag: false,
+            },
+            encoding_overrides: vec![EncodingOverride {
+                target: EncodingOverrideTarget::Index { index: 1 },
+                encoding: "GBK".to_string(),
+            }],
+            ..Default::default()
+        };
+
+        let (chunks, _) = rebuild(&zip, &config, &RebuildConfig::default(), &[]).unwrap();
+        let filenames = chunks
+            .iter()
+            .filter_map(|chunk| match chunk {
+                RebuildChunk::Binary(data) if data.starts_with(b"PK\x03\x04") => {
+                    let filename_length =
+                        u16::from_le_bytes(data[26..28].try_into().unwrap()) as usize;
+                    Some(String::from_utf8(data[30..30 + filename_length].to_vec()).unwrap())
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(filenames, ["写真.txt", "中文.txt"]);
+    }
+
+    #[test]
+    fn test_rebuild_prefix_handling() {
+        let entry = create_mock_entry(b"test.txt", true, None);
+        let mut zip = create_mock_zip(vec![entry]);
+        zip.prefix_length = 100;
+        let config = InspectConfig {
+            encoding: EncodingSelectionStrategy::EntryDetected {
+                fallback_encoding: None,
+                ignore_utf8_flag: false,
+                detection_hint: DetectionHint::default(),
+            },
+            ..Default::default()
+        };
+
+        let (dropped_chunks, dropped_size) =
+            rebuild(&zip, &config, &RebuildConfig::default(), &[]).unwrap();
+        assert!(matches!(dropped_chunks[0], RebuildChunk::Binary(_)));
+
+        let keep_config = RebuildConfig {
+            prefix_handling: PrefixHandling::Keep,
+            ..RebuildConfig::default()
+        };
+        let (kept_chunks, kept_size) = rebuild(&zip, &config, &keep_config, &[]).unwrap();
+        assert!(matches!(
+            kept_chunks[0],
+            RebuildChunk::Reference {
+                offset: 0,
+                size: 100
+            }
+        ));
+        assert_eq!(kept_size, dropped_size + 100);
+
+        // The central directory offset in the EOCD includes the prefix
+        let RebuildChunk::Binary(eocd) = kept_chunks.last().unwrap() else {
+            panic!("Expected EOCD to be binary");
+        };
+        let RebuildChunk::Binary(dropped_eocd) = dropped_chunks.last().unwrap() else {
+            panic!("Expected EOCD to be binary");
+        };
+        let cd_offset = u32::from_le_bytes(eocd[16..20].try_into().unwrap());
+        let dropped_cd_offset = u32::from_le_bytes(dropped_eocd[16..20].try_into().unwrap());
+        assert_eq!(cd_offset, dropped_cd_offset + 100);
+    }
+
+    struct MockReader {
+        data: Vec<u8>,
+    }
+
+    impl ZipReader for MockReader {
+        async fn get_size(&mut self) -> Result<u64, io::Error> {
+            Ok(self.data.len() as u64)
+        }
+
+        async fn read(&mut self, offset: u64, size: u64) -> Result<Vec<u8>, io::Error> {
+            assert!(size <= COPY_BUFFER_SIZE);
+            let start = (offset as usize).min(self.data.len());
+            let end = (start + size as usize).min(self.data.len());
+            Ok(self.data[start..end].to_vec())
+        }
+    }
+
+    impl ZipWriter for Vec<u8> {
+        async fn write(&mut self, data: &[u8]) -> Result<(), io::Error> {
+            self.extend_from_slice(data);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rebuild_to() {
+        let large_size = COPY_BUFFER_SIZE as usize * 2 + 10;
+        let source = (0..large_size + 200).map(|i| i as u8).collect::<Vec<_>>();
+
+        let mut large = create_mock_entry(b"large.bin", true, None);
+        large.file_offset = 100;
+        large.cdh.compressed_size = large_size as u32;
+        let mut small = create_mock_entry(b"small.bin", true, None);
+        small.file_offset = 100 + large_size as u64;
+        small.cdh.compressed_size = 100;
+        let empty = create_mock_entry(b"empty/", true, None);
+        let mut zip = create_mock_zip(vec![large, small, empty]);
+        zip.prefix_length = 100;
+
+        let config = InspectConfig {
+            encoding: EncodingSelectionStrategy::EntryDetected {
+                fallback_encoding: None,
+                ignore_utf8_flag: false,
+                detection_hint: DetectionHint::default(),
+            },
+            ..Default::default()
+        };
+        let rebuild_config = RebuildConfig {
+            prefix_handling: PrefixHandling::Keep,
+            ..RebuildConfig::default()
+        };
+        let (chunks, size) = rebuild(&zip, &config, &rebuild_config, &[]).unwrap();
+
+        let mut expected = Vec::new();
+        for chunk in &chunks {
+            match chunk {
+                RebuildChunk::Reference { offset, size }
+                | RebuildChunk::EntryData { offset, size } => expected
+                    .extend_from_slice(&source[*offset as usize..(*offset + *size) as usize]),
+                RebuildChunk::Binary(data) => expected.extend_from_slice(data),
+            }
+        }
+
+        let mut reader = MockReader { data: source };
+        let mut output = Vec::new();
+        let mut reports = Vec::new();
+        let written = rebuild_to(
+            &mut reader,
+            &mut output,
+            &chunks,
+            |progress| reports.push(*progress),
+            &CancellationToken::new(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(written, size);
+        assert_eq!(output, expected);
+
+        // The prefix is not counted as an entry
+        assert_eq!(
+            reports.first(),
+            Some(&RebuildProgress {
+                bytes_written: 0,
+                total_bytes: size,
+                entries_written: 0,
+                total_entries: 3,
+            })
+        );
+        assert_eq!(
+            reports.last(),
+            Some(&RebuildProgress {
+                bytes_written: size,
+                total_bytes: size,
+                entries_written: 3,
+                total_entries: 3,
+            })
+        );
+        assert!(
+            reports
+                .windows(2)
+                .all(|w| w[0].bytes_written <= w[1].bytes_written
+                    && w[0].entries_written <= w[1].entries_written)
+        );
+
+        // Cancelling stops the rebuild before the next write
+        let cancellation = CancellationToken::new();
+        let mut output = Vec::new();
+        let result = rebuild_to(
+            &mut reader,
+            &mut output,
+            &chunks,
+            |progress| {
+                if progress.bytes_written >= COPY_BUFFER_SIZE {
+                    cancellation.cancel();
+                }
+            },
+            &cancellation,
+        )
+        .await;
+        assert!(matches!(result, Err(RebuildError::Cancelled)));
+        assert!(output.len() < expected.len());
+        assert_eq!(output[..], expected[..output.len()]);
+    }
+
+    #[test]
+    fn test_progress_throttle() {
+        let progress = |bytes_written, entries_written| RebuildProgress {
+            bytes_written,
+            total_bytes: COPY_BUFFER_SIZE * 3,
+            entries_written,
+            total_entries: 2,
+        };
+        let mut throttle = ProgressThrottle::new();
+        let reports = [
+            progress(0, 0),
+            progress(30, 0),
+            progress(COPY_BUFFER_SIZE, 0),
+            progress(COPY_BUFFER_SIZE + 30, 0),
+            progress(COPY_BUFFER_SIZE + 60, 1),
+            progress(COPY_BUFFER_SIZE * 2 + 50, 1),
+            progress(COPY_BUFFER_SIZE * 2 + 60, 2),
+            progress(COPY_BUFFER_SIZE * 2 + 90, 2),
+            progress(COPY_BUFFER_SIZE * 3, 2),
+        ]
+        .iter()
+        .map(|progress| throttle.should_report(progress))
+        .collect::<Vec<_>>();
+        assert_eq!(
+            reports,
+            [true, false, true, false, true, false, true, false, true]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rebuild_legacy_filenames() {
+        let entries = vec![
+            create_mock_entry("写真.txt".as_bytes(), true, None),
+            create_mock_entry(b"readme.txt", true, None),
+            create_mock_entry("한국.txt".as_bytes(), true, None),
+        ];
+        let zip = create_mock_zip(entries);
+        let config = InspectConfig {
+            encoding: EncodingSelectionStrategy::EntryDetected {
+                fallback_encoding: None,
+                ignore_utf8_flag: false,
+                detection_hint: DetectionHint::default(),
+            },
+            ..Default::default()
+        };
+        let rebuild_config = RebuildConfig {
+            filename_mode: FilenameOutputMode::LegacyWithUnicodePath {
+                encoding: "Shift_JIS".to_string(),
+            },
+            ..RebuildConfig::default()
+        };
+
+        let (chunks, _, report) = rebuild_with_report(&zip, &config, &rebuild_config, &[]).unwrap();
+        assert_eq!(
+            report.warnings,
+            [RebuildWarning::NotRepresentable {
+                index: 2,
+                name: "한국.txt".to_string(),
+                encoding: "Shift_JIS".to_string(),
+            }]
+        );
+
+        let mut output = Vec::new();
+        rebuild_to(
+            &mut MockReader { data: Vec::new() },
+            &mut output,
+            &chunks,
+            |_| {},
+            &CancellationToken::new(),
+        )
+        .await
+        .unwrap();
+        let mut reader = MockReader { data: output };
+        let rebuilt = ZipFile::parse(&mut reader, false, |_, e| Err(e))
+            .await
+            .unwrap();
+
+        // Legacy name with a matching Unicode Path in both headers
+        let entry = &rebuilt.entries[0];
+        assert_eq!(entry.cdh.filename, b"\x8e\xca\x90^.txt");
+        assert!(!entry.cdh.flags.is_utf8());
+        for unicode_path in [&entry.cdh.unicode_path, &entry.lfh.unicode_path] {
+            let unicode_path = unicode_path.as_ref().unwrap();
+            assert!(unicode_path.crc32_matched);
+            assert_eq!(unicode_path.decoded_string.as_deref(), Some("写真.txt"));
+        }
+
+        // ASCII names need no Unicode Path
+        let entry = &rebuilt.entries[1];
+        assert_eq!(entry.cdh.filename, b"readme.txt");
+        assert!(!entry.cdh.flags.is_utf8());
+        assert!(entry.cdh.unicode_path.is_none());
+
+        // Names that cannot be represented fall back to UTF-8
+        let entry = &rebuilt.entries[2];
+        assert_eq!(entry.cdh.filename, "한국.txt".as_bytes());
+        assert!(entry.cdh.flags.is_utf8());
+        assert!(entry.cdh.unicode_path.is_none());
+
+        let unknown_config = RebuildConfig {
+            filename_mode: FilenameOutputMode::LegacyWithUnicodePath {
+                encoding: "unknown".to_string(),
+            },
+            ..RebuildConfig::default()
+        };
+        assert!(matches!(
+            rebuild(&zip, &config, &unknown_config, &[]),
+            Err(RebuildError::Inspect(ZipInspectError::EncodingNotFound(_)))
+        ));
+    }
+
+    #[test]
+    fn test_rebuild_extra_fields_too_long() {
+        let config = InspectConfig::default();
+        let rebuild_config = RebuildConfig {
+            filename_mode: FilenameOutputMode::LegacyWithUnicodePath {
+                encoding: "Shift_JIS".to_string(),
+            },
+            ..RebuildConfig::default()
+        };
+
+        // The Unicode Path of 22000 "あ" takes 66000 bytes
+        let name = "あ".repeat(22000);
+        let zip = create_mock_zip(vec![create_mock_entry(name.as_bytes(), true, None)]);
+        assert!(matches!(
+            rebuild(&zip, &config, &rebuild_config, &[]),
+            Err(RebuildError::ExtraFieldsTooLong { index: 0, length }) if length == 4 + 5 + 66000
+        ));
+
+        // Each field fits, but not together with the fields kept from the source
+        let mut entry = create_mock_entry("写真.txt".as_bytes(), true, None);
+        entry.lfh.extra_fields.push(ExtraField {
+            tag: 0xCAFE,
+            size: 65520,
+            data: vec![0; 65520],
+        });
+        let zip = create_mock_zip(vec![create_mock_entry(b"a.txt", true, None), entry]);
+        assert!(matches!(
+            rebuild(&zip, &config, &rebuild_config, &[]),
+            Err(RebuildError::ExtraFieldsTooLong { index: 1, length }) if length == 65524 + 4 + 5 + 10
+        ));
+    }
+
+    #[test]
+    fn test_rebuild_duplicate_names_after_normalization() {
+        let entries = vec![
+            create_mock_entry("caf\u{e9}.txt".as_bytes(), true, None),
+            create_mock_entry("cafe\u{301}.txt".as_bytes(), true, None),
+            create_mock_entry(b"dup.txt", true, None),
+            create_mock_entry(b"dup.txt", true, None),
+        ];
+        let zip = create_mock_zip(entries);
+        let mut config = InspectConfig::default();
+
+        // Duplicates already in the archive are kept as they are
+        assert!(rebuild(&zip, &config, &RebuildConfig::default(), &[]).is_ok());
+
+        config.unicode_normalization = UnicodeNormalization::Nfc;
+        let result = rebuild(&zip, &config, &RebuildConfig::default(), &[]);
+        let Err(RebuildError::DuplicateName { name, indices }) = result else {
+            panic!("Expected names colliding after normalization to fail");
+        };
+        assert_eq!(name, "caf\u{e9}.txt");
+        assert_eq!(indices, [0, 1]);
+
+        assert!(rebuild(&zip, &config, &RebuildConfig::default(), &[0]).is_ok());
+    }
+
+    #[test]
+    fn test_rebuild_name_too_long() {
+        let config = InspectConfig::default();
+        let zip = create_mock_zip(vec![
+            create_mock_entry(b"a.txt", true, None),
+            create_mock_entry(b"b.txt", true, None),
+        ]);
+
+        let overridden = RebuildConfig {
+            rename: RenameConfig {
+                overrides: vec![RenameOverride {
+                    index: 1,
+                    name: "b".repeat(70000),
+                }],
+                rules: Vec::new(),
+            },
+            ..RebuildConfig::default()
+        };
+        assert!(matches!(
+            rebuild(&zip, &config, &overridden, &[]),
+            Err(RebuildError::NameTooLong {
+                index: 1,
+                length: 70000
+            })
+        ));
+
+        // Rules can make names longer as well
+        let replaced = RebuildConfig {
+            rename: RenameConfig {
+                overrides: Vec::new(),
+                rules: vec![RenameRule::Replace {
+                    from: ".".to_string(),
+                    to: "_".repeat(70000),
+                }],
+            },
+            ..RebuildConfig::default()
+        };
+        assert!(matches!(
+            rebuild(&zip, &config, &replaced, &[]),
+            Err(RebuildError::NameTooLong {
+                index: 0,
+                length: 70004
+            })
+        ));
+    }
+
+    #[test]
+    fn test_rebuild_target_encoding() {
+        let entries = vec![
+            create_mock_entry("写真.txt".as_bytes(), true, None),
+            create_mock_entry("한국.txt".as_bytes(), true, None),
+        ];
+        let zip = create_mock_zip(entries);
+        let config = InspectConfig {
+            encoding: EncodingSelectionStrategy::EntryDetected {
+                fallback_encoding: None,
+                ignore_utf8_flag: false,
+                detection_hint: DetectionHint::default(),
+            },
+            ..Default::default()
+        };
+        let target = |encoding: &str, unmappable: UnmappablePolicy| RebuildConfig {
+            filename_mode: FilenameOutputMode::Legacy {
+                encoding: encoding.to_string(),
+                unmappable,
+            },
+            ..RebuildConfig::default()
+        };
+        let local_headers = |chunks: &[RebuildChunk]| {
+            chunks
+                .iter()
+                .filter_map(|chunk| match chunk {
+                    RebuildChunk::Binary(data) if data.starts_with(b"PK\x03\x04") => {
+                        let flags = u16::from_le_bytes(data[6..8].try_into().unwrap());
+                        let filename_length =
+                            u16::from_le_bytes(data[26..28].try_into().unwrap()) as usize;
+                        Some((flags & 0x0800, data[30..30 + filename_length].to_vec()))
+                    }
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let result = rebuild(
+            &zip,
+            &config,
+            &target("Shift_JIS", UnmappablePolicy::Fail),
+            &[],
+        );
+        let Err(RebuildError::NotRepresentable { encoding, indices }) = result else {
+            panic!("Expected unmappable names to fail");
+        };
+        assert_eq!(encoding, "Shift_JIS");
+        assert_eq!(indices, [1]);
+
+        let replace = UnmappablePolicy::Replace {
+            replacement: "_".to_string(),
+        };
+        let (chunks, _, report) =
+            rebuild_with_report(&zip, &config, &target("Shift_JIS", replace), &[]).unwrap();
+        assert_eq!(
+            local_headers(&chunks),
+            [(0, b"\x8e\xca\x90^.txt".to_vec()), (0, b"__.txt".to_vec())]
+        );
+        assert_eq!(
+            report.warnings,
+            [RebuildWarning::LossyConversion {
+                index: 1,
+                name: "한국.txt".to_string(),
+                written_name: "__.txt".to_string(),
+                comment_lossy: false,
+                unmappable: vec!['한', '국'],
+            }]
+        );
+
+        let (chunks, _, _) = rebuild_with_report(
+            &zip,
+            &config,
+            &target("Shift_JIS", UnmappablePolicy::NumericCharacterReference),
+            &[],
+        )
+        .unwrap();
+        assert_eq!(local_headers(&chunks)[1].1, b"&#54620;&#44397;.txt");
+
+        // Omitting the unmappable entry makes the conversion lossless
+        let (_, _, report) =
+            rebuild_with_report(&zip, &config, &target("GBK", UnmappablePolicy::Fail), &[1])
+                .unwrap();
+        assert!(report.warnings.is_empty());
+
+        let replace = UnmappablePolicy::Replace {
+            replacement: "국".to_string(),
+        };
+        assert!(matches!(
+            rebuild(&zip, &config, &target("Shift_JIS", replace), &[]),
+            Err(RebuildError::ReplacementNotRepresentable { .. })
+        ));
+        assert!(matches!(
+            rebuild(
+                &zip,
+                &config,
+                &target("UTF-16LE", UnmappablePolicy::Fail),
+                &[]
+            ),
+            Err(RebuildError::UnsupportedLegacyEncoding(_))
+        ));
+    }
+
+    #[test]
+    fn test_rebuild_target_encoding_name_too_long() {
+        let config = InspectConfig::default();
+        // 9000 "한" take 27000 bytes in UTF-8, but each is replaced with more bytes in Shift_JIS
+        let name = "한".repeat(9000);
+        let zip = create_mock_zip(vec![create_mock_entry(name.as_bytes(), true, None)]);
+        let target = |unmappable: UnmappablePolicy| RebuildConfig {
+            filename_mode: FilenameOutputMode::Legacy {
+                encoding: "Shift_JIS".to_string(),
+                unmappable,
+            },
+            ..RebuildConfig::default()
+        };
+
+        let replace = UnmappablePolicy::Replace {
+            replacement: "_".repeat(10),
+        };
+        assert!(matches!(
+            rebuild(&zip, &config, &target(replace), &[]),
+            Err(RebuildError::NameTooLong {
+                index: 0,
+                length: 90000
+            })
+        ));
+        // "&#54620;" takes 8 bytes
+        assert!(matches!(
+            rebuild(
+                &zip,
+                &config,
+                &target(UnmappablePolicy::NumericCharacterReference),
+                &[]
+            ),
+            Err(RebuildError::NameTooLong {
+                index: 0,
+                length: 72000
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_rebuild_report_and_restore() {
+        // "写真.txt" in Shift_JIS without the UTF-8 flag
+        let mut sjis = create_mock_entry(b"\x8e\xca\x90^.txt", false, None);
+        sjis.cdh.file_comment = b"\x83e\x83X\x83g".to_vec();
+        sjis.cdh.file_comment_length = sjis.cdh.file_comment.len() as u16;
+        let entries = vec![
+            sjis,
+            create_mock_entry(b"__MACOSX/._a", false, None),
+            create_mock_entry("中文.txt".as_bytes(), true, None),
+        ];
+        let zip = create_mock_zip(entries);
+        let config = InspectConfig {
+            encoding: EncodingSelectionStrategy::ForceSpecified {
+                encoding: "Shift_JIS".to_string(),
+                ignore_utf8_flag: false,
+            },
+            ..Default::default()
+        };
+
+        let (chunks, _, report) =
+            rebuild_with_report(&zip, &config, &RebuildConfig::default(), &[1]).unwrap();
+        assert_eq!(report.entries.len(), 3);
+        let entry = &report.entries[0];
+        assert_eq!(entry.original_filename, b"\x8e\xca\x90^.txt");
+        assert!(!entry.original_utf8_flag);
+        assert_eq!(entry.source_encoding.as_deref(), Some("Shift_JIS"));
+        assert_eq!(entry.new_name.as_deref(), Some("写真.txt"));
+        assert_eq!(entry.new_filename.as_deref(), Some("写真.txt".as_bytes()));
+        assert_eq!(entry.new_offset, Some(0));
+        let omitted = &report.entries[1];
+        assert_eq!(omitted.new_name, None);
+        assert_eq!(omitted.new_offset, None);
+        assert!(report.entries[2].new_offset.unwrap() > 0);
+
+        let mut output = Vec::new();
+        rebuild_to(
+            &mut MockReader { data: Vec::new() },
+            &mut output,
+            &chunks,
+            |_| {},
+            &CancellationToken::new(),
+        )
+        .await
+        .unwrap();
+        let mut reader = MockReader { data: output };
+        let rebuilt = ZipFile::parse(&mut reader, false, |_, e| Err(e))
+            .await
+            .unwrap();
+
+        let (chunks, _) = restore(&rebuilt, &report).unwrap();
+        let mut output = Vec::new();
+        rebuild_to(
+            &mut reader,
+            &mut output,
+            &chunks,
+            |_| {},
+            &CancellationToken::new(),
+        )
+        .await
+        .unwrap();
+        let restored = ZipFile::parse(&mut MockReader { data: output }, false, |_, e| Err(e))
+            .await
+            .unwrap();
+        let names = restored
+            .entries
+            .iter()
+            .map(|e| {
+                (
+                    e.cdh.filename.clone(),
+                    e.cdh.flags.is_utf8(),
+                    e.cdh.file_comment.clone(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                (
+                    b"\x8e\xca\x90^.txt".to_vec(),
+                    false,
+                    b"\x83e\x83X\x83g".to_vec()
+                ),
+                ("中文.txt".as_bytes().to_vec(), true, Vec::new()),
+            ]
+        );
+
+        // The archive must match the manifest
+        let mut renamed = report.clone();
+        renamed.entries[0].new_filename = Some(b"other.txt".to_vec());
+        assert!(matches!(
+            restore(&rebuilt, &renamed),
+            Err(RebuildError::ManifestMismatch { offset: 0 })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_restore_identical_headers() {
+        // "写真.txt" in Shift_JIS in the central directory, but as UTF-8 in the local file header
+        let mut entry = create_mock_entry(b"\x8e\xca\x90^.txt", false, None);
+        entry.lfh.filename = "写真.txt".as_bytes().to_vec();
+        entry.lfh.flags = GeneralPurposeBitFlag(0x0800);
+        entry.cdh.file_comment = b"\x83e\x83X\x83g".to_vec();
+        let mut zip = create_mock_zip(vec![entry]);
+        // "コメント" in Shift_JIS
+        zip.eocd.comment = b"\x83R\x83\x81\x83\x93\x83g".to_vec();
+
+        // Write the source with headers as a rebuild would, so that only the names and comments differ
+        let headers = zip
+            .entries
+            .iter()
+            .enumerate()
+            .map(|(index, entry)| EntryHeaders {
+                index: index as u64,
+                entry,
+                lfh_filename: entry.lfh.filename.clone(),
+                lfh_utf8_flag: entry.lfh.flags.is_utf8(),
+                filename: entry.cdh.filename.clone(),
+                comment: entry.cdh.file_comment.clone(),
+                utf8_flag: entry.cdh.flags.is_utf8(),
+                lfh_unicode_extra_fields: Vec::new(),
+                unicode_extra_fields: Vec::new(),
+            })
+            .collect();
+        let (chunks, _, _) = write_archive(
+            &zip,
+            PrefixHandling::Keep,
+            zip.eocd.comment.clone(),
+            headers,
+        )
+        .unwrap();
+        let mut original = Vec::new();
+        rebuild_to(
+            &mut MockReader { data: Vec::new() },
+            &mut original,
+            &chunks,
+            |_| {},
+            &CancellationToken::new(),
+        )
+        .await
+        .unwrap();
+        let mut reader = MockReader {
+            data: original.clone(),
+        };
+        let source = ZipFile::parse(&mut reader, false, |_, e| Err(e))
+            .await
+            .unwrap();
+
+        let config = InspectConfig {
+            encoding: EncodingSelectionStrategy::ForceSpecified {
+                encoding: "Shift_JIS".to_string(),
+                ignore_utf8_flag: false,
+            },
+            ..Default::default()
+        };
+        let rebuild_config = RebuildConfig {
+            archive_comment: ArchiveCommentPolicy::TranscodeUtf8,
+            ..Default::default()
+        };
+        let (chunks, _, report) =
+            rebuild_with_report(&source, &config, &rebuild_config, &[]).unwrap();
+        let mut rebuilt = Vec::new();
+        rebuild_to(
+            &mut reader,
+            &mut rebuilt,
+            &chunks,
+            |_| {},
+            &CancellationToken::new(),
+        )
+        .await
+        .unwrap();
+        assert_ne!(rebuilt, original);
+        let mut reader = MockReader { data: rebuilt };
+        let rebuilt_zip = ZipFile::parse(&mut reader, false, |_, e| Err(e))
+            .await
+            .unwrap();
+
+        let (chunks, _) = restore(&rebuilt_zip, &report).unwrap();
+        let mut restored = Vec::new();
+        rebuild_to(
+            &mut reader,
+            &mut restored,
+            &chunks,
+            |_| {},
+            &CancellationToken::new(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(restored, original);
+    }
+
+    #[tokio::test]
+    async fn test_restore_unicode_extra_fields() {
+        // "写真.txt" in Shift_JIS with Unicode Path extra fields, and a Unicode Comment extra field
+        let mut entry = create_mock_entry(b"\x8e\xca\x90^.txt", false, None);
+        entry.cdh.file_comment = b"\x83e\x83X\x83g".to_vec();
+        let unicode_path = unicode_path_field(0, &entry.cdh.filename, "写真.txt").unwrap();
+        let mut data = vec![1];
+        data.extend_from_slice(
+            &(crc_fast::checksum(
+                crc_fast::CrcAlgorithm::Crc32IsoHdlc,
+                &entry.cdh.file_comment,
+            ) as u32)
+                .to_le_bytes(),
+        );
+        data.extend_from_slice("テスト".as_bytes());
+        let unicode_comment = ExtraField {
+            tag: UnicodeCommentExtraField::TAG,
+            size: data.len() as u16,
+            data,
+        };
+        let zip = create_mock_zip(vec![entry]);
+
+        let headers = zip
+            .entries
+            .iter()
+            .enumerate()
+            .map(|(index, entry)| EntryHeaders {
+                index: index as u64,
+                entry,
+                lfh_filename: entry.lfh.filename.clone(),
+                lfh_utf8_flag: false,
+                filename: entry.cdh.filename.clone(),
+                comment: entry.cdh.file_comment.clone(),
+                utf8_flag: false,
+                lfh_unicode_extra_fields: vec![unicode_path.clone()],
+                unicode_extra_fields: vec![unicode_path.clone(), unicode_comment.clone()],
+            })
+            .collect();
+        let (chunks, _, _) =
+            write_archive(&zip, PrefixHandling::Keep, Vec::new(), headers).unwrap();
+        let mut original = Vec::new();
+        rebuild_to(
+            &mut MockReader { data: Vec::new() },
+            &mut original,
+            &chunks,
+            |_| {},
+            &CancellationToken::new(),
+        )
+        .await
+        .unwrap();
+        let mut reader = MockReader {
+            data: original.clone(),
+        };
+        let source = ZipFile::parse(&mut reader, false, |_, e| Err(e))
+            .await
+            .unwrap();
+        assert!(source.entries[0].lfh.unicode_path.is_some());
+        assert!(source.entries[0].cdh.unicode_comment.is_some());
+
+        let (chunks, _, report) = rebuild_with_report(
+            &source,
+            &InspectConfig::default(),
+            &RebuildConfig::default(),
+            &[],
+        )
+        .unwrap();
+        let entry = &report.entries[0];
+        assert_eq!(entry.new_name.as_deref(), Some("写真.txt"));
+        assert_eq!(
+            entry.original_unicode_extra_fields,
+            [
+                RecordedExtraField {
+                    tag: UnicodePathExtraField::TAG,
+                    data: unicode_path.data.clone(),
+                },
+                RecordedExtraField {
+                    tag: UnicodeCommentExtraField::TAG,
+                    data: unicode_comment.data,
+                },
+            ]
+        );
+        assert_eq!(
+            entry.original_lfh_unicode_extra_fields,
+            [RecordedExtraField {
+                tag: UnicodePathExtraField::TAG,
+                data: unicode_path.data,
+            }]
+        );
+
+        let mut rebuilt = Vec::new();
+        rebuild_to(
+            &mut reader,
+            &mut rebuilt,
+            &chunks,
+            |_| {},
+            &CancellationToken::new(),
+        )
+        .await
+        .unwrap();
+        let mut reader = MockReader { data: rebuilt };
+        let rebuilt_zip = ZipFile::parse(&mut reader, false, |_, e| Err(e))
+            .await
+            .unwrap();
+        assert!(rebuilt_zip.entries[0].cdh.unicode_path.is_none());
+        assert!(rebuilt_zip.entries[0].cdh.unicode_comment.is_none());
+
+        let (chunks, _) = restore(&rebuilt_zip, &report).unwrap();
+        let mut restored = Vec::new();
+        rebuild_to(
+            &mut reader,
+            &mut restored,
+            &chunks,
+            |_| {},
+            &CancellationToken::new(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(restored, original);
+    }
+
+    #[test]
+    fn test_rebuild_rename() {
+        let entries = vec![
+            create_mock_entry(b"top/", true, None),
+            create_mock_entry(b"top/a.txt", true, None),
+            create_mock_entry(b"top/b?.txt", true, None),
+            create_mock_entry(b"x/y.txt", true, None),
+        ];
+        let zip = create_mock_zip(entries);
+        let config = InspectConfig {
+            encoding: EncodingSelectionStrategy::EntryDetected {
+                fallback_encoding: None,
+                ignore_utf8_flag: false,
+                detection_hint: DetectionHint::default(),
+            },
+            ..Default::default()
+        };
+        let renamed = |index: u64, name: &str| RebuildConfig {
+            rename: RenameConfig {
+                overrides: vec![RenameOverride {
+                    index,
+                    name: name.to_string(),
+                }],
+                rules: vec![
+                    RenameRule::StripComponents { count: 1 },
+                    RenameRule::ReplaceCharacters {
+                        characters: WINDOWS_ILLEGAL_CHARACTERS.to_string(),
+                        replacement: "_".to_string(),
+                    },
+                ],
+            },
+            ..RebuildConfig::default()
+        };
+
+        let (_, _, report) = rebuild_with_report(&zip, &config, &renamed(3, "c.txt"), &[]).unwrap();
+        let new_names = report
+            .entries
+            .iter()
+            .map(|entry| entry.new_name.as_deref())
+            .collect::<Vec<_>>();
+        // The top-level folder is stripped down to nothing and left out
+        assert_eq!(
+            new_names,
+            [None, Some("a.txt"), Some("b_.txt"), Some("c.txt")]
+        );
+        assert_eq!(
+            report.warnings,
+            [RebuildWarning::RenamedToEmpty {
+                index: 0,
+                name: "top/".to_string(),
+            }]
+        );
+
+        let result = rebuild(&zip, &config, &renamed(3, "a.txt"), &[]);
+        let Err(RebuildError::DuplicateName { name, indices }) = result else {
+            panic!("Expected duplicate names to fail");
+        };
+        assert_eq!(name, "a.txt");
+        assert_eq!(indices, [1, 3]);
+
+        // Omitted entries do not take part in the uniqueness check
+        assert!(rebuild(&zip, &config, &renamed(3, "a.txt"), &[1]).is_ok());
+
+        assert!(matches!(
+            rebuild(&zip, &config, &renamed(4, "d.txt"), &[]),
+            Err(RebuildError::Inspect(
+                ZipInspectError::EntryIndexOutOfRange(4)
+            ))
+        ));
+
+        // Patterns are checked before anything is written
+        let invalid_pattern = RebuildConfig {
+            rename: RenameConfig {
+                overrides: Vec::new(),
+                rules: vec![RenameRule::Regex {
+                    pattern: "[".to_string(),
+                    replacement: String::new(),
+                }],
+            },
+            ..RebuildConfig::default()
+        };
+        assert!(matches!(
+            rebuild(&zip, &config, &invalid_pattern, &[]),
+            Err(RebuildError::Rename(_))
+        ));
+    }
 }
@@ -0,0 +1,312 @@
+//! Renaming of entries when rebuilding
+//!
+//! Names are renamed after decoding and before being written, either explicitly per entry or by rules
+//! applied to every other entry in order.
+
+use serde::{Deserialize, Serialize};
+
+/// Characters that are not allowed in filenames on Windows, including the control characters U+0000 to
+/// U+001F
+pub const WINDOWS_ILLEGAL_CHARACTERS: &str = "<>:\"\\|?*\
+    \u{00}\u{01}\u{02}\u{03}\u{04}\u{05}\u{06}\u{07}\u{08}\u{09}\u{0A}\u{0B}\u{0C}\u{0D}\u{0E}\u{0F}\
+    \u{10}\u{11}\u{12}\u{13}\u{14}\u{15}\u{16}\u{17}\u{18}\u{19}\u{1A}\u{1B}\u{1C}\u{1D}\u{1E}\u{1F}";
+
+/// Renaming applied to the decoded names of entries
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    derive(tsify::Tsify)
+)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+pub struct RenameConfig {
+    /// Names of specific entries, which are not subject to the rules
+    #[serde(default)]
+    pub overrides: Vec<RenameOverride>,
+    /// Rules applied in order to the names of the other entries
+    #[serde(default)]
+    pub rules: Vec<RenameRule>,
+}
+
+/// Name of a single entry
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    derive(tsify::Tsify)
+)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+pub struct RenameOverride {
+    pub index: u64,
+    pub name: String,
+}
+
+/// Rule rewriting the names of entries
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    derive(tsify::Tsify)
+)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+pub enum RenameRule {
+    /// Remove the first `count` path components, such as a top-level folder
+    ///
+    /// Like `tar --strip-components`, entries with no more than `count` components become empty and are
+    /// left out of the rebuilt archive.
+    StripComponents { count: u32 },
+    /// Replace every occurrence of `from` with `to`
+    Replace { from: String, to: String },
+    /// Replace each occurrence of any of `characters` with `replacement`
+    ReplaceCharacters {
+        characters: String,
+        replacement: String,
+    },
+    /// Replace every match of the regular expression `pattern` with `replacement`
+    ///
+    /// The replacement may refer to capture groups as `$1` or `${name}`. Needs the `regex` feature.
+    Regex {
+        pattern: String,
+        replacement: String,
+    },
+}
+
+/// Errors in rename rules, reported before anything is renamed
+#[derive(Debug, thiserror::Error)]
+pub enum RenameError {
+    #[error("Invalid pattern '{pattern}': {reason}")]
+    InvalidPattern { pattern: String, reason: String },
+    #[error("Pattern '{0}' cannot be used, as regular expressions are not supported by this build")]
+    RegexUnsupported(String),
+}
+
+/// Rule with its regular expression compiled
+enum CompiledRule<'a> {
+    Rule(&'a RenameRule),
+    #[cfg(feature = "regex")]
+    Regex {
+        regex: regex_lite::Regex,
+        replacement: &'a str,
+    },
+}
+
+/// Rename config ready to be applied, see [`RenameConfig::compile`]
+pub struct Renamer<'a> {
+    overrides: &'a [RenameOverride],
+    rules: Vec<CompiledRule<'a>>,
+}
+
+impl RenameRule {
+    fn compile(&self) -> Result<CompiledRule<'_>, RenameError> {
+        match self {
+            #[cfg(feature = "regex")]
+            RenameRule::Regex {
+                pattern,
+                replacement,
+            } => Ok(CompiledRule::Regex {
+                regex: regex_lite::Regex::new(pattern).map_err(|e| {
+                    RenameError::InvalidPattern {
+                        pattern: pattern.clone(),
+                        reason: e.to_string(),
+                    }
+                })?,
+                replacement,
+            }),
+            #[cfg(not(feature = "regex"))]
+            RenameRule::Regex { pattern, .. } => {
+                Err(RenameError::RegexUnsupported(pattern.clone()))
+            }
+            rule => Ok(CompiledRule::Rule(rule)),
+        }
+    }
+}
+
+impl CompiledRule<'_> {
+    fn apply(&self, name: &str) -> String {
+        match self {
+            CompiledRule::Rule(RenameRule::StripComponents { count }) => name
+                .splitn(*count as usize + 1, '/')
+                .nth(*count as usize)
+                .unwrap_or_default()
+                .to_string(),
+            CompiledRule::Rule(RenameRule::Replace { from, .. }) if from.is_empty() => {
+                name.to_string()
+            }
+            CompiledRule::Rule(RenameRule::Replace { from, to }) => name.replace(from.as_str(), to),
+            CompiledRule::Rule(RenameRule::ReplaceCharacters {
+                characters,
+                replacement,
+            }) => name
+                .chars()
+                .fold(String::with_capacity(name.len()), |mut renamed, c| {
+                    if characters.contains(c) {
+                        renamed.push_str(replacement);
+                    } else {
+                        renamed.push(c);
+                    }
+                    renamed
+                }),
+            // Compiled into `CompiledRule::Regex`, or rejected without the feature
+            CompiledRule::Rule(RenameRule::Regex { .. }) => name.to_string(),
+            #[cfg(feature = "regex")]
+            CompiledRule::Regex { regex, replacement } => {
+                regex.replace_all(name, *replacement).into_owned()
+            }
+        }
+    }
+}
+
+impl RenameConfig {
+    /// Returns whether the config renames nothing
+    pub fn is_empty(&self) -> bool {
+        self.overrides.is_empty() && self.rules.is_empty()
+    }
+
+    /// Compiles the rules, failing on invalid regular expressions
+    pub fn compile(&self) -> Result<Renamer<'_>, RenameError> {
+        Ok(Renamer {
+            overrides: &self.overrides,
+            rules: self
+                .rules
+                .iter()
+                .map(RenameRule::compile)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+impl Renamer<'_> {
+    /// Returns the new name of an entry, or None to keep its name
+    ///
+    /// Entries with no decoded name can only be renamed by overrides. An empty name means the entry should
+    /// be left out. The last override for an index wins.
+    pub fn apply(&self, index: u64, name: Option<&str>) -> Option<String> {
+        if let Some(entry_override) = self.overrides.iter().rev().find(|o| o.index == index) {
+            return Some(entry_override.name.clone());
+        }
+
+        let name = name?;
+        if self.rules.is_empty() {
+            return None;
+        }
+        Some(
+            self.rules
+                .iter()
+                .fold(name.to_string(), |name, rule| rule.apply(&name)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply_rule(rule: RenameRule, name: &str) -> String {
+        RenameConfig {
+            overrides: Vec::new(),
+            rules: vec![rule],
+        }
+        .compile()
+        .unwrap()
+        .apply(0, Some(name))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_rules() {
+        let strip = || RenameRule::StripComponents { count: 1 };
+        assert_eq!(apply_rule(strip(), "top/sub/a.txt"), "sub/a.txt");
+        assert_eq!(apply_rule(strip(), "top/sub/"), "sub/");
+        assert_eq!(apply_rule(strip(), "top/"), "");
+        assert_eq!(apply_rule(strip(), "a.txt"), "");
+
+        let replace = RenameRule::Replace {
+            from: "old".to_string(),
+            to: "new".to_string(),
+        };
+        assert_eq!(apply_rule(replace, "old/file.old"), "new/file.new");
+
+        let windows = || RenameRule::ReplaceCharacters {
+            characters: WINDOWS_ILLEGAL_CHARACTERS.to_string(),
+            replacement: "_".to_string(),
+        };
+        assert_eq!(
+            apply_rule(windows(), "a/what?: \"x\".txt"),
+            "a/what__ _x_.txt"
+        );
+        assert_eq!(
+            apply_rule(windows(), "tab\there\u{1F}.txt"),
+            "tab_here_.txt"
+        );
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_regex_rule() {
+        let regex = RenameRule::Regex {
+            pattern: r"^IMG_(\d+)\.JPG$".to_string(),
+            replacement: "photo-$1.jpg".to_string(),
+        };
+        assert_eq!(apply_rule(regex.clone(), "IMG_0042.JPG"), "photo-0042.jpg");
+        assert_eq!(apply_rule(regex, "notes.txt"), "notes.txt");
+
+        let config = RenameConfig {
+            overrides: Vec::new(),
+            rules: vec![RenameRule::Regex {
+                pattern: "(unclosed".to_string(),
+                replacement: String::new(),
+            }],
+        };
+        assert!(matches!(
+            config.compile(),
+            Err(RenameError::InvalidPattern { pattern, .. }) if pattern == "(unclosed"
+        ));
+    }
+
+    #[test]
+    fn test_apply() {
+        let config = RenameConfig {
+            overrides: vec![
+                RenameOverride {
+                    index: 1,
+                    name: "first.txt".to_string(),
+                },
+                RenameOverride {
+                    index: 1,
+                    name: "fixed.txt".to_string(),
+                },
+            ],
+            rules: vec![
+                RenameRule::StripComponents { count: 1 },
+                RenameRule::Replace {
+                    from: "a".to_string(),
+                    to: "b".to_string(),
+                },
+            ],
+        };
+        let renamer = config.compile().unwrap();
+
+        assert_eq!(
+            renamer.apply(0, Some("top/a.txt")),
+            Some("b.txt".to_string())
+        );
+        // Overrides are not subject to the rules and also apply to undecoded names
+        assert_eq!(
+            renamer.apply(1, Some("top/a.txt")),
+            Some("fixed.txt".to_string())
+        );
+        assert_eq!(renamer.apply(1, None), Some("fixed.txt".to_string()));
+        assert_eq!(renamer.apply(2, None), None);
+
+        let empty = RenameConfig::default();
+        assert!(empty.is_empty());
+        assert_eq!(empty.compile().unwrap().apply(0, Some("a.txt")), None);
+    }
+}
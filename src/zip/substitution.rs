@@ -0,0 +1,185 @@
+//! Character substitution tables applied to decoded strings
+//!
+//! Shift_JIS is decoded with the CP932 (Windows) mapping, which differs from JIS X 0208 for a handful of
+//! characters. The same bytes therefore show up as different characters depending on the platform that
+//! produced or displays the name, and these tables allow unifying them.
+
+use serde::{Deserialize, Serialize};
+
+/// Mapping of a single character to another
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    derive(tsify::Tsify)
+)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+pub struct CharacterMapping {
+    pub from: char,
+    pub to: char,
+}
+
+/// Named set of character mappings applied to strings decoded with specific encodings
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    derive(tsify::Tsify)
+)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+pub struct SubstitutionTable {
+    /// Name of the table, used in hit reports
+    pub name: String,
+    /// Labels of the encodings this table applies to, empty to apply to strings decoded with any encoding
+    pub source_encodings: Vec<String>,
+    /// The mappings, the first one matching a character wins
+    pub mappings: Vec<CharacterMapping>,
+}
+
+/// Built-in substitution tables
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    derive(tsify::Tsify)
+)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+pub enum SubstitutionPreset {
+    /// Replace CP932 specific characters decoded from Shift_JIS with their JIS X 0208 counterparts
+    Cp932ToJis,
+    /// Replace JIS X 0208 characters with their CP932 counterparts in names decoded from EUC-JP,
+    /// ISO-2022-JP or UTF-8
+    ///
+    /// UTF-8 is included on purpose, as names converted with JIS X 0208 mappings on Unix (e.g. by iconv)
+    /// carry these code points, so the preset is meant for Japanese archives only. Names in other encodings
+    /// are left alone, as the same characters are ordinary Latin-1 signs in them.
+    JisToCp932,
+}
+
+/// Characters that CP932 and JIS X 0208 map differently, as (CP932, JIS X 0208)
+const CP932_JIS_DIFFERENCES: [(char, char); 6] = [
+    // Wave Dash
+    ('\u{FF5E}', '\u{301C}'),
+    // Minus Sign
+    ('\u{FF0D}', '\u{2212}'),
+    // Double Vertical Line
+    ('\u{2225}', '\u{2016}'),
+    // Cent Sign
+    ('\u{FFE0}', '\u{00A2}'),
+    // Pound Sign
+    ('\u{FFE1}', '\u{00A3}'),
+    // Not Sign
+    ('\u{FFE2}', '\u{00AC}'),
+];
+
+impl SubstitutionPreset {
+    /// All built-in presets
+    pub const ALL: [SubstitutionPreset; 2] = [
+        SubstitutionPreset::Cp932ToJis,
+        SubstitutionPreset::JisToCp932,
+    ];
+
+    /// Returns the table for this preset
+    pub fn table(self) -> SubstitutionTable {
+        match self {
+            SubstitutionPreset::Cp932ToJis => SubstitutionTable {
+                name: "CP932 to JIS X 0208".to_string(),
+                source_encodings: vec!["Shift_JIS".to_string()],
+                mappings: CP932_JIS_DIFFERENCES
+                    .iter()
+                    .map(|&(from, to)| CharacterMapping { from, to })
+                    .collect(),
+            },
+            SubstitutionPreset::JisToCp932 => SubstitutionTable {
+                name: "JIS X 0208 to CP932".to_string(),
+                source_encodings: vec![
+                    "EUC-JP".to_string(),
+                    "ISO-2022-JP".to_string(),
+                    "UTF-8".to_string(),
+                ],
+                mappings: CP932_JIS_DIFFERENCES
+                    .iter()
+                    .map(|&(to, from)| CharacterMapping { from, to })
+                    .collect(),
+            },
+        }
+    }
+}
+
+impl SubstitutionTable {
+    /// Applies the mappings to the string
+    ///
+    /// All mappings are applied at once, so a table may swap two characters.
+    /// Returns the substituted string and the indices of the mappings that matched at least once.
+    pub fn apply(&self, string: &str) -> (String, Vec<usize>) {
+        let mut matched = vec![false; self.mappings.len()];
+        let substituted = string
+            .chars()
+            .map(|c| match self.mappings.iter().position(|m| m.from == c) {
+                Some(index) => {
+                    matched[index] = true;
+                    self.mappings[index].to
+                }
+                None => c,
+            })
+            .collect();
+
+        let hits = matched
+            .iter()
+            .enumerate()
+            .filter_map(|(index, &m)| m.then_some(index))
+            .collect();
+        (substituted, hits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply() {
+        let table = SubstitutionTable {
+            name: "swap".to_string(),
+            source_encodings: Vec::new(),
+            mappings: vec![
+                CharacterMapping {
+                    from: '\u{301C}',
+                    to: '\u{FF5E}',
+                },
+                CharacterMapping {
+                    from: '\u{FF5E}',
+                    to: '\u{301C}',
+                },
+                CharacterMapping { from: 'x', to: 'y' },
+            ],
+        };
+
+        let (string, hits) = table.apply("a\u{301C}b\u{FF5E}\u{301C}");
+        assert_eq!(string, "a\u{FF5E}b\u{301C}\u{FF5E}");
+        assert_eq!(hits, [0, 1]);
+
+        let (string, hits) = table.apply("abc");
+        assert_eq!(string, "abc");
+        assert!(hits.is_empty());
+    }
+
+    #[test]
+    fn test_presets() {
+        let cp932_to_jis = SubstitutionPreset::Cp932ToJis.table();
+        let (string, hits) = cp932_to_jis.apply("1\u{FF5E}2 \u{FF0D}3 \u{FFE1}");
+        assert_eq!(string, "1\u{301C}2 \u{2212}3 \u{00A3}");
+        assert_eq!(hits, [0, 1, 4]);
+
+        // The presets are inverse to each other
+        let jis_to_cp932 = SubstitutionPreset::JisToCp932.table();
+        let (string, _) = jis_to_cp932.apply(&string);
+        assert_eq!(string, "1\u{FF5E}2 \u{FF0D}3 \u{FFE1}");
+    }
+}
@@ -0,0 +1,446 @@
+use serde::{Deserialize, Serialize};
+
+use super::parse::{
+    ExtendedTimestampExtraField, ExtraField, InfoZipUnixExtraField, NtfsExtraField, ZipFileEntry,
+    ZipParseError,
+};
+
+/// Seconds between 1601-01-01 (FILETIME epoch) and 1970-01-01 (Unix epoch)
+const FILETIME_UNIX_EPOCH_OFFSET: i64 = 11_644_473_600;
+
+/// Source of a resolved timestamp, in order of preference
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    derive(tsify::Tsify)
+)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+pub enum TimestampSource {
+    /// NTFS Extra Field (0x000a), 100-nanosecond precision
+    Ntfs,
+    /// Extended Timestamp Extra Field (0x5455)
+    ExtendedTimestamp,
+    /// Info-ZIP Unix Extra Field (0x5855)
+    InfoZipUnix,
+    /// DOS date and time fields of the CDH
+    ///
+    /// These are in an unknown local time zone, so the resolved time is the local time as if it were UTC
+    Dos,
+}
+
+/// Timestamp resolved from an entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    derive(tsify::Tsify)
+)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+pub struct Timestamp {
+    /// Seconds since the Unix epoch (UTC)
+    pub unix_time: i64,
+    /// Sub-second part in nanoseconds
+    pub nanoseconds: u32,
+    /// Where the timestamp was taken from
+    pub source: TimestampSource,
+}
+
+impl Timestamp {
+    /// Convert a Unix time in seconds
+    pub fn from_unix_time(unix_time: i64, source: TimestampSource) -> Self {
+        Timestamp {
+            unix_time,
+            nanoseconds: 0,
+            source,
+        }
+    }
+
+    /// Convert a Windows FILETIME, returning None for the unset value 0
+    pub fn from_filetime(filetime: u64) -> Option<Self> {
+        if filetime == 0 {
+            return None;
+        }
+
+        Some(Timestamp {
+            unix_time: (filetime / 10_000_000) as i64 - FILETIME_UNIX_EPOCH_OFFSET,
+            nanoseconds: (filetime % 10_000_000) as u32 * 100,
+            source: TimestampSource::Ntfs,
+        })
+    }
+
+    /// Convert DOS date and time fields, returning None if they do not form a valid date
+    pub fn from_dos(date: u16, time: u16) -> Option<Self> {
+        let year = 1980 + (date >> 9) as i64;
+        let month = ((date >> 5) & 0x0F) as u32;
+        let day = (date & 0x1F) as u32;
+        let hour = (time >> 11) as i64;
+        let minute = ((time >> 5) & 0x3F) as i64;
+        let second = ((time & 0x1F) * 2) as i64;
+        if !(1..=12).contains(&month) || day == 0 || hour >= 24 || minute >= 60 || second >= 60 {
+            return None;
+        }
+
+        let days = days_from_civil(year, month, day);
+        Some(Timestamp::from_unix_time(
+            days * 86400 + hour * 3600 + minute * 60 + second,
+            TimestampSource::Dos,
+        ))
+    }
+
+    /// Format as ISO 8601, e.g. `2024-01-02T03:04:05Z`
+    ///
+    /// DOS timestamps are formatted without the `Z` suffix since their time zone is unknown.
+    pub fn to_iso8601(&self) -> String {
+        let days = self.unix_time.div_euclid(86400);
+        let seconds = self.unix_time.rem_euclid(86400);
+        let (year, month, day) = civil_from_days(days);
+        let fraction = if self.nanoseconds != 0 {
+            format!(".{:09}", self.nanoseconds)
+                .trim_end_matches('0')
+                .to_string()
+        } else {
+            String::new()
+        };
+        let zone = if self.source == TimestampSource::Dos {
+            ""
+        } else {
+            "Z"
+        };
+
+        format!(
+            "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}{fraction}{zone}",
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60,
+        )
+    }
+}
+
+/// Modification, access and creation times of an entry
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    derive(tsify::Tsify)
+)]
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    tsify(into_wasm_abi, from_wasm_abi)
+)]
+pub struct EntryTimestamps {
+    /// Last modification time
+    pub modification_time: Option<Timestamp>,
+    /// Last access time
+    pub access_time: Option<Timestamp>,
+    /// Creation time
+    pub creation_time: Option<Timestamp>,
+}
+
+impl EntryTimestamps {
+    /// Resolve the times of an entry from its extra fields, falling back to the DOS fields
+    ///
+    /// Each time is taken from the most precise source that has it. For each source, the CDH is
+    /// preferred over the LFH, except that the CDH Extended Timestamp only carries the modification
+    /// time. Malformed extra fields are ignored.
+    pub fn resolve(entry: &ZipFileEntry) -> Self {
+        let ntfs = find_extra_field(entry, NtfsExtraField::TAG, NtfsExtraField::parse);
+        let extended = [&entry.cdh.extra_fields, &entry.lfh.extra_fields].map(|fields| {
+            parse_extra_field(
+                fields,
+                ExtendedTimestampExtraField::TAG,
+                ExtendedTimestampExtraField::parse,
+            )
+        });
+        let unix = find_extra_field(
+            entry,
+            InfoZipUnixExtraField::TAG,
+            InfoZipUnixExtraField::parse,
+        );
+
+        let from_extended = |get: fn(&ExtendedTimestampExtraField) -> Option<i32>| {
+            extended.iter().flatten().find_map(get).map(|time| {
+                Timestamp::from_unix_time(time as i64, TimestampSource::ExtendedTimestamp)
+            })
+        };
+        let from_unix =
+            |time: i32| Timestamp::from_unix_time(time as i64, TimestampSource::InfoZipUnix);
+
+        EntryTimestamps {
+            modification_time: ntfs
+                .and_then(|f| Timestamp::from_filetime(f.modification_time))
+                .or_else(|| from_extended(|f| f.modification_time))
+                .or_else(|| unix.map(|f| from_unix(f.modification_time)))
+                .or_else(|| Timestamp::from_dos(entry.cdh.last_mod_date, entry.cdh.last_mod_time)),
+            access_time: ntfs
+                .and_then(|f| Timestamp::from_filetime(f.access_time))
+                .or_else(|| from_extended(|f| f.access_time))
+                .or_else(|| unix.map(|f| from_unix(f.access_time))),
+            creation_time: ntfs
+                .and_then(|f| Timestamp::from_filetime(f.creation_time))
+                .or_else(|| from_extended(|f| f.creation_time)),
+        }
+    }
+}
+
+/// Parse the first extra field with the given tag, ignoring malformed ones
+fn parse_extra_field<T>(
+    fields: &[ExtraField],
+    tag: u16,
+    parse: fn(&ExtraField) -> Result<T, ZipParseError>,
+) -> Option<T> {
+    fields
+        .iter()
+        .find(|ef| ef.tag == tag)
+        .and_then(|ef| parse(ef).ok())
+}
+
+/// Parse an extra field from the CDH, falling back to the LFH
+fn find_extra_field<T>(
+    entry: &ZipFileEntry,
+    tag: u16,
+    parse: fn(&ExtraField) -> Result<T, ZipParseError>,
+) -> Option<T> {
+    parse_extra_field(&entry.cdh.extra_fields, tag, parse)
+        .or_else(|| parse_extra_field(&entry.lfh.extra_fields, tag, parse))
+}
+
+/// Number of days since 1970-01-01 for a proleptic Gregorian date
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month_index = (month as i64 + 9) % 12;
+    let day_of_year = (153 * month_index + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Proleptic Gregorian date for a number of days since 1970-01-01
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zip::parse::{CentralDirectoryHeader, GeneralPurposeBitFlag, LocalFileHeader};
+
+    fn create_mock_entry(
+        last_mod_date: u16,
+        last_mod_time: u16,
+        cdh_extra_fields: Vec<ExtraField>,
+        lfh_extra_fields: Vec<ExtraField>,
+    ) -> ZipFileEntry {
+        let cdh = CentralDirectoryHeader {
+            signature: 0x02014b50,
+            version_made_by: 0,
+            version_needed: 0,
+            flags: GeneralPurposeBitFlag(0),
+            compression_method: 0,
+            last_mod_time,
+            last_mod_date,
+            crc32: 0,
+            compressed_size: 0,
+            uncompressed_size: 0,
+            filename_length: 0,
+            extra_field_length: 0,
+            file_comment_length: 0,
+            disk_number_start: 0,
+            internal_file_attributes: 0,
+            external_file_attributes: 0,
+            local_header_offset: 0,
+            filename: vec![],
+            extra_fields: cdh_extra_fields,
+            file_comment: vec![],
+            zip64: None,
+            unicode_path: None,
+            unicode_comment: None,
+        };
+        let lfh = LocalFileHeader {
+            signature: 0x04034b50,
+            version_needed: 0,
+            flags: GeneralPurposeBitFlag(0),
+            compression_method: 0,
+            last_mod_time,
+            last_mod_date,
+            crc32: 0,
+            compressed_size: 0,
+            uncompressed_size: 0,
+            filename_length: 0,
+            extra_field_length: 0,
+            filename: vec![],
+            extra_fields: lfh_extra_fields,
+            zip64: None,
+            unicode_path: None,
+        };
+
+        ZipFileEntry {
+            cdh,
+            lfh,
+            descriptor: None,
+            file_offset: 0,
+            file_size: 0,
+        }
+    }
+
+    fn create_extra_field(tag: u16, data: Vec<u8>) -> ExtraField {
+        ExtraField {
+            tag,
+            size: data.len() as u16,
+            data,
+        }
+    }
+
+    fn create_extended_timestamp(flags: u8, times: &[i32]) -> ExtraField {
+        let mut data = vec![flags];
+        for time in times {
+            data.extend_from_slice(&time.to_le_bytes());
+        }
+        create_extra_field(ExtendedTimestampExtraField::TAG, data)
+    }
+
+    // 2024-01-02 03:04:06 as DOS date and time
+    const DOS_DATE: u16 = ((2024 - 1980) << 9) | (1 << 5) | 2;
+    const DOS_TIME: u16 = (3 << 11) | (4 << 5) | 3;
+
+    #[test]
+    fn test_from_dos() {
+        let timestamp = Timestamp::from_dos(DOS_DATE, DOS_TIME).unwrap();
+        assert_eq!(timestamp.unix_time, 1704164646);
+        assert_eq!(timestamp.source, TimestampSource::Dos);
+        assert_eq!(timestamp.to_iso8601(), "2024-01-02T03:04:06");
+
+        // Zeroed fields do not form a valid date
+        assert_eq!(Timestamp::from_dos(0, 0), None);
+    }
+
+    #[test]
+    fn test_from_filetime() {
+        assert_eq!(Timestamp::from_filetime(0), None);
+
+        let timestamp = Timestamp::from_filetime(133_485_038_461_234_567).unwrap();
+        assert_eq!(timestamp.unix_time, 1704030246);
+        assert_eq!(timestamp.nanoseconds, 123_456_700);
+        assert_eq!(timestamp.to_iso8601(), "2023-12-31T13:44:06.1234567Z");
+    }
+
+    #[test]
+    fn test_to_iso8601_before_epoch() {
+        let timestamp = Timestamp::from_unix_time(-1, TimestampSource::ExtendedTimestamp);
+        assert_eq!(timestamp.to_iso8601(), "1969-12-31T23:59:59Z");
+    }
+
+    #[test]
+    fn test_resolve_dos_only() {
+        let entry = create_mock_entry(DOS_DATE, DOS_TIME, vec![], vec![]);
+        let timestamps = EntryTimestamps::resolve(&entry);
+        assert_eq!(
+            timestamps.modification_time.map(|t| t.source),
+            Some(TimestampSource::Dos)
+        );
+        assert_eq!(timestamps.access_time, None);
+        assert_eq!(timestamps.creation_time, None);
+    }
+
+    #[test]
+    fn test_resolve_extended_timestamp() {
+        // The CDH only carries the modification time, the LFH carries all three
+        let entry = create_mock_entry(
+            DOS_DATE,
+            DOS_TIME,
+            vec![create_extended_timestamp(0x07, &[1000])],
+            vec![create_extended_timestamp(0x07, &[2000, 3000, 4000])],
+        );
+        let timestamps = EntryTimestamps::resolve(&entry);
+        let times = [
+            timestamps.modification_time,
+            timestamps.access_time,
+            timestamps.creation_time,
+        ]
+        .map(|t| t.map(|t| (t.unix_time, t.source)));
+        assert_eq!(
+            times,
+            [
+                Some((1000, TimestampSource::ExtendedTimestamp)),
+                Some((3000, TimestampSource::ExtendedTimestamp)),
+                Some((4000, TimestampSource::ExtendedTimestamp)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_resolve_prefers_ntfs() {
+        let mut ntfs = vec![0u8; 4]; // Reserved
+        ntfs.extend_from_slice(&1u16.to_le_bytes()); // Attribute tag
+        ntfs.extend_from_slice(&24u16.to_le_bytes()); // Attribute size
+        ntfs.extend_from_slice(&133_485_038_460_000_000u64.to_le_bytes()); // Modification time
+        ntfs.extend_from_slice(&0u64.to_le_bytes()); // Access time (unset)
+        ntfs.extend_from_slice(&116_444_736_000_000_000u64.to_le_bytes()); // Creation time
+
+        let mut unix = Vec::new();
+        unix.extend_from_slice(&5000i32.to_le_bytes()); // Access time
+        unix.extend_from_slice(&6000i32.to_le_bytes()); // Modification time
+
+        let entry = create_mock_entry(
+            DOS_DATE,
+            DOS_TIME,
+            vec![
+                create_extended_timestamp(0x01, &[1000]),
+                create_extra_field(NtfsExtraField::TAG, ntfs),
+                create_extra_field(InfoZipUnixExtraField::TAG, unix),
+            ],
+            vec![],
+        );
+        let timestamps = EntryTimestamps::resolve(&entry);
+        let times = [
+            timestamps.modification_time,
+            timestamps.access_time,
+            timestamps.creation_time,
+        ]
+        .map(|t| t.map(|t| (t.unix_time, t.source)));
+        assert_eq!(
+            times,
+            [
+                Some((1704030246, TimestampSource::Ntfs)),
+                Some((5000, TimestampSource::InfoZipUnix)),
+                Some((0, TimestampSource::Ntfs)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_resolve_ignores_malformed_fields() {
+        let entry = create_mock_entry(
+            DOS_DATE,
+            DOS_TIME,
+            vec![
+                create_extra_field(ExtendedTimestampExtraField::TAG, vec![]),
+                create_extra_field(NtfsExtraField::TAG, vec![0; 4]),
+            ],
+            vec![],
+        );
+        let timestamps = EntryTimestamps::resolve(&entry);
+        assert_eq!(
+            timestamps.modification_time.map(|t| t.source),
+            Some(TimestampSource::Dos)
+        );
+    }
+}